@@ -0,0 +1,138 @@
+//! Tab completion for the (deet) prompt.
+
+use crate::debugger_command::{find_command, COMMANDS, INFO_TOPICS};
+use nix::sys::signal::Signal;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::path::Path;
+
+pub struct DebuggerHelper {
+    functions: Vec<String>,
+    files: Vec<String>,
+    /// Variables in scope where the inferior is stopped; updated by the debugger on each stop.
+    variables: Vec<String>,
+    filename_completer: FilenameCompleter,
+}
+
+impl DebuggerHelper {
+    /// functions and files come from the target's debugging information.
+    pub fn new(functions: Vec<String>, files: Vec<String>) -> DebuggerHelper {
+        DebuggerHelper {
+            functions,
+            files,
+            variables: Vec::new(),
+            filename_completer: FilenameCompleter::new(),
+        }
+    }
+
+    pub fn set_variables(&mut self, variables: Vec<String>) {
+        self.variables = variables;
+    }
+
+    /// Candidates for a breakpoint location: function names, and "file.c:" for line numbers.
+    fn locations(&self) -> Vec<String> {
+        let files = self.files.iter().map(|file| {
+            let name = Path::new(file)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| file.clone());
+            format!("{}:", name)
+        });
+        self.functions.iter().cloned().chain(files).collect()
+    }
+}
+
+impl Completer for DebuggerHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+        let candidates: Vec<String> = match previous.first() {
+            None => COMMANDS
+                .iter()
+                .flat_map(|cmd| std::iter::once(&cmd.name).chain(cmd.aliases.iter()))
+                .map(|name| name.to_string())
+                .collect(),
+            // Strip any /format suffix, e.g. x/4i
+            Some(command) => match find_command(command.split('/').next().unwrap_or(command))
+                .map(|cmd| cmd.name)
+            {
+                Some("run") | Some("source") | Some("tty") => {
+                    return self.filename_completer.complete(line, pos, ctx)
+                }
+                Some("set") if previous.get(1) == Some(&"args") => {
+                    return self.filename_completer.complete(line, pos, ctx)
+                }
+                Some("set") if previous.len() == 1 => {
+                    vec!["args".to_string(), "environment".to_string()]
+                }
+                Some("unset") if previous.len() == 1 => vec!["environment".to_string()],
+                Some("record") if previous.len() == 1 => vec!["stop".to_string()],
+                Some("delete") if previous.len() == 1 => {
+                    vec!["catchpoints".to_string(), "checkpoint".to_string()]
+                }
+                Some("catch") if previous.len() == 1 => vec!["syscall".to_string()],
+                Some("target") if previous.len() == 1 => vec!["remote".to_string()],
+                Some("save") if previous.len() > 1 => {
+                    return self.filename_completer.complete(line, pos, ctx)
+                }
+                Some("save") => vec!["breakpoints".to_string()],
+                Some("break") | Some("tbreak") | Some("until") | Some("advance")
+                | Some("disassemble") => self.locations(),
+                Some("trace") if previous.len() == 1 => self.locations(),
+                Some("trace") if previous.len() == 2 => {
+                    return self.filename_completer.complete(line, pos, ctx)
+                }
+                Some("trace") => self.variables.clone(),
+                Some("print") | Some("display") => self.variables.clone(),
+                Some("x/i") | Some("display/i") => {
+                    let mut candidates = self.functions.clone();
+                    candidates.push("$pc".to_string());
+                    candidates
+                }
+                Some("info") if previous.len() == 1 => {
+                    INFO_TOPICS.iter().map(|topic| topic.to_string()).collect()
+                }
+                Some("handle") if previous.len() == 1 => Signal::iterator()
+                    .map(|signal| signal.as_str().to_string())
+                    .collect(),
+                Some("help") => COMMANDS.iter().map(|cmd| cmd.name.to_string()).collect(),
+                _ => Vec::new(),
+            },
+        };
+
+        let mut matches: Vec<Pair> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        matches.sort_by(|a, b| a.display.cmp(&b.display));
+        matches.dedup_by(|a, b| a.display == b.display);
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for DebuggerHelper {}
+
+impl Highlighter for DebuggerHelper {}
+
+impl Validator for DebuggerHelper {}
+
+impl Helper for DebuggerHelper {}
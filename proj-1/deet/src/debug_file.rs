@@ -0,0 +1,98 @@
+//! Locates separate debug info for stripped binaries, the same way gdb does: first by build ID
+//! under <debug-dir>/.build-id/, then by the file name and CRC stored in .gnu_debuglink.
+
+use object::Object;
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_DEBUG_DIR: &str = "/usr/lib/debug";
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Returns the path of a debug file matching `object` (the binary at `path`), if one exists.
+pub fn find_debug_file(path: &str, object: &object::File, debug_dir: &str) -> Option<PathBuf> {
+    if let Some(build_id) = build_id(object).filter(|id| id.len() > 1) {
+        let candidate = build_id_path(debug_dir, &build_id);
+        if file_build_id(&candidate).as_ref() == Some(&build_id) {
+            return Some(candidate);
+        }
+    }
+
+    let (name, crc) = gnu_debuglink(object)?;
+    let exe_dir = fs::canonicalize(path).ok()?.parent()?.to_path_buf();
+    let candidates = vec![
+        exe_dir.join(&name),
+        exe_dir.join(".debug").join(&name),
+        Path::new(debug_dir)
+            .join(exe_dir.strip_prefix("/").unwrap_or(&exe_dir))
+            .join(&name),
+    ];
+    candidates
+        .into_iter()
+        .find(|candidate| file_crc(candidate) == Some(crc))
+}
+
+/// <debug-dir>/.build-id/ab/cdef0123....debug
+fn build_id_path(debug_dir: &str, build_id: &[u8]) -> PathBuf {
+    let hex: String = build_id.iter().map(|b| format!("{:02x}", b)).collect();
+    Path::new(debug_dir)
+        .join(".build-id")
+        .join(&hex[..2])
+        .join(format!("{}.debug", &hex[2..]))
+}
+
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    if little_endian {
+        Some(u32::from_le_bytes(bytes))
+    } else {
+        Some(u32::from_be_bytes(bytes))
+    }
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Reads the NT_GNU_BUILD_ID note out of .note.gnu.build-id.
+fn build_id(object: &object::File) -> Option<Vec<u8>> {
+    let data = object.section_data_by_name(".note.gnu.build-id")?;
+    let little_endian = object.is_little_endian();
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        let name_size = read_u32(&data, offset, little_endian)? as usize;
+        let desc_size = read_u32(&data, offset + 4, little_endian)? as usize;
+        let note_type = read_u32(&data, offset + 8, little_endian)?;
+        let name_start = offset + 12;
+        let desc_start = name_start + align4(name_size);
+        let name = data.get(name_start..name_start + name_size)?;
+        if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(data.get(desc_start..desc_start + desc_size)?.to_vec());
+        }
+        offset = desc_start + align4(desc_size);
+    }
+    None
+}
+
+/// .gnu_debuglink holds a NUL-terminated file name, padded to 4 bytes, followed by the CRC32 of
+/// the debug file.
+fn gnu_debuglink(object: &object::File) -> Option<(String, u32)> {
+    let data = object.section_data_by_name(".gnu_debuglink")?;
+    let name_len = data.iter().position(|&b| b == 0)?;
+    let name = String::from_utf8(data[..name_len].to_vec()).ok()?;
+    let crc = read_u32(&data, align4(name_len + 1), object.is_little_endian())?;
+    Some((name, crc))
+}
+
+fn file_build_id(path: &Path) -> Option<Vec<u8>> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    build_id(&object::File::parse(&*mmap).ok()?)
+}
+
+fn file_crc(path: &Path) -> Option<u32> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    Some(crc32fast::hash(&mmap))
+}
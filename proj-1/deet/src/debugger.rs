@@ -1,25 +1,130 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::net::TcpListener;
 use std::ops::Index;
+use std::path::Path;
+use std::str::FromStr;
 
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::Inferior;
+use crate::completer::DebuggerHelper;
+use crate::debugger_command::{find_command, DebuggerCommand, COMMANDS};
+use crate::disassembler::{self, DecodedInstruction};
+use crate::dwarf_data::{DwarfData, Error as DwarfError, Location, Type, TypeKind, Variable};
+use crate::expr::{self, Expr};
+use crate::gdbserver;
+use crate::inferior::{CallOutcome, Frame, Inferior, RunConfig, Status, ARGUMENT_REGISTERS};
+use crate::interrupt;
+use crate::record::Recorder;
+use crate::solib::{self, Library};
+use crate::syscall;
+use crate::tui::{Tui, View};
+use crate::value;
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitPidFlag;
+use regex::Regex;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{CompletionType, Config, Editor};
+
+/// Command file run at startup, like .gdbinit: the one in the home directory, and the one in the
+/// current directory if that is trusted (see trust_local_init).
+const INIT_FILE: &str = ".deetinit";
+/// How deeply source may nest, so that a file sourcing itself doesn't recurse forever
+const MAX_SOURCE_DEPTH: usize = 10;
+
+/// A breakpoint as the user specified it, so that it can be saved and re-resolved later.
+struct UserBreakpoint {
+    spec: String,
+    /// None while pending: the spec names a function that is neither in the executable nor in
+    /// any shared library loaded so far
+    addr: Option<usize>,
+    /// The shared library the breakpoint was resolved in, which may be loaded elsewhere on the
+    /// next run
+    library: Option<String>,
+    /// Set by tbreak: deleted the first time it is hit
+    temporary: bool,
+    /// Commands to run when the breakpoint is hit (see the commands command)
+    commands: Vec<String>,
+    /// Set by trace: log to a file when hit rather than stopping
+    trace: Option<Tracepoint>,
+}
+
+/// What a tracepoint logs, and where to.
+struct Tracepoint {
+    path: String,
+    variables: Vec<String>,
+}
+
+/// An expression printed every time the inferior stops (see the display command).
+struct Display {
+    expr: String,
+    /// Set by display/i: disassemble the instruction at expr instead of printing a variable
+    instructions: bool,
+}
+
+/// What to do when the inferior receives a signal (see the handle command).
+#[derive(Clone, Copy, PartialEq)]
+struct SignalPolicy {
+    stop: bool,
+    print: bool,
+    pass: bool,
+}
+
+impl Default for SignalPolicy {
+    fn default() -> Self {
+        SignalPolicy {
+            stop: true,
+            print: true,
+            pass: true,
+        }
+    }
+}
 
 pub struct Debugger {
     target: String,
     history_path: String,
-    readline: Editor<()>,
+    readline: Editor<DebuggerHelper>,
     inferior: Option<Inferior>,
+    run_config: RunConfig,
     dwarf_data: DwarfData,
-    break_points: Vec<usize>,
+    // User breakpoints by number, which stays the same when other breakpoints are deleted
+    break_points: BTreeMap<usize, UserBreakpoint>,
+    // Commands of the breakpoint the inferior last stopped at, still to be run
+    pending_commands: VecDeque<String>,
+    // System calls to stop at (see catch syscall): None stands for any of them
+    syscall_catches: Vec<Option<usize>>,
+    // Display expressions by number, printed every time the inferior stops
+    displays: BTreeMap<usize, Display>,
+    tui: Option<Tui>,
+    /// Whether to run the .deetinit in the current directory (see trust_local_init)
+    local_init: bool,
+    /// How many files are being sourced, one from the other
+    source_depth: usize,
+    signal_policies: HashMap<Signal, SignalPolicy>,
+    // Signal that stopped the inferior, to be delivered when it is continued
+    pending_signal: Option<Signal>,
+    // The call stack where the inferior last stopped, innermost first
+    frames: Vec<Frame>,
+    // Index into frames of the frame that print and info locals/args look at
+    selected_frame: usize,
+    // Set by "record": execution history for the reverse-* commands
+    recorder: Option<Recorder>,
+    // Forked copies of the inferior saved by "checkpoint", by checkpoint number
+    checkpoints: BTreeMap<usize, Inferior>,
+    // Where to look for the separate debug files of shared libraries
+    debug_dir: String,
+    // Shared libraries loaded into the inferior, for breakpoints on functions in them
+    libraries: Vec<Library>,
+    // Address of the dynamic linker's hook (see solib) in the inferior, where deet breaks
+    library_hook: Option<usize>,
+    // Set while the inferior runs in the background (see continue &)
+    running: bool,
 }
 
 impl Debugger {
     /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
+    pub fn new(target: &str, debug_dir: &str) -> Debugger {
         // TODO (milestone 3): initialize the DwarfData
-        let debug_data = match DwarfData::from_file(target) {
+        let debug_data = match DwarfData::from_file(target, debug_dir) {
             Ok(val) => val,
             Err(DwarfError::ErrorOpeningFile) => {
                 println!("Could not open file {}", target);
@@ -30,12 +135,28 @@ impl Debugger {
                 std::process::exit(1);
             }
         };
-        debug_data.print();
+        if let Some(debug_file) = debug_data.debug_file() {
+            println!("Reading debugging symbols from {}", debug_file);
+        }
+        if !debug_data.has_debug_info() {
+            println!(
+                "Warning: no debugging information found in {}, using the ELF symbol table \
+                 instead. Source-level features (line numbers, variables) are unavailable.",
+                target
+            );
+        }
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         //() is unit type, when we are doing something like println!() , we are implicitly returning () type.
         //here it means there has no helper or use default helper.
 
-        let mut readline = Editor::<()>::new();
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .build();
+        let mut readline = Editor::<DebuggerHelper>::with_config(config);
+        readline.set_helper(Some(DebuggerHelper::new(
+            debug_data.function_names(),
+            debug_data.file_names(),
+        )));
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path); //store inside Editor...
 
@@ -44,116 +165,435 @@ impl Debugger {
             history_path,
             readline,
             inferior: None,
+            run_config: RunConfig::default(),
             dwarf_data: debug_data,
-            break_points: Vec::new(),
+            break_points: BTreeMap::new(),
+            pending_commands: VecDeque::new(),
+            syscall_catches: Vec::new(),
+            displays: BTreeMap::new(),
+            tui: None,
+            local_init: false,
+            source_depth: 0,
+            signal_policies: HashMap::new(),
+            pending_signal: None,
+            frames: Vec::new(),
+            selected_frame: 0,
+            recorder: None,
+            checkpoints: BTreeMap::new(),
+            debug_dir: debug_dir.to_string(),
+            libraries: Vec::new(),
+            library_hook: None,
+            running: false,
+        }
+    }
+
+    /// Prints everything read from the DWARF (see --dump-dwarf).
+    pub fn dump_dwarf(&self) {
+        self.dwarf_data.print();
+    }
+
+    /// Switches to the split-screen text UI, unless stdout isn't a terminal.
+    pub fn enable_tui(&mut self) {
+        self.tui = Tui::new();
+        if self.tui.is_none() {
+            println!("Warning: stdout is not a terminal, falling back to the line-based interface");
         }
     }
 
+    /// Runs the .deetinit in the current directory at startup. Off by default: the directory may
+    /// be a checkout of someone else's code, and the file can run programs.
+    pub fn trust_local_init(&mut self) {
+        self.local_init = true;
+    }
+
     pub fn run(&mut self) {
+        if !self.source_init_files() {
+            return;
+        }
         loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    if let Some(infer) = &mut self.inferior {
-                        //Error handling of error of kill.
-                        println!("program exist, killing and restarting.");
-                        infer.kill().expect("kill failed");
-                        //Dropped the variable.
-                        self.inferior = None;
-                    }
-                    if let Some(inferior) = Inferior::new(&self.target, &args) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        let infer = self.inferior.as_mut().unwrap();
-                        for b in self.break_points.iter() {
-                            infer.breakpoint(b).unwrap();
-                        }
+            let command = self.get_next_command();
+            if !self.execute(command) || !self.run_breakpoint_commands() {
+                return;
+            }
+        }
+    }
 
-                        infer.goon().unwrap();
-                        self.wait_thread();
-                    } else {
-                        println!("Error starting subprocess");
-                    }
-                }
-                DebuggerCommand::Continue => match &mut self.inferior {
-                    //can we judge from here?
-                    Some(infer) => {
-                        match infer.find_break_point(&self.dwarf_data) {
-                            Some(addr) => {
-                                match infer.continue_from_breakpoint(&addr) {
-                                    Ok(a) => {}
-                                    Err(e) => {
-                                        println!("err when continue from breakpoint {}", e);
-                                    }
-                                };
-                                println!("found current location correspond with breakpoint.")
-                            }
-                            None => {
-                                println!("addr not found!");
-                            }
-                        };
-                        match infer.goon() {
-                            Ok(_status) => {
-                                self.wait_thread();
-                            }
-                            Err(_e) => {}
-                        }
-                    }
-                    None => {
-                        println!("Run the program first!");
+    /// Sources ~/.deetinit, then ./.deetinit if trusted. Returns false if one of them quit.
+    fn source_init_files(&mut self) -> bool {
+        let home_init = std::env::var("HOME")
+            .ok()
+            .map(|home| Path::new(&home).join(INIT_FILE));
+        if let Some(home_init) = &home_init {
+            if home_init.exists() && !self.source(&home_init.to_string_lossy()) {
+                return false;
+            }
+        }
+        let local_init = Path::new(INIT_FILE);
+        let same_file = |path: &Path| match (path.canonicalize(), local_init.canonicalize()) {
+            (Ok(path), Ok(local)) => path == local,
+            _ => false,
+        };
+        if !local_init.exists() || home_init.as_deref().map_or(false, same_file) {
+            return true;
+        }
+        if !self.local_init {
+            println!(
+                "Warning: not running {} in the current directory. Start deet with --local-init \
+                 if you trust it.",
+                INIT_FILE
+            );
+            return true;
+        }
+        self.source(INIT_FILE)
+    }
+
+    /// Executes a single command. Returns false once the user has asked to quit.
+    fn execute(&mut self, command: DebuggerCommand) -> bool {
+        self.poll_background();
+        if self.running && !runs_in_background(&command) {
+            println!(
+                "Cannot execute this command while the program is running.\nUse the \"interrupt\" \
+                 command to stop the program and then try again."
+            );
+            return true;
+        }
+        match command {
+            DebuggerCommand::Run(args) => {
+                self.running = false;
+                if let Some(infer) = &mut self.inferior {
+                    //Error handling of error of kill.
+                    println!("program exist, killing and restarting.");
+                    infer.kill().expect("kill failed");
+                    //Dropped the variable.
+                    self.inferior = None;
+                }
+                self.recorder = None;
+                self.delete_checkpoints();
+                if let Some(args) = args {
+                    self.run_config.program_args = args;
+                }
+                if let Some(inferior) = Inferior::new(&self.target, &self.run_config) {
+                    // Create the inferior
+                    self.inferior = Some(inferior);
+                    self.pending_signal = None;
+                    self.forget_libraries();
+                    self.insert_breakpoints();
+                    self.watch_libraries();
+                    let infer = self.inferior.as_mut().unwrap();
+                    infer.set_trace_syscalls(!self.syscall_catches.is_empty());
+                    infer.goon(None).unwrap();
+                    self.wait_thread();
+                } else {
+                    println!("Error starting subprocess");
+                }
+            }
+            DebuggerCommand::TargetRemote(address) => {
+                self.running = false;
+                if let Some(infer) = &mut self.inferior {
+                    println!("program exist, killing it.");
+                    infer.kill().ok();
+                    self.inferior = None;
+                }
+                self.recorder = None;
+                self.delete_checkpoints();
+                // gdb's ":1234" means this machine
+                let address = if address.starts_with(':') {
+                    format!("127.0.0.1{}", address)
+                } else {
+                    address
+                };
+                if let Some(inferior) = Inferior::connect(&address) {
+                    println!("Remote debugging using {}", address);
+                    if !self.syscall_catches.is_empty() {
+                        println!("Catching system calls is not supported on remote targets.");
                     }
-                },
-                DebuggerCommand::BackTrace => match &self.inferior {
-                    Some(infer) => {
-                        infer.print_backtrace(&self.dwarf_data).expect("msg");
+                    self.inferior = Some(inferior);
+                    self.pending_signal = None;
+                    self.forget_libraries();
+                    self.insert_breakpoints();
+                    // Reports where the process is stopped
+                    self.wait_thread();
+                }
+            }
+            DebuggerCommand::Continue if self.recorder.is_some() => self.record_execute(false),
+            DebuggerCommand::Continue => {
+                if self.inferior.is_none() {
+                    println!("Run the program first!");
+                } else if self.continue_inferior() {
+                    self.wait_thread();
+                }
+            }
+            DebuggerCommand::ContinueBackground => self.continue_background(),
+            DebuggerCommand::Interrupt => self.interrupt(),
+            DebuggerCommand::BackTrace => match &self.inferior {
+                Some(infer) => {
+                    infer
+                        .print_backtrace(&self.dwarf_data, &self.libraries)
+                        .expect("msg");
+                }
+                None => {
+                    println!("Run the program first!");
+                }
+            },
+            DebuggerCommand::SetArgs(args) => self.run_config.program_args = args,
+            DebuggerCommand::SetEnvironment(name, value) => {
+                self.run_config.environment.insert(name, Some(value));
+            }
+            DebuggerCommand::UnsetEnvironment(Some(name)) => {
+                self.run_config.environment.insert(name, None);
+            }
+            DebuggerCommand::UnsetEnvironment(None) => {
+                self.run_config.environment.clear();
+                self.run_config.clear_environment = true;
+            }
+            DebuggerCommand::Tty(path) => self.run_config.tty = Some(path),
+            DebuggerCommand::Record => {
+                if self.inferior.is_none() {
+                    println!("The program is not being run.");
+                } else if self.recorder.is_some() {
+                    println!("The process is already being recorded.");
+                } else {
+                    self.recorder = Some(Recorder::new());
+                }
+            }
+            DebuggerCommand::RecordStop => match self.recorder.take() {
+                Some(_) => {
+                    println!("Process record is stopped and all execution logs are deleted.")
+                }
+                None => println!("Process record is not started."),
+            },
+            DebuggerCommand::InfoRecord => match &self.recorder {
+                Some(recorder) => println!(
+                    "Recording: {} instructions can be stepped back over.",
+                    recorder.len()
+                ),
+                None => println!("No recording is currently active."),
+            },
+            DebuggerCommand::ReverseStepInstruction => {
+                if let Some(rip) = self.step_back() {
+                    self.report_status(Status::Stopped(Signal::SIGTRAP, rip));
+                }
+            }
+            DebuggerCommand::ReverseNext => self.reverse_next(),
+            DebuggerCommand::ReverseContinue => {
+                let mut stopped_at = None;
+                while let Some(rip) = self.step_back() {
+                    stopped_at = Some(rip);
+                    if self.is_breakpoint(rip) {
+                        break;
                     }
-                    None => {
-                        println!("Run the program first!");
+                }
+                if let Some(rip) = stopped_at {
+                    self.report_status(Status::Stopped(Signal::SIGTRAP, rip));
+                }
+            }
+            DebuggerCommand::Checkpoint => match &mut self.inferior {
+                Some(infer) => match infer.checkpoint() {
+                    Ok((copy, signals)) => {
+                        let id = self.checkpoints.keys().next_back().map_or(1, |id| id + 1);
+                        println!("checkpoint {}: fork returned pid {}.", id, copy.pid());
+                        self.checkpoints.insert(id, copy);
+                        self.keep_signals(signals);
                     }
+                    Err(e) => println!("Error creating checkpoint: {}", e),
                 },
-                DebuggerCommand::Quit => {
-                    if let Some(infer) = &mut self.inferior {
-                        //Error handling of error of kill.
-                        println!("program exist, killing");
-                        infer.kill().expect("kill failed");
-                        //Dropped the variable.
-                        self.inferior = None;
+                None => println!("The program is not being run."),
+            },
+            DebuggerCommand::Restart(id) => self.restart(id),
+            DebuggerCommand::InfoCheckpoints => {
+                if self.checkpoints.is_empty() {
+                    println!("No checkpoints.");
+                }
+                for (id, checkpoint) in &self.checkpoints {
+                    let rip = match checkpoint.get_registers() {
+                        Ok(regs) => regs.rip as usize,
+                        Err(e) => {
+                            println!("  {} process {}: {}", id, checkpoint.pid(), e);
+                            continue;
+                        }
+                    };
+                    match self.dwarf_data.get_line_from_addr(rip) {
+                        Some(line) => {
+                            println!(
+                                "  {} process {} at {:#x}, {}",
+                                id,
+                                checkpoint.pid(),
+                                rip,
+                                line
+                            )
+                        }
+                        None => println!("  {} process {} at {:#x}", id, checkpoint.pid(), rip),
                     }
-                    return;
                 }
-                DebuggerCommand::Break(addr_str) => {
-                    let addr_usize = self.str_to_addr(addr_str).unwrap();
-                    // setup multiple kind of addr should interpret it to usize addr
-
-                    // let mut char_iter = arg.chars();
-                    // if char_iter.next().unwrap() == '*' {
-                    //     let remain = char_iter.as_str().to_string();
-                    //     return Some(DebuggerCommand::Break(remain));
-                    // }
-
-                    //first store the addr_usize into the break vec, should check contains first.
-                    if !self.break_points.contains(&addr_usize) {
-                        self.break_points.push(addr_usize.clone()); //ownership transfer??
-                    }
-                    //if already run program, we set the break point immediately
-                    if let Some(infer) = &mut self.inferior {
-                        //Error handling of error of break.
-                        println!("program exist, loading break points");
-                        infer.breakpoint(&addr_usize).expect("kill failed");
-                    }
-                    let mut index = 0;
-                    let mut cnt = 0;
-                    for pt in self.break_points.iter() {
-                        if *pt == addr_usize {
-                            index = cnt;
-                            break;
+            }
+            DebuggerCommand::DeleteCheckpoint(id) => match self.checkpoints.remove(&id) {
+                Some(mut checkpoint) => {
+                    if let Err(e) = checkpoint.kill() {
+                        println!("Error killing checkpoint {}: {}", id, e);
+                    }
+                }
+                None => println!("No checkpoint number {}.", id),
+            },
+            DebuggerCommand::CatchSyscall(names) => {
+                if names.is_empty() {
+                    self.syscall_catches.push(None);
+                    println!("Catching any syscall.");
+                }
+                for name in names {
+                    match syscall::number(&name) {
+                        Some(number) => {
+                            self.syscall_catches.push(Some(number));
+                            println!("Catching syscall '{}' [{}].", syscall::name(number), number);
                         }
-                        cnt = cnt + 1;
+                        None => println!("Unknown syscall name '{}'.", name),
+                    }
+                }
+                match &mut self.inferior {
+                    Some(infer) if infer.is_remote() => {
+                        println!("Catching system calls is not supported on remote targets.")
+                    }
+                    Some(infer) => infer.set_trace_syscalls(!self.syscall_catches.is_empty()),
+                    None => {}
+                }
+            }
+            DebuggerCommand::DeleteCatchpoints => {
+                self.syscall_catches.clear();
+                if let Some(infer) = &mut self.inferior {
+                    infer.set_trace_syscalls(false);
+                }
+            }
+            DebuggerCommand::Print(expr) | DebuggerCommand::Call(expr) => {
+                self.print_expression(&expr)
+            }
+            DebuggerCommand::InfoLocals => self.print_frame_variables(false),
+            DebuggerCommand::InfoArgs => self.print_frame_variables(true),
+            DebuggerCommand::Up(count) => {
+                if self.selected_frame + 1 >= self.frames.len() {
+                    println!("Initial frame selected; you cannot go up.");
+                } else {
+                    let frame = std::cmp::min(self.selected_frame + count, self.frames.len() - 1);
+                    self.select_frame(frame);
+                }
+            }
+            DebuggerCommand::Down(count) => {
+                if self.selected_frame == 0 {
+                    println!("Bottom (innermost) frame selected; you cannot go down.");
+                } else {
+                    self.select_frame(self.selected_frame.saturating_sub(count));
+                }
+            }
+            DebuggerCommand::Frame(Some(frame)) => {
+                if frame < self.frames.len() {
+                    self.select_frame(frame);
+                } else {
+                    println!("No frame at level {}.", frame);
+                }
+            }
+            DebuggerCommand::Frame(None) => {
+                if self.frames.is_empty() {
+                    println!("No stack.");
+                } else {
+                    self.select_frame(self.selected_frame);
+                }
+            }
+            DebuggerCommand::Quit => {
+                self.running = false;
+                if let Some(infer) = &mut self.inferior {
+                    //Error handling of error of kill.
+                    println!("program exist, killing");
+                    infer.kill().expect("kill failed");
+                    //Dropped the variable.
+                    self.inferior = None;
+                }
+                self.delete_checkpoints();
+                return false;
+            }
+            DebuggerCommand::Break(spec) => self.set_breakpoint(spec, false, None),
+            DebuggerCommand::TemporaryBreak(spec) => self.set_breakpoint(spec, true, None),
+            DebuggerCommand::Trace(spec, path, variables) => {
+                self.set_breakpoint(spec, false, Some(Tracepoint { path, variables }))
+            }
+            DebuggerCommand::Commands(index) => {
+                println!("Type commands for when the breakpoint is hit, one per line.");
+                println!("End with a line saying just \"end\".");
+                let mut commands = Vec::new();
+                loop {
+                    match self.readline.readline(">") {
+                        Ok(line) if line.trim() == "end" => break,
+                        Ok(line) if is_comment(&line) => {}
+                        Ok(line) => commands.push(line.trim().to_string()),
+                        Err(_) => break,
                     }
-                    println!("Set breakpoint {} at {}", index, addr_usize);
+                }
+                self.set_breakpoint_commands(index, commands);
+            }
+            DebuggerCommand::Until(Some(spec)) => self.run_to_location(&spec, true),
+            DebuggerCommand::Until(None) => self.until_next_line(),
+            DebuggerCommand::Advance(spec) => self.run_to_location(&spec, false),
+            DebuggerCommand::InfoSymbol(addr_str) => match self.parse_address(&addr_str) {
+                Some(addr) => match self.dwarf_data.get_symbol_from_addr(addr) {
+                    Some((name, 0)) => println!("{:#x} is {}", addr, name),
+                    Some((name, offset)) => {
+                        println!("{:#x} is {}+{:#x}", addr, name, offset)
+                    }
+                    None => println!("No symbol matches {}.", addr_str),
+                },
+                None => println!("Invalid address {}", addr_str),
+            },
+            DebuggerCommand::InfoFunctions(regex) => self.info_functions(regex),
+            DebuggerCommand::InfoVariables(regex) => self.info_variables(regex),
+            DebuggerCommand::InfoTypes(regex) => self.info_types(regex),
+            DebuggerCommand::InfoLine(spec) => self.info_line(spec),
+            DebuggerCommand::InfoAddress(name) => self.info_address(&name),
+            DebuggerCommand::InfoFrame => self.info_frame(),
+            DebuggerCommand::StepInstruction if self.recorder.is_some() => {
+                self.record_execute(true)
+            }
+            DebuggerCommand::StepInstruction => match &mut self.inferior {
+                Some(infer) => match infer.step_instruction(self.pending_signal.take()) {
+                    Ok(status) => self.report_status(status),
+                    Err(e) => println!("Error stepping the program: {}", e),
+                },
+                None => {
+                    println!("Run the program first!");
+                }
+            },
+            DebuggerCommand::Disassemble(raw, arg) => self.disassemble(raw, arg),
+            DebuggerCommand::ExamineInstructions(count, expr) => {
+                self.examine_instructions(count, expr)
+            }
+            DebuggerCommand::Display(Some(expr)) => self.add_display(expr, false),
+            DebuggerCommand::DisplayInstructions(expr) => self.add_display(expr, true),
+            DebuggerCommand::Display(None) => self.show_displays(),
+            DebuggerCommand::Undisplay(Some(number)) => {
+                if self.displays.remove(&number).is_none() {
+                    println!("No display number {}.", number);
+                }
+            }
+            DebuggerCommand::Undisplay(None) => self.displays.clear(),
+            DebuggerCommand::InfoDisplay => {
+                if self.displays.is_empty() {
+                    println!("There are no auto-display expressions now.");
+                    return true;
+                }
+                println!("Auto-display expressions now in effect:");
+                println!("Num Expression");
+                for (number, display) in &self.displays {
+                    let format = if display.instructions { "/i " } else { "" };
+                    println!("{}:   {}{}", number, format, display.expr);
                 }
             }
+            DebuggerCommand::SaveBreakpoints(path) => match self.save_breakpoints(&path) {
+                Ok(()) => println!("Saved to file '{}'.", path),
+                Err(e) => println!("Could not save to {}: {}", path, e),
+            },
+            DebuggerCommand::Source(path) => return self.source(&path),
+            DebuggerCommand::Handle(signal, actions) => self.handle_signal(&signal, &actions),
+            DebuggerCommand::Help(topic) => Debugger::print_help(topic),
         }
+        true
     }
+
     fn parse_address(&self, addr: &str) -> Option<usize> {
         let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
             &addr[2..]
@@ -163,89 +603,2261 @@ impl Debugger {
         usize::from_str_radix(addr_without_0x, 16).ok()
     }
     fn str_to_addr(&self, str: String) -> Option<usize> {
-        match usize::from_str_radix(&str, 10) {
-            Ok(line) => match self.dwarf_data.get_addr_for_line(None, line) {
-                Some(addr) => {
-                    println!("addr from line: {}", addr);
-                    return Some(addr);
-                }
-                None => {}
-            },
-            Err(e) => {}
-        };
-        let mut char_iter = str.chars();
-        if char_iter.next().unwrap() == '*' {
-            let remain = char_iter.as_str().to_string();
-            return Some(self.parse_address(&remain).unwrap());
+        if let Ok(line) = usize::from_str_radix(&str, 10) {
+            if let Some(addr) = self.dwarf_data.get_addr_for_line(None, line) {
+                return Some(addr);
+            }
+        }
+        if str.starts_with('*') {
+            return self.parse_address(&str[1..]);
         }
         match self.dwarf_data.get_addr_for_function(None, &str) {
             Some(addr) => {
-                return Some(addr);
+                return Some(self.dwarf_data.skip_prologue(addr));
             }
             None => {}
         }
         None
     }
-    fn wait_thread(&mut self) {
-        let infer = self.inferior.as_ref().unwrap();
-        match infer.wait(None).expect("encounter error when waiting") {
-            crate::inferior::Status::Stopped(signal, instruction_ptr) => {
-                println!("Child stopped (signal {})", signal);
-                let line_number = self.dwarf_data.get_line_from_addr(instruction_ptr);
-                match line_number {
-                    Some(line) => println!("Stopped at {}", line),
-                    None => {}
+    /// Looks a function up in the shared libraries loaded into the inferior, returning where
+    /// breaking on it stops and the library it is in.
+    fn find_in_libraries(&self, spec: &str) -> Option<(usize, String)> {
+        self.libraries
+            .iter()
+            .find_map(|lib| lib.find_function(spec).map(|addr| (addr, lib.path.clone())))
+    }
+    /// Describes addr as a source line, or else as a symbol and offset.
+    fn describe_address(&self, addr: usize) -> Option<String> {
+        let (debug_data, bias) = solib::debug_data_at(&self.dwarf_data, &self.libraries, addr);
+        if let Some(line) = debug_data.get_line_from_addr(addr - bias) {
+            return Some(line.to_string());
+        }
+        let (name, offset) = debug_data.get_symbol_from_addr(addr - bias)?;
+        Some(format!("{}+{:#x} ({:#x})", name, offset, addr))
+    }
+    fn set_breakpoint(&mut self, spec: String, temporary: bool, trace: Option<Tracepoint>) {
+        let mut library = None;
+        let addr = match self.str_to_addr(spec.clone()) {
+            Some(addr) => Some(addr),
+            None if spec.starts_with('*') => {
+                println!("Invalid address \"{}\".", &spec[1..]);
+                return;
+            }
+            None if spec.chars().all(|c| c.is_ascii_digit()) => {
+                println!("No line {} in the current file.", spec);
+                return;
+            }
+            None => {
+                // Look in the libraries loaded so far, or leave the breakpoint pending until one
+                // that has the function is loaded
+                self.load_libraries();
+                match self.find_in_libraries(&spec) {
+                    Some((addr, path)) => {
+                        library = Some(path);
+                        Some(addr)
+                    }
+                    None => {
+                        println!("Function \"{}\" not defined.", spec);
+                        None
+                    }
                 }
             }
-            crate::inferior::Status::Exited(exit_code) => {
-                println!("Child exited (status {})", exit_code);
-                self.inferior = None;
+        };
+        // setup multiple kind of addr should interpret it to usize addr
+
+        //first store the breakpoint into the break map, should check contains first.
+        // Each tracepoint is kept separately, since it logs its own variables to its own file
+        let existing = self
+            .break_points
+            .iter()
+            .find(|(_, bp)| {
+                addr.is_some()
+                    && bp.addr == addr
+                    && bp.temporary == temporary
+                    && bp.trace.is_none()
+                    && trace.is_none()
+            })
+            .map(|(index, _)| *index);
+        let is_trace = trace.is_some();
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let index = self
+                    .break_points
+                    .keys()
+                    .next_back()
+                    .map_or(0, |index| index + 1);
+                self.break_points.insert(
+                    index,
+                    UserBreakpoint {
+                        spec: spec.clone(),
+                        addr,
+                        library,
+                        temporary,
+                        commands: Vec::new(),
+                        trace,
+                    },
+                );
+                //if already run program, we set the break point immediately
+                if let (Some(infer), Some(addr)) = (&mut self.inferior, addr) {
+                    //Error handling of error of break.
+                    println!("program exist, loading break points");
+                    if temporary {
+                        infer.one_shot_breakpoint(addr).expect("kill failed");
+                    } else {
+                        infer.breakpoint(&addr).expect("kill failed");
+                    }
+                }
+                index
             }
-            crate::inferior::Status::Signaled(signal) => {
-                println!("Child signaled (signal {})", signal);
-                self.inferior = None;
+        };
+        let addr = match addr {
+            Some(addr) => addr,
+            None => {
+                let kind = if temporary {
+                    "Temporary breakpoint"
+                } else if is_trace {
+                    "Tracepoint"
+                } else {
+                    "Breakpoint"
+                };
+                println!("{} {} ({}) pending.", kind, index, spec);
+                return;
             }
-            crate::inferior::Status::Continued => {}
+        };
+        if temporary {
+            println!("Temporary breakpoint {} at {}", index, addr);
+        } else if is_trace {
+            println!("Tracepoint {} at {}", index, addr);
+        } else {
+            println!("Set breakpoint {} at {}", index, addr);
         }
     }
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
-        loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
+
+    /// Forgets the libraries of the previous inferior before another is started or connected
+    /// to. Breakpoints resolved in them go back to pending, as the libraries may be loaded at
+    /// other addresses this time.
+    fn forget_libraries(&mut self) {
+        self.libraries.clear();
+        self.library_hook = None;
+        for bp in self.break_points.values_mut() {
+            if bp.library.take().is_some() {
+                bp.addr = None;
+            }
+        }
+    }
+
+    /// Starts following the shared libraries of a newly started inferior, breaking at the
+    /// dynamic linker's hook to pick them up as they are loaded. Remote targets aren't followed.
+    fn watch_libraries(&mut self) {
+        let pid = match &self.inferior {
+            Some(infer) if !infer.is_remote() => infer.pid(),
+            _ => return,
+        };
+        self.load_libraries();
+        let hook = solib::interpreter_base(pid).and_then(|base| {
+            self.libraries
+                .iter()
+                .find(|lib| lib.start == base)?
+                .find_symbol(solib::DEBUG_STATE_HOOK)
+        });
+        if let (Some(hook), Some(infer)) = (hook, &mut self.inferior) {
+            match infer.one_shot_breakpoint(hook) {
+                Ok(()) => self.library_hook = Some(hook),
+                Err(e) => println!("Error setting breakpoint at {:#x}: {}", hook, e),
+            }
+        }
+    }
+
+    /// Brings the list of shared libraries up to date with what the inferior has mapped, and
+    /// resolves pending breakpoints in the libraries it has just loaded.
+    fn load_libraries(&mut self) {
+        let pid = match &self.inferior {
+            Some(infer) if !infer.is_remote() => infer.pid(),
+            _ => {
+                self.libraries.clear();
+                return;
+            }
+        };
+        let executable = fs::canonicalize(&self.target).ok();
+        let mapped: Vec<(usize, String)> = solib::mapped_files(pid)
+            .into_iter()
+            .filter(|(_, path)| Some(Path::new(path)) != executable.as_deref())
+            .collect();
+
+        // Breakpoints in libraries that have been unloaded are pending again
+        let (kept, unloaded): (Vec<Library>, Vec<Library>) = std::mem::take(&mut self.libraries)
+            .into_iter()
+            .partition(|lib| mapped.contains(&(lib.start, lib.path.clone())));
+        self.libraries = kept;
+        for lib in unloaded {
+            for (index, bp) in self.break_points.iter_mut() {
+                if bp.library.as_ref() != Some(&lib.path) {
+                    continue;
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                if let (Some(infer), Some(addr)) = (&mut self.inferior, bp.addr) {
+                    // The library's code is gone, so there is no int3 left to take out
+                    if bp.temporary {
+                        infer.remove_one_shot_breakpoint(addr).ok();
+                    } else {
+                        infer.remove_breakpoint(addr).ok();
+                    }
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                bp.addr = None;
+                bp.library = None;
+                println!(
+                    "Breakpoint {} ({}) pending, {} was unloaded",
+                    index, bp.spec, lib.path
+                );
+            }
+        }
+
+        let first_new = self.libraries.len();
+        for (start, path) in mapped {
+            if self.libraries.iter().any(|lib| lib.path == path) {
+                continue;
+            }
+            if let Some(lib) = Library::load(&path, start, &self.debug_dir) {
+                self.libraries.push(lib);
+            }
+        }
+        for lib in &self.libraries[first_new..] {
+            for (index, bp) in self.break_points.iter_mut() {
+                if bp.addr.is_some() || bp.spec.starts_with('*') {
+                    continue;
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
+                let addr = match lib.find_function(&bp.spec) {
+                    Some(addr) => addr,
+                    None => continue,
+                };
+                if let Some(infer) = &mut self.inferior {
+                    let result = if bp.temporary {
+                        infer.one_shot_breakpoint(addr)
+                    } else {
+                        infer.breakpoint(&addr)
+                    };
+                    if let Err(e) = result {
+                        println!("Error setting breakpoint at {:#x}: {}", addr, e);
                         continue;
                     }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                }
+                bp.addr = Some(addr);
+                bp.library = Some(lib.path.clone());
+                println!(
+                    "Pending breakpoint {} ({}) resolved at {:#x} in {}",
+                    index, bp.spec, addr, lib.path
+                );
+            }
+        }
+    }
+    fn set_breakpoint_commands(&mut self, index: Option<usize>, commands: Vec<String>) {
+        for command in &commands {
+            if DebuggerCommand::from_line(command).is_none() {
+                println!("Unrecognized command \"{}\".", command);
+                return;
+            }
+        }
+        let index = match index.or_else(|| self.break_points.keys().next_back().cloned()) {
+            Some(index) => index,
+            None => {
+                println!("No breakpoints specified.");
+                return;
+            }
+        };
+        match self.break_points.get_mut(&index) {
+            Some(bp) => bp.commands = commands,
+            None => println!("No breakpoint number {}.", index),
+        }
+    }
+    /// Runs the commands of the breakpoint the inferior last stopped at, which may continue it to
+    /// the next breakpoint with commands, and so on. Returns false if one of them was quit.
+    fn run_breakpoint_commands(&mut self) -> bool {
+        while let Some(line) = self.pending_commands.pop_front() {
+            if let Some(command) = DebuggerCommand::from_line(&line) {
+                if !self.execute(command) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    /// If addr only has tracepoints (and no breakpoints that stop), logs each of them and returns
+    /// true.
+    fn log_tracepoints(&self, addr: usize) -> bool {
+        let mut tracepoints = Vec::new();
+        for bp in self
+            .break_points
+            .values()
+            .filter(|bp| bp.addr == Some(addr))
+        {
+            match &bp.trace {
+                Some(trace) => tracepoints.push(trace),
+                None => return false,
+            }
+        }
+        if tracepoints.is_empty() {
+            return false;
+        }
+        let infer = match &self.inferior {
+            Some(infer) => infer,
+            None => return false,
+        };
+        let frames = infer
+            .unwind(&self.dwarf_data, &self.libraries)
+            .unwrap_or_default();
+        let frame = match frames.first() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        for trace in tracepoints {
+            let mut entry = frame.describe(&self.dwarf_data, &self.libraries);
+            for (index, name) in trace.variables.iter().enumerate() {
+                let lookup_ptr = frame.lookup_ptr();
+                let value = match self
+                    .dwarf_data
+                    .get_variable(lookup_ptr, frame.inline_depth, name)
+                {
+                    Some(var) => match self.read_variable(infer, var, frame) {
+                        Ok(value) => value,
+                        Err(e) => format!("<cannot access memory: {}>", e),
+                    },
+                    None => "<not in scope>".to_string(),
+                };
+                let separator = if index == 0 { ": " } else { ", " };
+                entry.push_str(&format!("{}{} = {}", separator, name, value));
+            }
+            let result = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&trace.path)
+                .and_then(|mut file| writeln!(file, "{}", entry));
+            if let Err(e) = result {
+                println!("Could not write to {}: {}", trace.path, e);
+            }
+        }
+        true
+    }
+    /// Deletes the temporary breakpoint at addr, if there is one, now that it has been hit.
+    fn delete_temporary_breakpoint(&mut self, addr: usize) {
+        let index = match self
+            .break_points
+            .iter()
+            .find(|(_, bp)| bp.temporary && bp.addr == Some(addr))
+        {
+            Some((index, _)) => *index,
+            None => return,
+        };
+        self.break_points.remove(&index);
+        println!("Temporary breakpoint {} hit and deleted", index);
+        if let Some(infer) = &mut self.inferior {
+            if let Err(e) = infer.remove_one_shot_breakpoint(addr) {
+                println!("Error removing breakpoint at {:#x}: {}", addr, e);
+            }
+        }
+    }
+    /// Continues to spec, stopping early if the current function returns, like gdb's until and
+    /// advance. With current_frame_only (until), the location is only stopped at in this frame
+    /// or a caller, not in calls made from here.
+    fn run_to_location(&mut self, spec: &str, current_frame_only: bool) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        if self.recorder.is_some() {
+            println!("until and advance are not supported while recording.");
+            return;
+        }
+        let addr = self.str_to_addr(spec.to_string());
+        let addr = match addr.or_else(|| self.find_in_libraries(spec).map(|(addr, _)| addr)) {
+            Some(addr) => addr,
+            None => {
+                println!("Function \"{}\" not defined.", spec);
+                return;
+            }
+        };
+        // The innermost real (not inlined) frame, and where it returns to
+        let frame = self.frames.iter().position(|frame| frame.inline_depth == 0);
+        let stack_ptr = frame.map(|index| self.frames[index].stack_ptr);
+        let return_addr = frame
+            .and_then(|index| self.frames.get(index + 1))
+            .map(|caller| caller.instruction_ptr);
+        let targets: Vec<usize> = std::iter::once(addr).chain(return_addr).collect();
+        let infer = self.inferior.as_mut().unwrap();
+        for target in &targets {
+            if let Err(e) = infer.one_shot_breakpoint(*target) {
+                println!("Error setting breakpoint at {:#x}: {}", target, e);
+                return;
+            }
+        }
+        let status = loop {
+            let status = match self.resume() {
+                Some(status) => status,
+                None => return,
+            };
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if !self.is_user_breakpoint(rip) => rip,
+                _ => break status,
+            };
+            let rsp = match self.inferior.as_ref().map(|infer| infer.get_registers()) {
+                Some(Ok(regs)) => regs.rsp as usize,
+                _ => break status,
+            };
+            // A stack pointer below the original frame's means a call made from it (possibly a
+            // recursive one) hit the breakpoint; at or above it, this frame or a caller did
+            let in_callee = stack_ptr.map_or(false, |stack_ptr| rsp < stack_ptr);
+            if rip == addr && !(current_frame_only && in_callee) {
+                break status;
+            }
+            if Some(rip) == return_addr && rsp > stack_ptr.unwrap_or(0) {
+                break status;
+            }
+            if !targets.contains(&rip) {
+                break status;
+            }
+        };
+        if let Some(infer) = &mut self.inferior {
+            for target in targets {
+                infer.remove_one_shot_breakpoint(target).ok();
+            }
+        }
+        self.report_status(status);
+    }
+    /// until without a location: runs to the start of a line after the current one, stepping
+    /// over calls. As with gdb, jumping back to an earlier line (at the end of a loop) doesn't
+    /// stop it, while returning from the function does.
+    fn until_next_line(&mut self) {
+        let infer = match &self.inferior {
+            Some(infer) => infer,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        if self.recorder.is_some() {
+            println!("until and advance are not supported while recording.");
+            return;
+        }
+        let rip = match infer.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return;
+            }
+        };
+        let (function_start, function_end, line_end) = match (
+            self.dwarf_data.get_function_range_from_addr(rip),
+            self.dwarf_data.get_line_range(rip),
+        ) {
+            (Some((start, end)), Some((_, _, line_end))) => (start, end, line_end),
+            _ => {
+                println!("No line number information available.");
+                return;
+            }
+        };
+        let status = loop {
+            let status = match self.step_over_instruction() {
+                Some(status) => status,
+                None => return,
+            };
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if !self.is_user_breakpoint(rip) => rip,
+                _ => break status,
+            };
+            if rip < function_start || rip >= function_end {
+                break status;
+            }
+            if rip >= line_end {
+                match self.dwarf_data.get_line_range(rip) {
+                    Some((_, start, _)) if start != rip => {}
+                    _ => break status,
+                }
+            }
+        };
+        self.report_status(status);
+    }
+    /// Runs the instruction the inferior is at, for until. A call is run to completion rather
+    /// than stepped into, unless it stops somewhere on the way. Signals that aren't set to stop
+    /// the program are handled as when continuing. Returns None (after saying why) if the
+    /// inferior couldn't be resumed.
+    fn step_over_instruction(&mut self) -> Option<Status> {
+        let infer = self.inferior.as_ref().unwrap();
+        let regs = match infer.get_registers() {
+            Ok(regs) => regs,
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return None;
+            }
+        };
+        let rip = regs.rip as usize;
+        let call = infer
+            .read_memory(rip, disassembler::MAX_INSTRUCTION_LEN)
+            .ok()
+            .and_then(|code| disassembler::decode(&code, rip, 1).pop())
+            .filter(|instruction| instruction.text.starts_with("call"));
+        let call = match call {
+            Some(call) => call,
+            None => loop {
+                let infer = self.inferior.as_mut().unwrap();
+                let status = match infer.step_instruction(self.pending_signal.take()) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        println!("Error stepping the program: {}", e);
+                        return None;
                     }
+                };
+                let signal = match status {
+                    Status::Stopped(signal, _) if signal != Signal::SIGTRAP => signal,
+                    _ => return Some(status),
+                };
+                let policy = self.signal_policy(signal);
+                self.pending_signal = if policy.pass { Some(signal) } else { None };
+                if policy.stop {
+                    return Some(status);
+                }
+                if policy.print {
+                    println!("Child received signal {}", signal);
                 }
+            },
+        };
+        let return_addr = rip + call.bytes.len();
+        let infer = self.inferior.as_mut().unwrap();
+        if let Err(e) = infer.one_shot_breakpoint(return_addr) {
+            println!("Error setting breakpoint at {:#x}: {}", return_addr, e);
+            return None;
+        }
+        let status = loop {
+            let status = match self.resume() {
+                Some(status) => status,
+                None => break None,
+            };
+            let rip = match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if rip == return_addr => rip,
+                _ => break Some(status),
+            };
+            // A recursive call returning to the same place, deeper in the stack, carries on
+            let rsp = self
+                .inferior
+                .as_ref()
+                .unwrap()
+                .get_registers()
+                .map(|r| r.rsp);
+            if self.is_user_breakpoint(rip) || rsp.map_or(true, |rsp| rsp >= regs.rsp) {
+                break Some(status);
             }
+        };
+        if let Some(infer) = &mut self.inferior {
+            infer.remove_one_shot_breakpoint(return_addr).ok();
         }
+        status
     }
+    /// Continues the inferior, first stepping over the breakpoint it is stopped at (if any), and
+    /// waits for it to stop again. Returns None (after saying why) if it couldn't be resumed.
+    fn resume(&mut self) -> Option<Status> {
+        let status = self.resume_once()?;
+        self.pass_tracepoints(status)
+    }
+    /// Keeps the inferior going for as long as it stops only at tracepoints.
+    fn pass_tracepoints(&mut self, mut status: Status) -> Option<Status> {
+        while let Status::Stopped(Signal::SIGTRAP, rip) = status {
+            if !self.log_tracepoints(rip) {
+                break;
+            }
+            status = self.resume_once()?;
+        }
+        Some(status)
+    }
+    fn resume_once(&mut self) -> Option<Status> {
+        if !self.continue_inferior() {
+            return None;
+        }
+        Some(self.wait_for_stop())
+    }
+    /// Continues the inferior (stepping over the breakpoint it is stopped at, if any), without
+    /// waiting for it. Returns false if it couldn't be continued.
+    fn continue_inferior(&mut self) -> bool {
+        let infer = match self.inferior.as_mut() {
+            Some(infer) => infer,
+            None => return false,
+        };
+        match infer.find_break_point(&self.dwarf_data) {
+            Ok(Some(addr)) => {
+                if let Err(e) = infer.continue_from_breakpoint(&addr) {
+                    println!("err when continue from breakpoint {}", e);
+                    return false;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return false;
+            }
+        }
+        if let Err(e) = infer.goon(self.pending_signal.take()) {
+            println!("Error continuing the program: {}", e);
+            return false;
+        }
+        true
+    }
+    /// continue &: continues the inferior and goes back to the prompt while it runs.
+    fn continue_background(&mut self) {
+        match &self.inferior {
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+            Some(infer) if infer.is_remote() => {
+                println!("Background execution is not supported on remote targets.");
+                return;
+            }
+            Some(_) if self.recorder.is_some() => {
+                println!("Background execution is not supported while recording.");
+                return;
+            }
+            Some(_) => {}
+        }
+        if self.continue_inferior() {
+            println!("Continuing.");
+            self.running = true;
+        }
+    }
+    /// Stops the inferior running in the background, and reports where.
+    fn interrupt(&mut self) {
+        let infer = match &self.inferior {
+            Some(infer) if self.running => infer,
+            _ => {
+                println!("The program is not running.");
+                return;
+            }
+        };
+        interrupt::request(interrupt::Target::of(infer));
+        self.running = false;
+        self.wait_thread();
+    }
+    /// Checks on the inferior running in the background, reporting it if it has stopped or
+    /// exited. Tracepoints it has hit are logged and it is left running.
+    fn poll_background(&mut self) {
+        while self.running {
+            let status = match self.next_stop(Some(WaitPidFlag::WNOHANG)) {
+                Some(status) => status,
+                None => return,
+            };
+            if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                if self.log_tracepoints(rip) && self.continue_inferior() {
+                    continue;
+                }
+            }
+            self.running = false;
+            self.report_status(status);
+        }
+    }
+    fn wait_thread(&mut self) {
+        let status = self.wait_for_stop();
+        if let Some(status) = self.pass_tracepoints(status) {
+            self.report_status(status);
+        }
+    }
+    /// Waits for the inferior to stop, exit or be killed, passing on signals that aren't
+    /// configured to stop it. Ctrl+C stops it meanwhile.
+    fn wait_for_stop(&mut self) -> Status {
+        let _interrupts = interrupt::catch(self.inferior.as_ref().unwrap());
+        self.next_stop(None)
+            .expect("inferior still running after a blocking wait")
+    }
+    /// Does the work of wait_for_stop. With WNOHANG, returns None if the inferior is still
+    /// running.
+    fn next_stop(&mut self, options: Option<WaitPidFlag>) -> Option<Status> {
+        loop {
+            let status = self
+                .inferior
+                .as_ref()
+                .unwrap()
+                .wait(options)
+                .expect("encounter error when waiting");
+            if let Status::Continued = status {
+                return None;
+            }
+            if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                // The dynamic linker has loaded or unloaded libraries (see solib); unless the
+                // user also has a breakpoint here, carry on once they have been looked at
+                if Some(rip) == self.library_hook {
+                    self.load_libraries();
+                    if !self.is_user_breakpoint(rip) {
+                        let infer = self.inferior.as_mut().unwrap();
+                        match infer
+                            .step_instruction(None)
+                            .expect("error stepping the inferior")
+                        {
+                            Status::Stopped(Signal::SIGTRAP, _) => {
+                                infer.goon(None).expect("error continuing the inferior");
+                                continue;
+                            }
+                            status => return Some(status),
+                        }
+                    }
+                }
+            }
+            let infer = self.inferior.as_ref().unwrap();
+            if let Status::SyscallEntry(number, _) | Status::SyscallExit(number, _) = status {
+                if !self.catches_syscall(number) {
+                    infer.goon(None).expect("error continuing the inferior");
+                    continue;
+                }
+            }
+            if let Status::Stopped(signal, _) = status {
+                // Stopped by Ctrl+C or interrupt, rather than by a signal of the program's own,
+                // which isn't passed on to it
+                if (signal == Signal::SIGINT || signal == Signal::SIGSTOP)
+                    && interrupt::take_request()
+                {
+                    self.pending_signal = None;
+                    return Some(status);
+                }
+                if signal != Signal::SIGTRAP {
+                    let policy = self.signal_policy(signal);
+                    let deliver = if policy.pass { Some(signal) } else { None };
+                    if !policy.stop {
+                        if policy.print {
+                            println!("Child received signal {}", signal);
+                        }
+                        infer.goon(deliver).expect("error continuing the inferior");
+                        continue;
+                    }
+                    self.pending_signal = deliver;
+                }
+            }
+            return Some(status);
+        }
+    }
+    fn catches_syscall(&self, number: usize) -> bool {
+        self.syscall_catches
+            .iter()
+            .any(|catch| catch.map_or(true, |catch| catch == number))
+    }
+
+    /// Puts the user's breakpoints into a newly started or connected inferior.
+    fn insert_breakpoints(&mut self) {
+        let infer = match &mut self.inferior {
+            Some(infer) => infer,
+            None => return,
+        };
+        for bp in self.break_points.values() {
+            let addr = match bp.addr {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let result = if bp.temporary {
+                infer.one_shot_breakpoint(addr)
+            } else {
+                infer.breakpoint(&addr)
+            };
+            if let Err(e) = result {
+                println!("Error setting breakpoint at {:#x}: {}", addr, e);
+            }
+        }
+    }
+
+    /// Starts the program and serves it to a GDB remote protocol client (e.g. gdb's target
+    /// remote) on address, instead of reading commands. An address without a host (":1234")
+    /// listens on the loopback interface only. Returns deet's exit status.
+    pub fn gdbserver(&mut self, address: &str, args: Vec<String>) -> i32 {
+        self.run_config.program_args.args = args;
+        let address = if address.starts_with(':') {
+            format!("127.0.0.1{}", address)
+        } else {
+            address.to_string()
+        };
+        let listener = match TcpListener::bind(&address) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Can't listen on {}: {}", address, e);
+                return 1;
+            }
+        };
+        let infer = match Inferior::new(&self.target, &self.run_config) {
+            Some(infer) => infer,
+            None => {
+                eprintln!("Error starting subprocess");
+                return 1;
+            }
+        };
+        println!("Process {} created; pid = {}", self.target, infer.pid());
+        if let Ok(address) = listener.local_addr() {
+            println!("Listening on port {}", address.port());
+        }
+        match gdbserver::serve(infer, &listener) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Remote connection failed: {}", e);
+                1
+            }
+        }
+    }
+
+    /// Runs the program to completion without stopping, logging every system call it makes to
+    /// stderr like strace. Returns the program's exit status.
+    pub fn strace(&mut self, args: Vec<String>) -> i32 {
+        self.run_config.program_args.args = args;
+        let mut infer = match Inferior::new(&self.target, &self.run_config) {
+            Some(infer) => infer,
+            None => {
+                eprintln!("Error starting subprocess");
+                return 1;
+            }
+        };
+        infer.set_trace_syscalls(true);
+        let mut signal = None;
+        loop {
+            let status = infer.goon(signal.take()).and_then(|_| infer.wait(None));
+            match status {
+                // Calls are logged on return, when their results are known, except for those
+                // that don't return
+                Ok(Status::SyscallEntry(number, _)) if !syscall::is_noreturn(number) => {}
+                Ok(Status::Continued) => {}
+                Ok(Status::SyscallEntry(..)) => match infer.get_registers() {
+                    Ok(regs) => eprintln!("{} = ?", syscall::format_call(&infer, &regs, false)),
+                    Err(e) => {
+                        eprintln!("Error reading registers: {}", e);
+                        return 1;
+                    }
+                },
+                Ok(Status::SyscallExit(..)) => match infer.get_registers() {
+                    Ok(regs) => eprintln!(
+                        "{} = {}",
+                        syscall::format_call(&infer, &regs, true),
+                        syscall::format_return(&regs)
+                    ),
+                    Err(e) => {
+                        eprintln!("Error reading registers: {}", e);
+                        return 1;
+                    }
+                },
+                Ok(Status::Stopped(received, _)) => {
+                    eprintln!("--- {} ---", received);
+                    signal = Some(received);
+                }
+                Ok(Status::Exited(exit_code)) => {
+                    eprintln!("+++ exited with {} +++", exit_code);
+                    return exit_code;
+                }
+                Ok(Status::Signaled(received)) => {
+                    eprintln!("+++ killed by {} +++", received);
+                    return 128 + received as i32;
+                }
+                Err(e) => {
+                    eprintln!("Error tracing the program: {}", e);
+                    return 1;
+                }
+            }
+        }
+    }
+    fn set_completion_variables(&mut self, variables: Vec<String>) {
+        if let Some(helper) = self.readline.helper_mut() {
+            helper.set_variables(variables);
+        }
+    }
+    /// Prints the command list, or the documentation for one command.
+    fn print_help(topic: Option<String>) {
+        match topic {
+            None => {
+                println!("List of commands:\n");
+                for cmd in COMMANDS {
+                    println!("{} -- {}", cmd.name, cmd.help);
+                }
+                println!("\nType \"help\" followed by a command name for more information.");
+            }
+            Some(name) => match find_command(&name) {
+                Some(cmd) => {
+                    println!("Usage: {}", cmd.usage);
+                    println!("{}", cmd.help);
+                    if !cmd.aliases.is_empty() {
+                        println!("Aliases: {}", cmd.aliases.join(", "));
+                    }
+                }
+                None => println!("Undefined command: \"{}\". Try \"help\".", name),
+            },
+        }
+    }
+    fn signal_policy(&self, signal: Signal) -> SignalPolicy {
+        self.signal_policies
+            .get(&signal)
+            .cloned()
+            .unwrap_or_default()
+    }
+    /// handle <signal> [stop|nostop] [print|noprint] [pass|nopass]
+    fn handle_signal(&mut self, name: &str, actions: &[String]) {
+        let name = name.to_uppercase();
+        let signal = if name.starts_with("SIG") {
+            Signal::from_str(&name)
+        } else {
+            Signal::from_str(&format!("SIG{}", name))
+        };
+        let signal = match signal {
+            Ok(Signal::SIGTRAP) | Ok(Signal::SIGKILL) | Ok(Signal::SIGSTOP) => {
+                println!("{} is used by the debugger and cannot be handled.", name);
+                return;
+            }
+            Ok(signal) => signal,
+            Err(_) => {
+                println!("Unrecognized signal {}", name);
+                return;
+            }
+        };
+        let mut policy = self.signal_policy(signal);
+        for action in actions {
+            match action.as_str() {
+                // Stopping implies printing, and not printing implies not stopping
+                "stop" => {
+                    policy.stop = true;
+                    policy.print = true;
+                }
+                "nostop" => policy.stop = false,
+                "print" => policy.print = true,
+                "noprint" => {
+                    policy.print = false;
+                    policy.stop = false;
+                }
+                "pass" | "noignore" => policy.pass = true,
+                "nopass" | "ignore" => policy.pass = false,
+                _ => {
+                    println!("Unrecognized action {}", action);
+                    return;
+                }
+            }
+        }
+        self.signal_policies.insert(signal, policy);
+        let yes_no = |b: bool| if b { "Yes" } else { "No" };
+        println!("Signal        Stop\tPrint\tPass to program");
+        println!(
+            "{:<14}{}\t{}\t{}",
+            signal.as_str(),
+            yes_no(policy.stop),
+            yes_no(policy.print),
+            yes_no(policy.pass)
+        );
+    }
+    /// Runs the commands in a file, one per line, skipping blank lines and # comments. Returns
+    /// false if one of them was quit.
+    fn source(&mut self, path: &str) -> bool {
+        if self.source_depth == MAX_SOURCE_DEPTH {
+            println!(
+                "{}: source nested too deeply (more than {} files)",
+                path, MAX_SOURCE_DEPTH
+            );
+            return true;
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("Could not read {}: {}", path, e);
+                return true;
+            }
+        };
+        self.source_depth += 1;
+        let keep_going = self.source_lines(path, &contents);
+        self.source_depth -= 1;
+        keep_going
+    }
+    /// Runs the commands in contents, read from path. Returns false if one of them quit.
+    fn source_lines(&mut self, path: &str, contents: &str) -> bool {
+        let mut lines = contents.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let line = line.trim();
+            if is_comment(line) {
+                continue;
+            }
+            match DebuggerCommand::from_line(line) {
+                // The commands are on the following lines, rather than typed at a prompt
+                Some(DebuggerCommand::Commands(breakpoint)) => {
+                    let commands = lines
+                        .by_ref()
+                        .map(|(_, line)| line.trim().to_string())
+                        .take_while(|line| line != "end")
+                        .filter(|line| !is_comment(line))
+                        .collect();
+                    self.set_breakpoint_commands(breakpoint, commands);
+                }
+                Some(command) => {
+                    if !self.execute(command) || !self.run_breakpoint_commands() {
+                        return false;
+                    }
+                }
+                None => println!("{}:{}: Unrecognized command \"{}\".", path, index + 1, line),
+            }
+        }
+        true
+    }
+    /// Writes the breakpoints (as originally specified), display expressions and signal policies
+    /// as commands that can be read back with source or from a .deetinit.
+    fn save_breakpoints(&self, path: &str) -> io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        for bp in self.break_points.values() {
+            match &bp.trace {
+                Some(trace) => {
+                    write!(file, "trace {} {}", bp.spec, trace.path)?;
+                    for variable in &trace.variables {
+                        write!(file, " {}", variable)?;
+                    }
+                    writeln!(file)?;
+                }
+                None if bp.temporary => writeln!(file, "tbreak {}", bp.spec)?,
+                None => writeln!(file, "break {}", bp.spec)?,
+            }
+            if !bp.commands.is_empty() {
+                writeln!(file, "commands")?;
+                for command in &bp.commands {
+                    writeln!(file, "  {}", command)?;
+                }
+                writeln!(file, "end")?;
+            }
+        }
+        for display in self.displays.values() {
+            let command = if display.instructions {
+                "display/i"
+            } else {
+                "display"
+            };
+            writeln!(file, "{} {}", command, display.expr)?;
+        }
+        let mut policies: Vec<(&Signal, &SignalPolicy)> = self
+            .signal_policies
+            .iter()
+            .filter(|(_, policy)| **policy != SignalPolicy::default())
+            .collect();
+        policies.sort_by_key(|(signal, _)| **signal as i32);
+        for (signal, policy) in policies {
+            writeln!(
+                file,
+                "handle {} {} {} {}",
+                signal.as_str(),
+                if policy.stop { "stop" } else { "nostop" },
+                if policy.print { "print" } else { "noprint" },
+                if policy.pass { "pass" } else { "nopass" }
+            )?;
+        }
+        Ok(())
+    }
+    fn report_status(&mut self, status: Status) {
+        match status {
+            Status::Stopped(signal, instruction_ptr) => {
+                println!("Child stopped (signal {})", signal);
+                // Commands left over from the previous stop are dropped once the inferior moves on
+                self.pending_commands.clear();
+                if signal == Signal::SIGTRAP {
+                    if let Some(bp) = self
+                        .break_points
+                        .values()
+                        .find(|bp| bp.addr == Some(instruction_ptr) && !bp.commands.is_empty())
+                    {
+                        self.pending_commands.extend(bp.commands.iter().cloned());
+                    }
+                    self.delete_temporary_breakpoint(instruction_ptr);
+                }
+                self.report_location(instruction_ptr);
+            }
+            Status::SyscallEntry(number, instruction_ptr) => {
+                let call = self.format_syscall(false);
+                println!(
+                    "Catchpoint (call to syscall {}), {}",
+                    syscall::name(number),
+                    call
+                );
+                self.pending_commands.clear();
+                self.report_location(instruction_ptr);
+            }
+            Status::SyscallExit(number, instruction_ptr) => {
+                let call = self.format_syscall(true);
+                println!(
+                    "Catchpoint (returned from syscall {}), {}",
+                    syscall::name(number),
+                    call
+                );
+                self.pending_commands.clear();
+                self.report_location(instruction_ptr);
+            }
+            Status::Exited(exit_code) => {
+                println!("Child exited (status {})", exit_code);
+                self.pending_commands.clear();
+                self.inferior = None;
+                self.recorder = None;
+                self.frames.clear();
+                self.set_completion_variables(Vec::new());
+            }
+            Status::Signaled(signal) => {
+                println!("Child signaled (signal {})", signal);
+                self.pending_commands.clear();
+                self.inferior = None;
+                self.recorder = None;
+                self.frames.clear();
+                self.set_completion_variables(Vec::new());
+            }
+            Status::Continued => {}
+        }
+    }
+    /// Prints where the inferior stopped, and updates the frames, completions and displays.
+    fn report_location(&mut self, instruction_ptr: usize) {
+        if let Some(location) = self.describe_address(instruction_ptr) {
+            println!("Stopped at {}", location);
+        }
+        self.frames = match &self.inferior {
+            Some(infer) => infer
+                .unwind(&self.dwarf_data, &self.libraries)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        self.selected_frame = 0;
+        let inline_depth = self.frames.first().map_or(0, |frame| frame.inline_depth);
+        let variables = self
+            .dwarf_data
+            .variable_names(instruction_ptr, inline_depth);
+        self.set_completion_variables(variables);
+        self.show_displays();
+    }
+    /// Formats the system call the inferior is stopped at, and (on return) its result.
+    fn format_syscall(&self, returned: bool) -> String {
+        let infer = match &self.inferior {
+            Some(infer) => infer,
+            None => return String::new(),
+        };
+        match infer.get_registers() {
+            Ok(regs) if returned => format!(
+                "{} = {}",
+                syscall::format_call(infer, &regs, true),
+                syscall::format_return(&regs)
+            ),
+            Ok(regs) => syscall::format_call(infer, &regs, false),
+            Err(e) => format!("<cannot read registers: {}>", e),
+        }
+    }
+    /// Replaces the inferior with a fresh fork of checkpoint id, so that the checkpoint itself can
+    /// be restarted again later.
+    fn restart(&mut self, id: usize) {
+        let checkpoint = match self.checkpoints.get_mut(&id) {
+            Some(checkpoint) => checkpoint,
+            None => {
+                println!("No checkpoint number {}.", id);
+                return;
+            }
+        };
+        let (mut copy, signals) = match checkpoint.checkpoint() {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                println!("Error restarting checkpoint {}: {}", id, e);
+                return;
+            }
+        };
+        // The checkpoint's memory has the breakpoints of when it was taken; replace them with the
+        // current ones
+        if let Err(e) = copy.clear_breakpoints() {
+            println!("Error removing breakpoints: {}", e);
+        }
+        for bp in self.break_points.values() {
+            let addr = match bp.addr {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let result = if bp.temporary {
+                copy.one_shot_breakpoint(addr)
+            } else {
+                copy.breakpoint(&addr)
+            };
+            if let Err(e) = result {
+                println!("Error setting breakpoint at {:#x}: {}", addr, e);
+            }
+        }
+        if let Some(hook) = self.library_hook {
+            if let Err(e) = copy.one_shot_breakpoint(hook) {
+                println!("Error setting breakpoint at {:#x}: {}", hook, e);
+            }
+        }
+        let rip = match copy.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return;
+            }
+        };
+        if let Some(infer) = &mut self.inferior {
+            if let Err(e) = infer.kill() {
+                println!("Error killing process {}: {}", infer.pid(), e);
+            }
+        }
+        println!("Switching to process {}", copy.pid());
+        copy.set_trace_syscalls(!self.syscall_catches.is_empty());
+        self.inferior = Some(copy);
+        self.recorder = None;
+        self.pending_signal = None;
+        self.keep_signals(signals);
+        self.report_status(Status::Stopped(Signal::SIGTRAP, rip));
+    }
+
+    /// Handles signals that arrived while a checkpoint was forked as if the inferior had stopped
+    /// for them; the first one to pass is delivered on the next resume.
+    fn keep_signals(&mut self, signals: Vec<Signal>) {
+        for signal in signals {
+            let policy = self.signal_policy(signal);
+            if policy.print {
+                println!("Child received signal {}", signal);
+            }
+            if policy.pass && self.pending_signal.is_none() {
+                self.pending_signal = Some(signal);
+            }
+        }
+    }
+
+    fn delete_checkpoints(&mut self) {
+        for (_, mut checkpoint) in std::mem::take(&mut self.checkpoints) {
+            checkpoint.kill().ok();
+        }
+    }
+
+    /// Runs the inferior under the recorder: one instruction, or (for continue) until it reaches
+    /// a breakpoint, stops for a signal or exits.
+    fn record_execute(&mut self, single_step: bool) {
+        let (infer, recorder) = match (&mut self.inferior, &mut self.recorder) {
+            (Some(infer), Some(recorder)) => (infer, recorder),
+            _ => return,
+        };
+        let mut signal = self.pending_signal.take();
+        let status = loop {
+            let status = match recorder.step(infer, signal.take()) {
+                Ok(status) => status,
+                Err(e) => {
+                    println!("Error while recording: {}", e);
+                    return;
+                }
+            };
+            match status {
+                Status::Stopped(Signal::SIGTRAP, rip) => {
+                    if single_step || infer.has_breakpoint(rip) {
+                        break status;
+                    }
+                }
+                Status::Stopped(received, _) => {
+                    let policy = self
+                        .signal_policies
+                        .get(&received)
+                        .copied()
+                        .unwrap_or_default();
+                    let deliver = if policy.pass { Some(received) } else { None };
+                    if policy.stop || single_step {
+                        self.pending_signal = deliver;
+                        break status;
+                    }
+                    if policy.print {
+                        println!("Child received signal {}", received);
+                    }
+                    signal = deliver;
+                }
+                _ => break status,
+            }
+        };
+        self.report_status(status);
+    }
+
+    /// Undoes one recorded instruction. Returns the new instruction pointer, or None (after
+    /// saying why) if there is nothing to undo.
+    fn step_back(&mut self) -> Option<usize> {
+        let (infer, recorder) = match (&mut self.inferior, &mut self.recorder) {
+            (Some(infer), Some(recorder)) => (infer, recorder),
+            _ => {
+                println!("Target is not being recorded. Use \"record\" first.");
+                return None;
+            }
+        };
+        match recorder.step_back(infer) {
+            Ok(Some(rip)) => Some(rip),
+            Ok(None) => {
+                println!("No more reverse-execution history.");
+                None
+            }
+            Err(e) => {
+                println!("Error running backwards: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Runs backwards to the start of the previous line executed in the current frame, or to the
+    /// start of the current line if the inferior is partway through it.
+    fn reverse_next(&mut self) {
+        let (depth, previous) = match (&self.inferior, &self.recorder) {
+            (Some(_), Some(recorder)) => (
+                recorder.depth(),
+                // The last instruction run in this frame, skipping over calls made from it
+                recorder
+                    .history()
+                    .find(|&(_, step_depth)| step_depth <= recorder.depth()),
+            ),
+            _ => {
+                println!("Target is not being recorded. Use \"record\" first.");
+                return;
+            }
+        };
+        // None if that instruction was in the caller (or there isn't one), in which case stepping
+        // back over the call is as far as reverse-next goes
+        let line = previous
+            .filter(|&(_, step_depth)| step_depth == depth)
+            .map(|(rip, _)| self.source_line(rip));
+        let mut stopped_at = None;
+        while let Some((rip, step_depth)) = self.recorder.as_ref().and_then(|r| r.history().next())
+        {
+            let in_line = step_depth == depth && line.as_ref() == Some(&self.source_line(rip));
+            if step_depth <= depth && !in_line {
+                break;
+            }
+            match self.step_back() {
+                Some(rip) => stopped_at = Some(rip),
+                None => break,
+            }
+            if step_depth > depth && self.is_breakpoint(rip) {
+                self.report_status(Status::Stopped(Signal::SIGTRAP, rip));
+                return;
+            }
+        }
+        if line.is_none() {
+            stopped_at = self.step_back().or(stopped_at);
+        }
+        if let Some(rip) = stopped_at {
+            self.report_status(Status::Stopped(Signal::SIGTRAP, rip));
+        }
+    }
+
+    fn source_line(&self, addr: usize) -> Option<(String, usize)> {
+        self.dwarf_data
+            .get_line_from_addr(addr)
+            .map(|line| (line.file, line.number))
+    }
+
+    /// Whether one of the user's breakpoints (as opposed to one set by until or advance) is at
+    /// addr.
+    fn is_user_breakpoint(&self, addr: usize) -> bool {
+        self.break_points.values().any(|bp| bp.addr == Some(addr))
+    }
+
+    fn is_breakpoint(&self, addr: usize) -> bool {
+        self.inferior
+            .as_ref()
+            .map_or(false, |infer| infer.has_breakpoint(addr))
+    }
+
+    /// Makes frames[index] the selected frame and prints it, like gdb's frame command.
+    fn select_frame(&mut self, index: usize) {
+        self.selected_frame = index;
+        let frame = &self.frames[index];
+        println!(
+            "#{} {}",
+            index,
+            frame.describe(&self.dwarf_data, &self.libraries)
+        );
+        let variables = self
+            .dwarf_data
+            .variable_names(frame.lookup_ptr(), frame.inline_depth);
+        self.set_completion_variables(variables);
+    }
+
+    fn add_display(&mut self, expr: String, instructions: bool) {
+        let number = self
+            .displays
+            .keys()
+            .next_back()
+            .map_or(1, |number| number + 1);
+        let display = Display { expr, instructions };
+        if self.inferior.is_some() {
+            self.show_display(number, &display);
+        }
+        self.displays.insert(number, display);
+    }
+
+    fn show_displays(&self) {
+        if self.inferior.is_none() {
+            return;
+        }
+        for (number, display) in &self.displays {
+            self.show_display(*number, display);
+        }
+    }
+
+    /// Prints a display expression, unless it is a variable that isn't in scope in the selected
+    /// frame.
+    fn show_display(&self, number: usize, display: &Display) {
+        if display.instructions {
+            println!("{}: x/i {}", number, display.expr);
+            self.examine_instructions(1, Some(display.expr.clone()));
+            return;
+        }
+        let (infer, frame) = match (&self.inferior, self.frames.get(self.selected_frame)) {
+            (Some(infer), Some(frame)) => (infer, frame),
+            _ => return,
+        };
+        if let Some(var) =
+            self.dwarf_data
+                .get_variable(frame.lookup_ptr(), frame.inline_depth, &display.expr)
+        {
+            match self.read_variable(infer, var, frame) {
+                Ok(value) => println!("{}: {} = {}", number, display.expr, value),
+                Err(e) => println!(
+                    "{}: {} = <cannot access memory: {}>",
+                    number, display.expr, e
+                ),
+            }
+        }
+    }
+
+    /// Reads a variable's value out of the inferior, resolving locals relative to frame.
+    fn read_variable(
+        &self,
+        infer: &Inferior,
+        var: &Variable,
+        frame: &Frame,
+    ) -> Result<String, nix::Error> {
+        let addr = match var.location.address(frame.base_ptr, frame.stack_ptr) {
+            Some(addr) => addr,
+            None => return Ok("<optimized out>".to_string()),
+        };
+        let bytes = infer.read_memory(addr, var.entity_type.size)?;
+        Ok(value::Formatter::new(&self.dwarf_data, infer).format(&var.entity_type, &bytes))
+    }
+
+    fn print_variable(&self, name: &str) {
+        let (infer, frame) = match (&self.inferior, self.frames.get(self.selected_frame)) {
+            (Some(infer), Some(frame)) => (infer, frame),
+            _ => {
+                println!("Run the program first!");
+                return;
+            }
+        };
+        match self
+            .dwarf_data
+            .get_variable(frame.lookup_ptr(), frame.inline_depth, name)
+        {
+            Some(var) => match self.read_variable(infer, var, frame) {
+                Ok(value) => println!("{} = {}", name, value),
+                Err(e) => println!("Cannot access memory for {}: {}", name, e),
+            },
+            None => println!("No symbol \"{}\" in current context.", name),
+        }
+    }
+
+    /// Prints a variable, or calls a function in the inferior and prints what it returns.
+    fn print_expression(&mut self, text: &str) {
+        let (name, args) = match expr::parse(text) {
+            Ok(Expr::Call(name, args)) => (name, args),
+            Err(e) if text.contains('(') => {
+                println!("{}", e);
+                return;
+            }
+            _ => return self.print_variable(text),
+        };
+        match self.call_function(&name, &args) {
+            Ok((_, Some(value))) => println!("{} = {}", text, value),
+            Ok((_, None)) => {}
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    /// Calls the named function in the inferior, in the executable or a shared library. Returns
+    /// what it returned as an integer (to pass on to another call), and formatted by its return
+    /// type (None if it returns nothing).
+    fn call_function(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+    ) -> Result<(u64, Option<String>), String> {
+        if self.inferior.is_none() || self.frames.is_empty() {
+            return Err("You can't do that without a process to debug.".to_string());
+        }
+        if self.recorder.is_some() {
+            return Err("Calling functions is not supported while recording.".to_string());
+        }
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.evaluate_argument(arg)?);
+        }
+        if values.len() > ARGUMENT_REGISTERS {
+            return Err(format!(
+                "Too many arguments in function call: at most {} are supported.",
+                ARGUMENT_REGISTERS
+            ));
+        }
+        let library = match self.dwarf_data.get_addr_for_function(None, name) {
+            Some(_) => None,
+            None => Some(
+                self.libraries
+                    .iter()
+                    .position(|lib| lib.function_address(name).is_some())
+                    .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?,
+            ),
+        };
+        let (addr, return_type) = self.callee(name, library);
+        if let Some(return_type) = &return_type {
+            let resolved = value::resolve_alias(self.callee_debug_data(library), return_type);
+            match resolved.kind {
+                TypeKind::Base | TypeKind::Pointer(_) if resolved.size <= 8 => {}
+                _ => {
+                    return Err(format!(
+                        "Calling functions that return {} is not supported.",
+                        return_type.name
+                    ))
+                }
+            }
+        }
+        // The function returns to the program's entry point, which has long since run. That is
+        // found in the auxiliary vector, as a position-independent executable isn't mapped at
+        // the addresses in its symbol table.
+        let infer = self.inferior.as_ref().unwrap();
+        let entry_point = if infer.is_remote() {
+            None
+        } else {
+            solib::entry_point(infer.pid())
+        };
+        let return_addr = entry_point
+            .or_else(|| self.dwarf_data.get_addr_for_symbol("_start"))
+            .ok_or("Can't call functions: _start isn't in the program's symbol table.")?;
+
+        let call = self
+            .inferior
+            .as_mut()
+            .unwrap()
+            .start_call(addr, &values, return_addr)
+            .map_err(|e| format!("Error calling {}: {}", name, e))?;
+        // Signals are handled as when continuing, and the library hook stepped past; the call is
+        // abandoned only at the user's breakpoints and on signals set to stop the program
+        let status = loop {
+            match self.wait_for_stop() {
+                Status::SyscallEntry(..) | Status::SyscallExit(..) => {
+                    let infer = self.inferior.as_ref().unwrap();
+                    infer.goon(None).expect("error continuing the inferior");
+                }
+                status => break status,
+            }
+        };
+        let outcome = self
+            .inferior
+            .as_mut()
+            .unwrap()
+            .finish_call(call, status)
+            .map_err(|e| format!("Error calling {}: {}", name, e))?;
+        let (rax, xmm0) = match outcome {
+            CallOutcome::Returned(rax, xmm0) => (rax, xmm0),
+            CallOutcome::Abandoned(status) => return Err(self.abandon_call(name, status)),
+        };
+        let return_type = match return_type {
+            Some(return_type) => return_type,
+            None => return Ok((rax, None)),
+        };
+        let dwarf_data = self.callee_debug_data(library);
+        let resolved = value::resolve_alias(dwarf_data, &return_type);
+        let register = if value::is_float(resolved) { xmm0 } else { rax };
+        let bytes = &register.to_le_bytes()[..resolved.size];
+        let infer = self.inferior.as_ref().unwrap();
+        Ok((
+            value::register_value(resolved, bytes),
+            Some(value::Formatter::new(dwarf_data, infer).format(&return_type, bytes)),
+        ))
+    }
+
+    /// Returns the address of the named function to call, in the executable or (with library)
+    /// the library at that index, and what it returns. Functions without debugging information
+    /// are taken to return int, as in C.
+    fn callee(&self, name: &str, library: Option<usize>) -> (usize, Option<Type>) {
+        let (addr, bias) = match library {
+            Some(index) => {
+                let lib = &self.libraries[index];
+                (lib.function_address(name).unwrap(), lib.bias)
+            }
+            None => (
+                self.dwarf_data.get_addr_for_function(None, name).unwrap(),
+                0,
+            ),
+        };
+        let return_type = match self.callee_debug_data(library).get_function_at(addr - bias) {
+            Some(func) if func.address == addr - bias => func.return_type.clone(),
+            _ => Some(Type::new("int".to_string(), 4)),
+        };
+        (addr, return_type)
+    }
+
+    fn callee_debug_data(&self, library: Option<usize>) -> &DwarfData {
+        match library {
+            Some(index) => &self.libraries[index].debug_data,
+            None => &self.dwarf_data,
+        }
+    }
+
+    /// Evaluates an argument of a function call to the integer passed in its register.
+    fn evaluate_argument(&mut self, arg: &Expr) -> Result<u64, String> {
+        let name = match arg {
+            Expr::Int(value) => return Ok(*value as u64),
+            Expr::Call(name, args) => {
+                return self.call_function(name, args).map(|(value, _)| value)
+            }
+            Expr::Variable(name) | Expr::AddressOf(name) => name,
+        };
+        let infer = self.inferior.as_ref().unwrap();
+        let frame = &self.frames[self.selected_frame];
+        let var = self
+            .dwarf_data
+            .get_variable(frame.lookup_ptr(), frame.inline_depth, name)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+        let addr = var
+            .location
+            .address(frame.base_ptr, frame.stack_ptr)
+            .ok_or_else(|| format!("Value of \"{}\" has been optimized out.", name))?;
+        if let Expr::AddressOf(_) = arg {
+            return Ok(addr as u64);
+        }
+        let resolved = value::resolve_alias(&self.dwarf_data, &var.entity_type);
+        match resolved.kind {
+            TypeKind::Base | TypeKind::Pointer(_)
+                if resolved.size <= 8 && !value::is_float(resolved) => {}
+            _ => {
+                return Err(format!(
+                    "Passing {} (of type {}) is not supported.",
+                    name, var.entity_type.name
+                ))
+            }
+        }
+        let bytes = infer
+            .read_memory(addr, resolved.size)
+            .map_err(|e| format!("Cannot access memory for {}: {}", name, e))?;
+        Ok(value::register_value(resolved, &bytes))
+    }
+
+    /// Reports that the inferior stopped (or exited) during a call to the named function, which
+    /// has been abandoned, returning the message.
+    fn abandon_call(&mut self, name: &str, status: Status) -> String {
+        let (signal, addr) = match status {
+            Status::Stopped(signal, addr) => (signal, addr),
+            status => {
+                self.report_status(status);
+                return format!(
+                    "The program being debugged exited while in a function called from deet ({}).",
+                    name
+                );
+            }
+        };
+        let reason = if signal == Signal::SIGTRAP && self.is_user_breakpoint(addr) {
+            "at a breakpoint".to_string()
+        } else {
+            format!("by signal {}", signal)
+        };
+        format!(
+            "The program being debugged was stopped {} at {} while in a function called from \
+             deet.\nThe call to {} was abandoned and the registers restored.",
+            reason,
+            self.describe_address(addr)
+                .unwrap_or_else(|| format!("{:#x}", addr)),
+            name
+        )
+    }
+
+    /// Prints the arguments (for info args) or the locals (for info locals) of the selected
+    /// frame.
+    fn print_frame_variables(&self, args: bool) {
+        let (infer, frame) = match (&self.inferior, self.frames.get(self.selected_frame)) {
+            (Some(infer), Some(frame)) => (infer, frame),
+            _ => {
+                println!("No frame selected.");
+                return;
+            }
+        };
+        if self
+            .dwarf_data
+            .get_function_at(frame.lookup_ptr())
+            .is_none()
+        {
+            println!("No symbol table info available.");
+            return;
+        }
+        let vars =
+            self.dwarf_data
+                .get_local_variables(frame.lookup_ptr(), frame.inline_depth, args);
+        if vars.is_empty() {
+            println!("{}", if args { "No arguments." } else { "No locals." });
+        }
+        for var in vars {
+            match self.read_variable(infer, var, frame) {
+                Ok(value) => println!("{} = {}", var.name, value),
+                Err(e) => println!("{} = <cannot access memory: {}>", var.name, e),
+            }
+        }
+    }
+
+    /// Lists the functions with code whose names match regex, by source file, then the ones only
+    /// in the symbol table.
+    fn info_functions(&self, regex: Option<String>) {
+        let regex = match compile_regex("functions", &regex) {
+            Some(regex) => regex,
+            None => return,
+        };
+        for (file, _) in self.dwarf_data.units() {
+            let mut functions: Vec<(&str, usize, String)> = file
+                .functions
+                .iter()
+                .filter(|func| func.address != 0 && regex.is_match(&func.qualified_name))
+                .map(|func| {
+                    let signature = if func.qualified_name.contains('(') {
+                        func.qualified_name.clone()
+                    } else {
+                        let types: Vec<&str> = func
+                            .parameters
+                            .iter()
+                            .map(|param| param.entity_type.name.as_str())
+                            .collect();
+                        format!("{}({})", func.qualified_name, types.join(", "))
+                    };
+                    (func.qualified_name.as_str(), func.line_number, signature)
+                })
+                .collect();
+            functions.sort();
+            functions.dedup();
+            if !functions.is_empty() {
+                println!("\nFile {}:", file.name);
+            }
+            for (_, line_number, signature) in functions {
+                println!("{}:\t{};", line_number, signature);
+            }
+        }
+        let symbols: Vec<_> = self
+            .dwarf_data
+            .symbols()
+            .iter()
+            .filter(|sym| {
+                regex.is_match(&sym.name)
+                    && self
+                        .dwarf_data
+                        .get_function_at(sym.address)
+                        .map_or(true, |func| func.address != sym.address)
+            })
+            .collect();
+        if !symbols.is_empty() {
+            println!("\nNon-debugging symbols:");
+        }
+        for sym in symbols {
+            println!("{:#018x}  {}", sym.address, sym.name);
+        }
+    }
+
+    /// Lists the global variables whose names match regex, by source file.
+    fn info_variables(&self, regex: Option<String>) {
+        let regex = match compile_regex("variables", &regex) {
+            Some(regex) => regex,
+            None => return,
+        };
+        for (file, _) in self.dwarf_data.units() {
+            let mut variables: Vec<(&str, usize, &str)> = file
+                .global_variables
+                .iter()
+                .filter(|var| regex.is_match(&var.name))
+                .map(|var| {
+                    (
+                        var.name.as_str(),
+                        var.line_number,
+                        var.entity_type.name.as_str(),
+                    )
+                })
+                .collect();
+            variables.sort();
+            variables.dedup();
+            if !variables.is_empty() {
+                println!("\nFile {}:", file.name);
+            }
+            for (name, line_number, type_name) in variables {
+                println!("{}:\t{} {};", line_number, type_name, name);
+            }
+        }
+    }
+
+    /// Lists the types whose names match regex, by source file. Pointer and array types, and
+    /// const or volatile qualified ones, are left out.
+    fn info_types(&self, regex: Option<String>) {
+        let regex = match compile_regex("types", &regex) {
+            Some(regex) => regex,
+            None => return,
+        };
+        for (file, types) in self.dwarf_data.units() {
+            let mut names: Vec<&str> = types
+                .values()
+                .filter(|ty| match ty.kind {
+                    TypeKind::Pointer(_) | TypeKind::Array { .. } => false,
+                    _ => true,
+                })
+                .map(|ty| ty.name.as_str())
+                .filter(|name| {
+                    !name.is_empty()
+                        && !name.starts_with("const ")
+                        && !name.starts_with("volatile ")
+                        && regex.is_match(name)
+                })
+                .collect();
+            names.sort();
+            names.dedup();
+            if !names.is_empty() {
+                println!("\nFile {}:", file.name);
+            }
+            for name in names {
+                println!("\t{}", name);
+            }
+        }
+    }
+
+    /// Describes the code a line compiled to. spec is a line number, a function or *<address>;
+    /// without one, the line the selected frame is at is described.
+    fn info_line(&self, spec: Option<String>) {
+        let (addr, line_number) = match &spec {
+            Some(spec) if spec.starts_with('*') => match self.parse_address(&spec[1..]) {
+                Some(addr) => (addr, None),
+                None => {
+                    println!("Invalid address {}", &spec[1..]);
+                    return;
+                }
+            },
+            Some(spec) => match spec.parse::<usize>() {
+                Ok(line_number) => match self.dwarf_data.get_addr_for_line(None, line_number) {
+                    Some(addr) => (addr, Some(line_number)),
+                    None => {
+                        println!("Line {} is out of range.", line_number);
+                        return;
+                    }
+                },
+                Err(_) => match self.dwarf_data.get_addr_for_function(None, spec) {
+                    Some(addr) => (addr, None),
+                    None => {
+                        println!("Function \"{}\" not defined.", spec);
+                        return;
+                    }
+                },
+            },
+            None => match self.frames.get(self.selected_frame) {
+                Some(frame) => (frame.lookup_ptr(), None),
+                None => {
+                    println!("No line number information available.");
+                    return;
+                }
+            },
+        };
+        match self.dwarf_data.get_line_range(addr) {
+            Some((line, start, end)) => match line_number {
+                Some(line_number) if line_number != line.number => println!(
+                    "Line {} of \"{}\" is at address {:#x} {} but contains no code.",
+                    line_number,
+                    line.file,
+                    start,
+                    self.format_symbol(start)
+                ),
+                _ => println!(
+                    "Line {} of \"{}\" starts at address {:#x} {} and ends at {:#x} {}.",
+                    line.number,
+                    line.file,
+                    start,
+                    self.format_symbol(start),
+                    end,
+                    self.format_symbol(end)
+                ),
+            },
+            // The unit's own line table doesn't cover addr (e.g. code from a header file), but
+            // addr2line may still know the line
+            None => match self.dwarf_data.get_line_from_addr(addr) {
+                Some(line) => println!(
+                    "Line {} of \"{}\" is at address {:#x} {}.",
+                    line.number,
+                    line.file,
+                    addr,
+                    self.format_symbol(addr)
+                ),
+                None => println!(
+                    "No line number information available for address {:#x} {}",
+                    addr,
+                    self.format_symbol(addr)
+                ),
+            },
+        }
+    }
+
+    /// Says where a variable (in scope in the selected frame, or global) or function is stored.
+    fn info_address(&self, name: &str) {
+        let var = match (&self.inferior, self.frames.get(self.selected_frame)) {
+            (Some(_), Some(frame)) => {
+                self.dwarf_data
+                    .get_variable(frame.lookup_ptr(), frame.inline_depth, name)
+            }
+            _ => self.dwarf_data.get_global_variable(name),
+        };
+        if let Some(var) = var {
+            match var.location {
+                Location::Address(addr) => {
+                    println!(
+                        "Symbol \"{}\" is static storage at address {:#x}.",
+                        name, addr
+                    )
+                }
+                Location::FramePointerOffset(offset) => println!(
+                    "Symbol \"{}\" is a variable at frame base reg $rbp offset 16+{}.",
+                    name, offset
+                ),
+                Location::BasePointerOffset(offset) => println!(
+                    "Symbol \"{}\" is a variable at frame base reg $rbp offset {}.",
+                    name, offset
+                ),
+                Location::StackPointerOffset(offset) => println!(
+                    "Symbol \"{}\" is a variable at frame base reg $rsp offset {}.",
+                    name, offset
+                ),
+                Location::OptimizedOut => println!("Symbol \"{}\" is optimized out.", name),
+            }
+            return;
+        }
+        match self.dwarf_data.get_addr_for_function(None, name) {
+            Some(addr) => println!("Symbol \"{}\" is a function at address {:#x}.", name, addr),
+            None => println!("No symbol \"{}\" in current context.", name),
+        }
+    }
+
+    /// Describes the selected frame: where it is on the stack, where it was called from, and
+    /// where its arguments, locals and saved registers are. Frames are assumed to keep rbp as a
+    /// frame pointer, as unwinding does.
+    fn info_frame(&self) {
+        let (infer, frame) = match (&self.inferior, self.frames.get(self.selected_frame)) {
+            (Some(infer), Some(frame)) => (infer, frame),
+            _ => {
+                println!("No stack.");
+                return;
+            }
+        };
+        let index = self.selected_frame;
+        // The canonical frame address: rsp before the call, above the return address and the
+        // saved rbp
+        let frame_address = |frame: &Frame| frame.base_ptr + 16;
+        let cfa = frame_address(frame);
+        println!("Stack level {}, frame at {:#x}:", index, cfa);
+        let saved_rip = if frame.inline_depth > 0 {
+            format!("inlined into frame {}", index + 1)
+        } else {
+            match infer.read_memory(cfa - 8, 8) {
+                Ok(bytes) if bytes.len() == 8 => {
+                    let mut word = [0; 8];
+                    word.copy_from_slice(&bytes);
+                    format!("saved rip = {:#x}", u64::from_le_bytes(word))
+                }
+                _ => "saved rip = <unavailable>".to_string(),
+            }
+        };
+        println!(
+            " rip = {:#x} in {}; {}",
+            frame.instruction_ptr,
+            frame.describe(&self.dwarf_data, &self.libraries),
+            saved_rip
+        );
+        if let Some(caller) = self.frames.get(index + 1) {
+            println!(" called by frame at {:#x}", frame_address(caller));
+        }
+        if index > 0 {
+            println!(
+                " caller of frame at {:#x}",
+                frame_address(&self.frames[index - 1])
+            );
+        }
+        let args: Vec<String> = self
+            .dwarf_data
+            .get_local_variables(frame.lookup_ptr(), frame.inline_depth, true)
+            .into_iter()
+            .map(|var| match self.read_variable(infer, var, frame) {
+                Ok(value) => format!("{}={}", var.name, value),
+                Err(_) => format!("{}=<unavailable>", var.name),
+            })
+            .collect();
+        println!(
+            " Arglist at {:#x}, args: {}",
+            frame.base_ptr,
+            args.join(", ")
+        );
+        println!(
+            " Locals at {:#x}, Previous frame's sp is {:#x}",
+            frame.base_ptr, cfa
+        );
+        println!(" Saved registers:");
+        println!("  rbp at {:#x}, rip at {:#x}", cfa - 16, cfa - 8);
+    }
+
+    /// Redraws the TUI panes (if enabled) to reflect the inferior's current state.
+    fn refresh_tui(&mut self) {
+        let tui = match &mut self.tui {
+            Some(tui) => tui,
+            None => return,
+        };
+        let dwarf_data = &self.dwarf_data;
+        let libraries = &self.libraries;
+        let breakpoint_lines = self
+            .break_points
+            .values()
+            .filter_map(|bp| dwarf_data.get_line_from_addr(bp.addr?))
+            .collect();
+        let view = match &self.inferior {
+            Some(infer) => {
+                let registers = infer.get_registers().ok();
+                // Follow the selected frame, so that up and down move through the source
+                let line = match self.frames.get(self.selected_frame) {
+                    Some(frame) => frame.line.clone(),
+                    None => {
+                        registers.and_then(|regs| dwarf_data.get_line_from_addr(regs.rip as usize))
+                    }
+                };
+                View {
+                    line,
+                    breakpoint_lines,
+                    registers,
+                    backtrace: infer
+                        .unwind(dwarf_data, libraries)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|frame| frame.describe(dwarf_data, libraries))
+                        .collect(),
+                }
+            }
+            // Show where the program will start
+            None => View {
+                line: dwarf_data
+                    .get_addr_for_function(None, "main")
+                    .and_then(|addr| dwarf_data.get_line_from_addr(addr)),
+                breakpoint_lines,
+                registers: None,
+                backtrace: Vec::new(),
+            },
+        };
+        if let Err(e) = tui.render(&view) {
+            println!("Warning: failed to draw the TUI: {}", e);
+        }
+    }
+    /// Resolves an address expression: $pc, a function name or a hex address.
+    fn parse_expr_addr(&self, expr: &str, rip: usize) -> Option<usize> {
+        match expr {
+            "$pc" | "$rip" => Some(rip),
+            _ => self
+                .dwarf_data
+                .get_addr_for_function(None, expr)
+                .or_else(|| self.parse_address(expr.trim_start_matches('*'))),
+        }
+    }
+    fn disassemble(&self, raw: bool, arg: Option<String>) {
+        let infer = match &self.inferior {
+            Some(infer) => infer,
+            None => {
+                println!("Run the program first!");
+                return;
+            }
+        };
+        let rip = match infer.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return;
+            }
+        };
+        let range = match &arg {
+            None => self.dwarf_data.get_function_range_from_addr(rip),
+            Some(arg) => match arg.find(',') {
+                Some(comma) => self
+                    .parse_expr_addr(&arg[..comma], rip)
+                    .and_then(|start| Some((start, self.parse_expr_addr(&arg[comma + 1..], rip)?))),
+                None => self.dwarf_data.get_function_range(arg).or_else(|| {
+                    self.dwarf_data
+                        .get_function_range_from_addr(self.parse_expr_addr(arg, rip)?)
+                }),
+            },
+        };
+        let (start, end) = match range {
+            Some((start, end)) if start < end => (start, end),
+            _ => {
+                println!("No function contains the specified address.");
+                return;
+            }
+        };
+        let code = match infer.read_memory(start, end - start) {
+            Ok(code) => code,
+            Err(e) => {
+                println!("Cannot access memory at {:#x}: {}", start, e);
+                return;
+            }
+        };
+        match self.dwarf_data.get_symbol_from_addr(start) {
+            Some((name, 0)) => println!("Dump of assembler code for function {}:", name),
+            _ => println!("Dump of assembler code from {:#x} to {:#x}:", start, end),
+        }
+        self.print_instructions(&disassembler::decode(&code, start, usize::MAX), rip, raw);
+        println!("End of assembler dump.");
+    }
+    fn examine_instructions(&self, count: usize, expr: Option<String>) {
+        let infer = match &self.inferior {
+            Some(infer) => infer,
+            None => {
+                println!("Run the program first!");
+                return;
+            }
+        };
+        let rip = match infer.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(e) => {
+                println!("Error reading registers: {}", e);
+                return;
+            }
+        };
+        let addr = match expr {
+            Some(expr) => match self.parse_expr_addr(&expr, rip) {
+                Some(addr) => addr,
+                None => {
+                    println!("Invalid address {}", expr);
+                    return;
+                }
+            },
+            None => rip,
+        };
+        match infer.read_memory(addr, count * disassembler::MAX_INSTRUCTION_LEN) {
+            Ok(code) => {
+                self.print_instructions(&disassembler::decode(&code, addr, count), rip, false)
+            }
+            Err(e) => println!("Cannot access memory at {:#x}: {}", addr, e),
+        }
+    }
+    /// Prints instructions gdb-style, marking the one at rip with "=>" and printing the source
+    /// line whenever it changes. With raw, each instruction's bytes come before it, as with
+    /// gdb's /r.
+    fn print_instructions(&self, instructions: &[DecodedInstruction], rip: usize, raw: bool) {
+        let mut last_line = None;
+        for instruction in instructions {
+            if let Some(line) = self.dwarf_data.get_line_from_addr(instruction.address) {
+                let location = line.to_string();
+                if last_line.as_ref() != Some(&location) {
+                    println!("{}", location);
+                    last_line = Some(location);
+                }
+            }
+            let marker = if instruction.address == rip {
+                "=>"
+            } else {
+                "  "
+            };
+            let target = match instruction.branch_target {
+                Some(target) => format!(" {}", self.format_symbol(target)),
+                None => String::new(),
+            };
+            let bytes = if raw {
+                let hex: Vec<String> = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("{}\t", hex.join(" "))
+            } else {
+                String::new()
+            };
+            println!(
+                "{} {:#x} {}:\t{}{}{}",
+                marker,
+                instruction.address,
+                self.format_symbol(instruction.address),
+                bytes,
+                instruction.text,
+                target
+            );
+        }
+    }
+    /// Formats addr as <func+offset> for disassembly listings.
+    fn format_symbol(&self, addr: usize) -> String {
+        match self.dwarf_data.get_symbol_from_addr(addr) {
+            Some((name, offset)) => format!("<{}+{}>", name, offset),
+            None => String::new(),
+        }
+    }
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            self.poll_background();
+            self.refresh_tui();
+            // Print prompt and get next line of user input
+            match self.readline.readline("(deet) ") {
+                Err(ReadlineError::Interrupted) if self.running => {
+                    // User pressed ctrl+c with the program running in the background: stop it
+                    return DebuggerCommand::Interrupt;
+                }
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return DebuggerCommand::Quit;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() == 0 {
+                        continue;
+                    }
+                    self.readline.add_history_entry(line.as_str());
+                    if let Err(err) = self.readline.save_history(&self.history_path) {
+                        println!(
+                            "Warning: failed to save history file at {}: {}",
+                            self.history_path, err
+                        );
+                    }
+                    if let Some(cmd) = DebuggerCommand::from_line(&line) {
+                        return cmd;
+                    } else {
+                        println!("Unrecognized command.");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Compiles the regular expression given to info functions, variables or types, printing the
+/// heading of the listing. An invalid regular expression is reported and None returned.
+fn compile_regex(what: &str, regex: &Option<String>) -> Option<Regex> {
+    match regex {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(regex) => {
+                println!("All {} matching regular expression \"{}\":", what, pattern);
+                Some(regex)
+            }
+            Err(e) => {
+                println!("Invalid regular expression \"{}\": {}", pattern, e);
+                None
+            }
+        },
+        None => {
+            println!("All defined {}:", what);
+            Some(Regex::new("").unwrap())
+        }
+    }
+}
+
+/// Whether command can be run while the inferior runs in the background: it doesn't need the
+/// inferior stopped, or (like run and quit) gets rid of it.
+fn runs_in_background(command: &DebuggerCommand) -> bool {
+    matches!(
+        command,
+        DebuggerCommand::Interrupt
+            | DebuggerCommand::Help(_)
+            | DebuggerCommand::Quit
+            | DebuggerCommand::Run(_)
+            | DebuggerCommand::TargetRemote(_)
+            | DebuggerCommand::SetArgs(_)
+            | DebuggerCommand::SetEnvironment(_, _)
+            | DebuggerCommand::UnsetEnvironment(_)
+            | DebuggerCommand::Tty(_)
+            | DebuggerCommand::Handle(_, _)
+            | DebuggerCommand::Source(_)
+    )
+}
+
+/// Whether a line of a sourced file or a commands block is blank or a # comment, and so has
+/// nothing to run.
+fn is_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
 }
@@ -1,22 +1,415 @@
 pub enum DebuggerCommand {
     Quit,
-    Run(Vec<String>),
+    /// None reuses the arguments of the previous run (or set args)
+    Run(Option<ProgramArgs>),
     Continue,
+    /// continue &: resume the program and return to the prompt while it runs
+    ContinueBackground,
+    Interrupt,
     BackTrace,
     Break(String),
+    TemporaryBreak(String),
+    /// A location, the file to log to and the variables to log
+    Trace(String, String, Vec<String>),
+    /// None means the most recently set breakpoint
+    Commands(Option<usize>),
+    /// None steps to the next line instead of running to a location
+    Until(Option<String>),
+    Advance(String),
+    InfoSymbol(String),
+    /// Regular expressions the names listed must match; None lists everything
+    InfoFunctions(Option<String>),
+    InfoVariables(Option<String>),
+    InfoTypes(Option<String>),
+    /// None describes the line the selected frame is at
+    InfoLine(Option<String>),
+    InfoAddress(String),
+    InfoFrame,
+    StepInstruction,
+    /// With true (disassemble /r), the raw bytes of each instruction are shown too
+    Disassemble(bool, Option<String>),
+    ExamineInstructions(usize, Option<String>),
+    /// None shows every display expression again
+    Display(Option<String>),
+    DisplayInstructions(String),
+    /// None deletes every display expression
+    Undisplay(Option<usize>),
+    InfoDisplay,
+    SaveBreakpoints(String),
+    Source(String),
+    Handle(String, Vec<String>),
+    Help(Option<String>),
+    Print(String),
+    /// A function call expression (see expr)
+    Call(String),
+    InfoLocals,
+    InfoArgs,
+    Up(usize),
+    Down(usize),
+    Frame(Option<usize>),
+    SetArgs(ProgramArgs),
+    SetEnvironment(String, String),
+    /// None clears the whole environment
+    UnsetEnvironment(Option<String>),
+    Tty(String),
+    Record,
+    RecordStop,
+    InfoRecord,
+    ReverseStepInstruction,
+    ReverseNext,
+    ReverseContinue,
+    Checkpoint,
+    Restart(usize),
+    InfoCheckpoints,
+    DeleteCheckpoint(usize),
+    /// System call names or numbers; none catches every system call
+    CatchSyscall(Vec<String>),
+    DeleteCatchpoints,
+    /// The host:port of a GDB remote protocol stub
+    TargetRemote(String),
+}
+
+/// The program's arguments and I/O redirections, as given to run or set args.
+#[derive(Clone, Default)]
+pub struct ProgramArgs {
+    pub args: Vec<String>,
+    pub stdin: Option<String>,
+    pub stdout: Option<String>,
+    /// Whether stdout was redirected with >> rather than >
+    pub append: bool,
+}
+
+/// Documentation for a command, used by help and tab completion.
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "advance",
+        aliases: &[],
+        usage: "advance <function>|<line>|*<address>",
+        help: "Continue until a location is reached, or the current function returns.",
+    },
+    CommandInfo {
+        name: "backtrace",
+        aliases: &["bt", "back"],
+        usage: "backtrace",
+        help: "Print the call stack of the stopped program.",
+    },
+    CommandInfo {
+        name: "break",
+        aliases: &["b"],
+        usage: "break <function>|<line>|*<address>",
+        help: "Set a breakpoint at a function, a line of the main source file, or an address.",
+    },
+    CommandInfo {
+        name: "call",
+        aliases: &[],
+        usage: "call <function>(<arguments>...)",
+        help: "Call a function in the program and print what it returns. Arguments can be \
+               integers, characters, variables, addresses of variables (&x) and further calls. \
+               If the program stops in the function (at a breakpoint or on a signal), the call \
+               is abandoned and the registers are restored.",
+    },
+    CommandInfo {
+        name: "catch",
+        aliases: &[],
+        usage: "catch syscall [<name>|<number>...]",
+        help: "Stop when the program enters or returns from the given system calls (by default, \
+               any system call), showing their arguments and return values.",
+    },
+    CommandInfo {
+        name: "checkpoint",
+        aliases: &[],
+        usage: "checkpoint",
+        help: "Save a snapshot of the stopped program (as a forked copy) to restart from later.",
+    },
+    CommandInfo {
+        name: "commands",
+        aliases: &[],
+        usage: "commands [<n>]",
+        help: "Give breakpoint n (by default the last one set) commands to run when it is hit, \
+               one per line, ending with a line saying just \"end\". They can end with continue.",
+    },
+    CommandInfo {
+        name: "continue",
+        aliases: &["c", "cont"],
+        usage: "continue [&]",
+        help: "Continue running the stopped program. With &, the program runs in the background \
+               and deet goes back to the prompt; where it stops is reported at the next command, \
+               or the interrupt command (or Ctrl+C) stops it. Ctrl+C also stops a program \
+               running in the foreground.",
+    },
+    CommandInfo {
+        name: "delete",
+        aliases: &[],
+        usage: "delete checkpoint <n> | delete catchpoints",
+        help: "Delete a checkpoint, killing its copy of the program, or stop catching system \
+               calls.",
+    },
+    CommandInfo {
+        name: "disassemble",
+        aliases: &["disas"],
+        usage: "disassemble [/r] [<function>|<start>,<end>]",
+        help: "Disassemble a function (by default the current one) or an address range. /r shows \
+               each instruction's bytes in hex as well.",
+    },
+    CommandInfo {
+        name: "display",
+        aliases: &[],
+        usage: "display [<variable>] | display/i <address>",
+        help: "Print a variable (or, with /i, the instruction at an address such as $pc) every \
+               time the program stops, when it is in scope. Without arguments, print them all now.",
+    },
+    CommandInfo {
+        name: "down",
+        aliases: &[],
+        usage: "down [<n>]",
+        help: "Select the frame n levels down (towards the innermost frame).",
+    },
+    CommandInfo {
+        name: "frame",
+        aliases: &["f"],
+        usage: "frame [<n>]",
+        help: "Select frame n of the backtrace, or describe the selected frame.",
+    },
+    CommandInfo {
+        name: "handle",
+        aliases: &[],
+        usage: "handle <signal> [stop|nostop] [print|noprint] [pass|nopass]",
+        help: "Set whether a signal stops the program, is reported, and is passed on to it.",
+    },
+    CommandInfo {
+        name: "help",
+        aliases: &["h"],
+        usage: "help [<command>]",
+        help: "List all commands, or describe one.",
+    },
+    CommandInfo {
+        name: "info",
+        aliases: &[],
+        usage: "info functions|variables|types [<regex>] | info line [<location>] | \
+                info address <name> | info symbol <address> | info frame | info locals | \
+                info args | info record | info checkpoints | info display",
+        help: "Show the functions, global variables or types whose names match a regular \
+               expression, the code a source line compiled to, where a variable or function is \
+               stored, which function an address belongs to, a description of the selected \
+               frame, its local variables or arguments, how much execution has been recorded, \
+               the checkpoints, or the display expressions.",
+    },
+    CommandInfo {
+        name: "interrupt",
+        aliases: &[],
+        usage: "interrupt",
+        help: "Stop the program running in the background (see continue &).",
+    },
+    CommandInfo {
+        name: "print",
+        aliases: &["p"],
+        usage: "print <variable> | print <function>(<arguments>...)",
+        help: "Print a variable visible in the selected frame, or call a function and print \
+               what it returns (see call).",
+    },
+    CommandInfo {
+        name: "quit",
+        aliases: &["q"],
+        usage: "quit",
+        help: "Kill the program (if running) and exit deet.",
+    },
+    CommandInfo {
+        name: "record",
+        aliases: &["rec"],
+        usage: "record [stop]",
+        help: "Start recording execution so that it can be stepped backwards, or stop recording. \
+               While recording, the program runs one instruction at a time, which is slow.",
+    },
+    CommandInfo {
+        name: "restart",
+        aliases: &[],
+        usage: "restart <n>",
+        help: "Replace the program with a copy of checkpoint n. The checkpoint is kept, so it can \
+               be restarted again.",
+    },
+    CommandInfo {
+        name: "reverse-continue",
+        aliases: &["rc"],
+        usage: "reverse-continue",
+        help: "Run backwards through the recording to the previous breakpoint.",
+    },
+    CommandInfo {
+        name: "reverse-next",
+        aliases: &["rn"],
+        usage: "reverse-next",
+        help: "Run backwards to the start of the previous source line, stepping over calls.",
+    },
+    CommandInfo {
+        name: "reverse-stepi",
+        aliases: &["rsi"],
+        usage: "reverse-stepi",
+        help: "Run backwards by a single machine instruction.",
+    },
+    CommandInfo {
+        name: "run",
+        aliases: &["r"],
+        usage: "run [<args>...] [< <input>] [> <output>]",
+        help: "Start the program, restarting it if it is already running. Arguments are quoted \
+               and redirected as in a shell; without any, those of the last run are used again.",
+    },
+    CommandInfo {
+        name: "save",
+        aliases: &[],
+        usage: "save breakpoints <file>",
+        help: "Save breakpoints, displays and signal handling as commands that source can replay.",
+    },
+    CommandInfo {
+        name: "set",
+        aliases: &[],
+        usage: "set args [<args>...] | set environment <name>=<value>",
+        help: "Set the arguments or an environment variable for the next run.",
+    },
+    CommandInfo {
+        name: "source",
+        aliases: &[],
+        usage: "source <file>",
+        help: "Run the deet commands in a file. ~/.deetinit is sourced at startup, and so is \
+               .deetinit in the current directory when deet is started with --local-init.",
+    },
+    CommandInfo {
+        name: "stepi",
+        aliases: &["si"],
+        usage: "stepi",
+        help: "Execute a single machine instruction.",
+    },
+    CommandInfo {
+        name: "target",
+        aliases: &[],
+        usage: "target remote [<host>]:<port>",
+        help: "Debug a process run by gdbserver (or deet --gdbserver), e.g. in a container, over \
+               the GDB remote protocol.",
+    },
+    CommandInfo {
+        name: "tbreak",
+        aliases: &[],
+        usage: "tbreak <function>|<line>|*<address>",
+        help: "Set a temporary breakpoint, which is deleted the first time it is hit.",
+    },
+    CommandInfo {
+        name: "trace",
+        aliases: &[],
+        usage: "trace <function>|<line>|*<address> <file> [<variable>...]",
+        help: "Set a tracepoint: each time the location is reached, append the variables' values \
+               to a file and carry on without stopping.",
+    },
+    CommandInfo {
+        name: "tty",
+        aliases: &[],
+        usage: "tty <terminal>",
+        help: "Run the program on another terminal, e.g. /dev/pts/3, from the next run.",
+    },
+    CommandInfo {
+        name: "undisplay",
+        aliases: &[],
+        usage: "undisplay [<n>]",
+        help: "Stop displaying expression n, or all of them.",
+    },
+    CommandInfo {
+        name: "unset",
+        aliases: &[],
+        usage: "unset environment [<name>]",
+        help: "Remove an environment variable (or all of them) for the next run.",
+    },
+    CommandInfo {
+        name: "until",
+        aliases: &["u"],
+        usage: "until [<function>|<line>|*<address>]",
+        help: "Continue until a location is reached in the current frame, or the current function \
+               returns. Unlike advance, the location is skipped over in calls made from here. \
+               Without a location, run to the next line, stepping over calls and not stopping \
+               when a loop jumps back to an earlier line.",
+    },
+    CommandInfo {
+        name: "up",
+        aliases: &[],
+        usage: "up [<n>]",
+        help: "Select the frame n levels up (towards main).",
+    },
+    CommandInfo {
+        name: "x/i",
+        aliases: &[],
+        usage: "x/<count>i [<address>]",
+        help: "Disassemble count instructions starting at an address (by default $pc).",
+    },
+];
+
+/// Subcommands of info, for tab completion.
+pub const INFO_TOPICS: &[&str] = &[
+    "address",
+    "args",
+    "checkpoints",
+    "display",
+    "frame",
+    "functions",
+    "line",
+    "locals",
+    "record",
+    "symbol",
+    "types",
+    "variables",
+];
+
+/// Looks a command up by name (with or without its /format suffix) or alias.
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|cmd| {
+        cmd.name == name || cmd.name.split('/').next() == Some(name) || cmd.aliases.contains(&name)
+    })
 }
 
 impl DebuggerCommand {
+    /// Parses a line of input. The program arguments given to run and set args are split the way
+    /// a shell would; everything else is split on whitespace and handed to from_tokens.
+    pub fn from_line(line: &str) -> Option<DebuggerCommand> {
+        let (command, rest) = split_first_word(line.trim());
+        match command {
+            "r" | "run" if rest.is_empty() => Some(DebuggerCommand::Run(None)),
+            "r" | "run" => Some(DebuggerCommand::Run(Some(parse_program_args(rest)?))),
+            "set" => {
+                let (setting, value) = split_first_word(rest);
+                match setting {
+                    "args" => Some(DebuggerCommand::SetArgs(parse_program_args(value)?)),
+                    "environment" | "env" => {
+                        // Both "set environment NAME=value" and "set environment NAME value"
+                        let (name, value) = match value.find('=') {
+                            Some(index) => (value[..index].trim(), value[index + 1..].trim()),
+                            None => split_first_word(value),
+                        };
+                        if name.is_empty() {
+                            return None;
+                        }
+                        Some(DebuggerCommand::SetEnvironment(
+                            name.to_string(),
+                            value.to_string(),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => DebuggerCommand::from_tokens(&line.split_whitespace().collect()),
+        }
+    }
+
     pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
-        match tokens[0] {
+        match *tokens.first()? {
             "q" | "quit" => Some(DebuggerCommand::Quit),
-            "r" | "run" => {
-                let args = tokens[1..].to_vec();
-                Some(DebuggerCommand::Run(
-                    args.iter().map(|s| s.to_string()).collect(),
-                ))
-            }
-            "c" | "continue" | "cont" => Some(DebuggerCommand::Continue),
+            "c" | "continue" | "cont" => match tokens.get(1) {
+                Some(&"&") => Some(DebuggerCommand::ContinueBackground),
+                Some(_) => None,
+                None => Some(DebuggerCommand::Continue),
+            },
+            "interrupt" => Some(DebuggerCommand::Interrupt),
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::BackTrace),
             "b" | "break" => {
                 let arg = tokens[1].to_string();
@@ -24,8 +417,253 @@ impl DebuggerCommand {
 
                 //Some(DebuggerCommand::Break("s".to_string()))
             }
+            "tbreak" => Some(DebuggerCommand::TemporaryBreak(tokens.get(1)?.to_string())),
+            "trace" => Some(DebuggerCommand::Trace(
+                tokens.get(1)?.to_string(),
+                tokens.get(2)?.to_string(),
+                tokens[3..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "commands" => match tokens.get(1) {
+                Some(n) => Some(DebuggerCommand::Commands(Some(n.parse().ok()?))),
+                None => Some(DebuggerCommand::Commands(None)),
+            },
+            "u" | "until" => Some(DebuggerCommand::Until(
+                tokens.get(1).map(|spec| spec.to_string()),
+            )),
+            "advance" => Some(DebuggerCommand::Advance(tokens.get(1)?.to_string())),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "rec" | "record" => match tokens.get(1) {
+                None => Some(DebuggerCommand::Record),
+                Some(&"stop") => Some(DebuggerCommand::RecordStop),
+                _ => None,
+            },
+            "checkpoint" => Some(DebuggerCommand::Checkpoint),
+            "catch" => match tokens.get(1) {
+                Some(&"syscall") => Some(DebuggerCommand::CatchSyscall(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                _ => None,
+            },
+            "target" => match tokens.get(1) {
+                Some(&"remote") => Some(DebuggerCommand::TargetRemote(tokens.get(2)?.to_string())),
+                _ => None,
+            },
+            "restart" => Some(DebuggerCommand::Restart(tokens.get(1)?.parse().ok()?)),
+            "delete" => match tokens.get(1) {
+                Some(&"checkpoint") => Some(DebuggerCommand::DeleteCheckpoint(
+                    tokens.get(2)?.parse().ok()?,
+                )),
+                Some(&"catchpoints") => Some(DebuggerCommand::DeleteCatchpoints),
+                _ => None,
+            },
+            "rsi" | "reverse-stepi" => Some(DebuggerCommand::ReverseStepInstruction),
+            "rn" | "reverse-next" => Some(DebuggerCommand::ReverseNext),
+            "rc" | "reverse-continue" => Some(DebuggerCommand::ReverseContinue),
+            "disas" | "disassemble" => {
+                let raw = tokens.get(1) == Some(&"/r");
+                let first = if raw { 2 } else { 1 };
+                // "disassemble 0x401000, 0x401020" gets split on whitespace; glue it back together
+                let arg = tokens[first..].concat();
+                if arg.is_empty() {
+                    Some(DebuggerCommand::Disassemble(raw, None))
+                } else {
+                    Some(DebuggerCommand::Disassemble(raw, Some(arg)))
+                }
+            }
+            "display" => {
+                let arg = tokens[1..].join(" ");
+                if arg.is_empty() {
+                    Some(DebuggerCommand::Display(None))
+                } else {
+                    Some(DebuggerCommand::Display(Some(arg)))
+                }
+            }
+            "display/i" => Some(DebuggerCommand::DisplayInstructions(
+                tokens.get(1)?.to_string(),
+            )),
+            "undisplay" => match tokens.get(1) {
+                Some(n) => Some(DebuggerCommand::Undisplay(Some(n.parse().ok()?))),
+                None => Some(DebuggerCommand::Undisplay(None)),
+            },
+            x if x.starts_with("x/") && x.ends_with('i') => {
+                let count = &x[2..x.len() - 1];
+                let count = if count.is_empty() {
+                    1
+                } else {
+                    count.parse().ok().filter(|&count| count > 0)?
+                };
+                Some(DebuggerCommand::ExamineInstructions(
+                    count,
+                    tokens.get(1).map(|s| s.to_string()),
+                ))
+            }
+            "save" => match tokens.get(1) {
+                Some(&"breakpoints") => {
+                    Some(DebuggerCommand::SaveBreakpoints(tokens.get(2)?.to_string()))
+                }
+                _ => None,
+            },
+            "source" => Some(DebuggerCommand::Source(tokens.get(1)?.to_string())),
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens.get(1)?.to_string(),
+                tokens[2..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "unset" => match tokens.get(1) {
+                Some(&"environment") | Some(&"env") => Some(DebuggerCommand::UnsetEnvironment(
+                    tokens.get(2).map(|s| s.to_string()),
+                )),
+                _ => None,
+            },
+            "tty" => Some(DebuggerCommand::Tty(tokens.get(1)?.to_string())),
+            "h" | "help" => Some(DebuggerCommand::Help(tokens.get(1).map(|s| s.to_string()))),
+            "call" => Some(DebuggerCommand::Call(rest_of(tokens, 1)?)),
+            "p" | "print" => {
+                let arg = tokens[1..].join(" ");
+                if arg.is_empty() {
+                    None
+                } else {
+                    Some(DebuggerCommand::Print(arg))
+                }
+            }
+            "up" => Some(DebuggerCommand::Up(parse_count(tokens.get(1))?)),
+            "down" => Some(DebuggerCommand::Down(parse_count(tokens.get(1))?)),
+            "f" | "frame" => match tokens.get(1) {
+                Some(n) => Some(DebuggerCommand::Frame(Some(n.parse().ok()?))),
+                None => Some(DebuggerCommand::Frame(None)),
+            },
+            "info" => match tokens.get(1) {
+                Some(&"symbol") => Some(DebuggerCommand::InfoSymbol(tokens.get(2)?.to_string())),
+                Some(&"functions") => Some(DebuggerCommand::InfoFunctions(rest_of(tokens, 2))),
+                Some(&"variables") => Some(DebuggerCommand::InfoVariables(rest_of(tokens, 2))),
+                Some(&"types") => Some(DebuggerCommand::InfoTypes(rest_of(tokens, 2))),
+                Some(&"line") => Some(DebuggerCommand::InfoLine(rest_of(tokens, 2))),
+                Some(&"address") => Some(DebuggerCommand::InfoAddress(tokens.get(2)?.to_string())),
+                Some(&"frame") => Some(DebuggerCommand::InfoFrame),
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"args") => Some(DebuggerCommand::InfoArgs),
+                Some(&"record") => Some(DebuggerCommand::InfoRecord),
+                Some(&"checkpoints") => Some(DebuggerCommand::InfoCheckpoints),
+                Some(&"display") => Some(DebuggerCommand::InfoDisplay),
+                _ => None,
+            },
             // Default case:
             _ => None,
         }
     }
 }
+
+/// Joins the tokens from index start on back together, or returns None if there are none.
+fn rest_of(tokens: &[&str], start: usize) -> Option<String> {
+    match tokens.get(start..) {
+        Some(rest) if !rest.is_empty() => Some(rest.join(" ")),
+        _ => None,
+    }
+}
+
+/// Parses the optional count argument of up and down, which defaults to 1.
+fn parse_count(arg: Option<&&str>) -> Option<usize> {
+    match arg {
+        Some(n) => n.parse().ok(),
+        None => Some(1),
+    }
+}
+
+/// Splits off the first whitespace-separated word, returning it and the (trimmed) rest.
+fn split_first_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// Parses program arguments like a shell: quotes ('...' and "...") and backslashes protect
+/// whitespace and special characters, and unquoted <, > and >> redirect stdin and stdout. Returns
+/// None if a quote is left open or a redirection has no file name.
+fn parse_program_args(text: &str) -> Option<ProgramArgs> {
+    enum Word {
+        Text(String),
+        Redirect(&'static str),
+    }
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Distinguishes an empty quoted argument ("") from no argument at all
+    let mut in_word = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' if chars.peek() == Some(&'"') || chars.peek() == Some(&'\\') => {
+                            word.push(chars.next()?)
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next()?);
+            }
+            '<' | '>' => {
+                if in_word {
+                    words.push(Word::Text(std::mem::replace(&mut word, String::new())));
+                    in_word = false;
+                }
+                if c == '<' {
+                    words.push(Word::Redirect("<"));
+                } else if chars.peek() == Some(&'>') {
+                    chars.next();
+                    words.push(Word::Redirect(">>"));
+                } else {
+                    words.push(Word::Redirect(">"));
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(Word::Text(std::mem::replace(&mut word, String::new())));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(Word::Text(word));
+    }
+
+    let mut program_args = ProgramArgs::default();
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        match word {
+            Word::Text(arg) => program_args.args.push(arg),
+            Word::Redirect(redirect) => {
+                let path = match words.next()? {
+                    Word::Text(path) => path,
+                    Word::Redirect(_) => return None,
+                };
+                if redirect == "<" {
+                    program_args.stdin = Some(path);
+                } else {
+                    program_args.stdout = Some(path);
+                    program_args.append = redirect == ">>";
+                }
+            }
+        }
+    }
+    Some(program_args)
+}
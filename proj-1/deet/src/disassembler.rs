@@ -0,0 +1,48 @@
+//! Decodes x86-64 machine code read from the inferior for the disassemble and x/i commands.
+
+use iced_x86::{
+    Decoder, DecoderError, DecoderOptions, Formatter, GasFormatter, Instruction, OpKind,
+};
+
+/// The longest possible x86-64 instruction.
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+pub struct DecodedInstruction {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    /// AT&T syntax, like gdb's default.
+    pub text: String,
+    /// Target of a direct call or jump, so that callers can annotate it with a symbol name.
+    pub branch_target: Option<usize>,
+}
+
+/// Decodes up to max_count instructions from code, which was read starting at address. Stops
+/// early if code ends partway through an instruction.
+pub fn decode(code: &[u8], address: usize, max_count: usize) -> Vec<DecodedInstruction> {
+    let mut decoder = Decoder::with_ip(64, code, address as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_first_operand_char_index(7);
+    formatter.options_mut().set_branch_leading_zeros(false);
+    let mut instruction = Instruction::default();
+    let mut decoded = Vec::new();
+    while decoder.can_decode() && decoded.len() < max_count {
+        decoder.decode_out(&mut instruction);
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            break;
+        }
+        let mut text = String::new();
+        formatter.format(&instruction, &mut text);
+        let branch_target = match instruction.op0_kind() {
+            OpKind::NearBranch64 => Some(instruction.near_branch_target() as usize),
+            _ => None,
+        };
+        let offset = instruction.ip() as usize - address;
+        decoded.push(DecodedInstruction {
+            address: instruction.ip() as usize,
+            bytes: code[offset..offset + instruction.len()].to_vec(),
+            text,
+            branch_target,
+        });
+    }
+    decoded
+}
@@ -1,7 +1,13 @@
+use crate::debug_file;
 use crate::gimli_wrapper;
+use crate::sections::Sections;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection, SymbolKind};
+use once_cell::unsync::OnceCell;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::rc::Rc;
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -11,13 +17,102 @@ pub enum Error {
 }
 
 pub struct DwarfData {
-    files: Vec<File>,
-    addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    // The DWARF sections, from which compilation units are parsed the first time something in
+    // them is looked up. None when the binary has no DWARF.
+    dwarf: Option<gimli_wrapper::Dwarf>,
+    // Compilation units in .debug_info order, i.e. sorted by offset
+    units: Vec<Unit>,
+    // Address ranges of the units' code as (start, end, unit index), sorted
+    unit_ranges: Vec<(usize, usize, usize)>,
+    symbols: Vec<Symbol>,
+    // Indexes into symbols, sorted by the last component of the symbol's name (see short_name)
+    symbol_names: Vec<usize>,
+    // Every DWARF function as (short name, unit index, function index), sorted. Only built, by
+    // parsing every unit, when a function isn't found through the symbol table.
+    function_names: OnceCell<Vec<(String, usize, usize)>>,
+    // None when the binary has no DWARF (e.g. built without -g); only the ELF symbol table is
+    // available then.
+    addr2line: Option<Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>>,
+    debug_file: Option<String>,
+}
+
+/// A compilation unit, parsed on first use.
+struct Unit {
+    name: String,
+    offset: usize,
+    parsed: OnceCell<ParsedUnit>,
+}
+
+/// A compilation unit's contents, with indexes for looking things up in it.
+struct ParsedUnit {
+    file: File,
+    // Every type in the unit, by offset, for looking up the types of members and pointees
+    types: HashMap<usize, Type>,
+    // The functions with code as (start, end, index into file.functions), sorted
+    function_ranges: Vec<(usize, usize, usize)>,
+    // The line table as (line number, address), sorted
+    lines: Vec<(usize, usize)>,
+    // The line table as (address, line number), sorted by address
+    rows: Vec<(usize, usize)>,
+}
+
+impl ParsedUnit {
+    fn new(file: File, types: HashMap<usize, Type>) -> ParsedUnit {
+        let mut function_ranges: Vec<(usize, usize, usize)> = file
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, func)| func.address != 0)
+            .map(|(index, func)| (func.address, func.address + func.text_length, index))
+            .collect();
+        function_ranges.sort();
+        let mut lines: Vec<(usize, usize)> = file
+            .lines
+            .iter()
+            .map(|line| (line.number, line.address))
+            .collect();
+        lines.sort();
+        let mut rows: Vec<(usize, usize)> = lines
+            .iter()
+            .map(|&(number, address)| (address, number))
+            .collect();
+        rows.sort();
+        rows.dedup_by_key(|&mut (address, _)| address);
+        ParsedUnit {
+            file,
+            types,
+            function_ranges,
+            lines,
+            rows,
+        }
+    }
+
+    fn get_function_at(&self, curr_addr: usize) -> Option<&Function> {
+        let index = find_range(&self.function_ranges, curr_addr)?;
+        Some(&self.file.functions[index])
+    }
+}
+
+/// Returns the index stored with the range containing addr, given (start, end, index) ranges
+/// sorted by start that don't overlap.
+fn find_range(ranges: &[(usize, usize, usize)], addr: usize) -> Option<usize> {
+    let position = match ranges.binary_search_by_key(&addr, |&(start, _, _)| start) {
+        Ok(position) => position,
+        Err(0) => return None,
+        Err(position) => position - 1,
+    };
+    let (_, end, index) = ranges[position];
+    if addr < end {
+        Some(index)
+    } else {
+        None
+    }
 }
 
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+        let names: Vec<&str> = self.units.iter().map(|unit| unit.name.as_str()).collect();
+        write!(f, "DwarfData {{units: {:?}}}", names)
     }
 }
 
@@ -28,44 +123,185 @@ impl From<gimli_wrapper::Error> for Error {
 }
 
 impl DwarfData {
-    pub fn from_file(path: &str) -> Result<DwarfData, Error> {
+    /// Loads debugging information for the binary at `path`. If the binary itself was stripped of
+    /// DWARF, a separate debug file is looked up by build ID under `debug_dir` and through
+    /// .gnu_debuglink; failing that, only the ELF symbol table is loaded.
+    pub fn from_file(path: &str, debug_dir: &str) -> Result<DwarfData, Error> {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
         let object = object::File::parse(&*mmap)
             .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+        let mut symbols = load_symbols(&object);
+        let sections = Sections::parse(&mmap);
+        if sections.contains(".debug_info") {
+            return DwarfData::load_dwarf(&object, &sections, symbols, None);
+        }
+
+        if let Some(debug_path) = debug_file::find_debug_file(path, &object, debug_dir) {
+            let debug_file = fs::File::open(&debug_path).or(Err(Error::ErrorOpeningFile))?;
+            let debug_mmap =
+                unsafe { memmap::Mmap::map(&debug_file).or(Err(Error::ErrorOpeningFile))? };
+            let debug_object = object::File::parse(&*debug_mmap)
+                .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+            let debug_sections = Sections::parse(&debug_mmap);
+            if debug_sections.contains(".debug_info") {
+                // The stripped executable usually keeps only .dynsym; the debug file has .symtab
+                symbols.extend(load_symbols(&debug_object));
+                sort_symbols(&mut symbols);
+                let debug_path = debug_path.to_string_lossy().to_string();
+                return DwarfData::load_dwarf(
+                    &debug_object,
+                    &debug_sections,
+                    symbols,
+                    Some(debug_path),
+                );
+            }
+        }
+
+        Ok(DwarfData::new(None, Vec::new(), symbols, None, None))
+    }
+
+    fn load_dwarf(
+        object: &object::File,
+        sections: &Sections,
+        symbols: Vec<Symbol>,
+        debug_file: Option<String>,
+    ) -> Result<DwarfData, Error> {
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
-        Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+        let dwarf = gimli_wrapper::load_dwarf(sections, endian)?;
+        let units = gimli_wrapper::load_units(&dwarf)?;
+        let addr2line = load_addr2line(sections, object.is_little_endian())?;
+        Ok(DwarfData::new(
+            Some(dwarf),
+            units,
+            symbols,
+            Some(addr2line),
+            debug_file,
+        ))
+    }
+
+    fn new(
+        dwarf: Option<gimli_wrapper::Dwarf>,
+        summaries: Vec<gimli_wrapper::UnitSummary>,
+        symbols: Vec<Symbol>,
+        addr2line: Option<
+            Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+        >,
+        debug_file: Option<String>,
+    ) -> DwarfData {
+        let mut unit_ranges: Vec<(usize, usize, usize)> = summaries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, unit)| {
+                // Code removed by the linker is left with ranges starting at 0
+                unit.ranges
+                    .iter()
+                    .filter(|(start, end)| *start != 0 && start < end)
+                    .map(move |&(start, end)| (start, end, index))
+            })
+            .collect();
+        unit_ranges.sort();
+        let mut symbol_names: Vec<usize> = (0..symbols.len()).collect();
+        symbol_names
+            .sort_by(|a, b| short_name(&symbols[*a].name).cmp(short_name(&symbols[*b].name)));
+        DwarfData {
+            dwarf,
+            units: summaries
+                .into_iter()
+                .map(|unit| Unit {
+                    name: unit.name,
+                    offset: unit.offset,
+                    parsed: OnceCell::new(),
+                })
+                .collect(),
+            unit_ranges,
+            symbols,
+            symbol_names,
+            function_names: OnceCell::new(),
+            addr2line,
+            debug_file,
+        }
+    }
+
+    /// Path of the separate debug file the DWARF was read from, if it didn't come from the binary
+    /// itself.
+    pub fn debug_file(&self) -> Option<&str> {
+        self.debug_file.as_ref().map(String::as_str)
+    }
+
+    /// Returns false if the binary had no DWARF and only the ELF symbol table was loaded.
+    pub fn has_debug_info(&self) -> bool {
+        self.addr2line.is_some()
+    }
+
+    /// Returns the contents of the unit at index, parsing it the first time. A unit that can't be
+    /// parsed is reported and treated as empty.
+    fn unit(&self, index: usize) -> &ParsedUnit {
+        let unit = &self.units[index];
+        unit.parsed.get_or_init(|| {
+            let dwarf = self
+                .dwarf
+                .as_ref()
+                .expect("units are only loaded from DWARF");
+            match gimli_wrapper::load_unit(dwarf, unit.offset) {
+                Ok((file, types)) => ParsedUnit::new(file, types),
+                Err(e) => {
+                    println!(
+                        "Could not read debugging symbols for {}: {:?}",
+                        unit.name, e
+                    );
+                    let file = File {
+                        name: unit.name.clone(),
+                        ..Default::default()
+                    };
+                    ParsedUnit::new(file, HashMap::new())
+                }
+            }
         })
     }
 
+    /// Every unit, parsing each as it's reached.
+    fn all_units<'a>(&'a self) -> impl Iterator<Item = &'a ParsedUnit> + 'a {
+        (0..self.units.len()).map(move |index| self.unit(index))
+    }
+
+    /// Index of the unit whose code contains curr_addr.
+    fn unit_index_at(&self, curr_addr: usize) -> Option<usize> {
+        find_range(&self.unit_ranges, curr_addr)
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
-        })
+        let index = self.units.iter().position(|unit| {
+            unit.name == file || (!file.contains("/") && unit.name.ends_with(&format!("/{}", file)))
+        })?;
+        Some(&self.unit(index).file)
     }
 
+    /// Returns the address of the given line, or if no code was generated for it, of the next
+    /// line that has some.
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
-        let target_file = match file {
-            Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+        let index = match file {
+            Some(filename) => self.units.iter().position(|unit| {
+                unit.name == filename
+                    || (!filename.contains("/") && unit.name.ends_with(&format!("/{}", filename)))
+            })?,
+            None if self.units.is_empty() => return None,
+            None => 0,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        let lines = &self.unit(index).lines;
+        let position = match lines.binary_search(&(line_number, 0)) {
+            Ok(position) | Err(position) => position,
+        };
+        Some(lines.get(position)?.1)
     }
 
+    /// Looks a function up by name, which may be qualified (see Function::matches).
     #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
         match file {
@@ -73,24 +309,324 @@ impl DwarfData {
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(|func| func.address != 0 && func.matches(func_name))?
                     .address,
             ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
+            None => match self.find_function(func_name) {
+                Some(func) => Some(func.address),
+                None => self.get_addr_for_symbol(func_name),
+            },
+        }
+    }
+
+    /// Looks up a DWARF function by name. Functions are found through the symbol table where
+    /// possible, so that only the unit defining them is parsed; others (like static functions
+    /// without a symbol) take parsing every unit.
+    fn find_function(&self, func_name: &str) -> Option<&Function> {
+        for sym in self.find_symbols(func_name) {
+            let func = self
+                .unit_index_at(sym.address)
+                .and_then(|index| self.unit(index).get_function_at(sym.address));
+            if let Some(func) = func {
+                if func.address == sym.address {
+                    return Some(func);
+                }
+            }
+        }
+        let names = self.function_names.get_or_init(|| {
+            let mut names = Vec::new();
+            for (unit_index, unit) in self.all_units().enumerate() {
+                for (index, func) in unit.file.functions.iter().enumerate() {
+                    // Declarations of functions defined elsewhere (like a library's) have no
+                    // address
+                    if func.address == 0 {
+                        continue;
+                    }
+                    names.push((
+                        short_name(&func.qualified_name).to_string(),
+                        unit_index,
+                        index,
+                    ));
+                    if func.name != short_name(&func.qualified_name) {
+                        names.push((func.name.clone(), unit_index, index));
                     }
                 }
-                None
             }
+            names.sort();
+            names
+        });
+        let key = short_name(func_name);
+        let start = match names.binary_search_by(|(name, _, _)| {
+            name.as_str().cmp(key).then(std::cmp::Ordering::Greater)
+        }) {
+            Ok(start) | Err(start) => start,
+        };
+        names[start..]
+            .iter()
+            .take_while(|(name, _, _)| name == key)
+            .map(|&(_, unit_index, index)| &self.unit(unit_index).file.functions[index])
+            .find(|func| func.matches(func_name))
+    }
+
+    /// Returns the address just past the prologue of the function starting at addr, where its
+    /// arguments have been stored and can be printed, or addr itself if that isn't known. As in
+    /// gdb, this is where breaking on a function stops.
+    pub fn skip_prologue(&self, addr: usize) -> usize {
+        self.get_function_at(addr)
+            .filter(|func| func.address == addr && func.body_address != 0)
+            .map_or(addr, |func| func.body_address)
+    }
+
+    /// Symbols matching name (see path_matches), in address order.
+    fn find_symbols<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Symbol> + 'a {
+        let key = short_name(name);
+        let start = match self.symbol_names.binary_search_by(|index| {
+            short_name(&self.symbols[*index].name)
+                .cmp(key)
+                .then(std::cmp::Ordering::Greater)
+        }) {
+            Ok(start) | Err(start) => start,
+        };
+        self.symbol_names[start..]
+            .iter()
+            .map(move |index| &self.symbols[*index])
+            .take_while(move |sym| short_name(&sym.name) == key)
+            .filter(move |sym| path_matches(&sym.name, name))
+    }
+
+    /// Looks up a function in the ELF symbol table (.symtab, then .dynsym).
+    pub fn get_addr_for_symbol(&self, name: &str) -> Option<usize> {
+        Some(self.find_symbols(name).next()?.address)
+    }
+
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        let index = match self.units.binary_search_by_key(&offset, |unit| unit.offset) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        self.unit(index).types.get(&offset)
+    }
+
+    /// Returns the symbol containing curr_addr along with the offset of curr_addr into it, e.g.
+    /// ("main", 0x1a) for main+0x1a.
+    pub fn get_symbol_from_addr(&self, curr_addr: usize) -> Option<(String, usize)> {
+        let sym = self.find_symbol(curr_addr)?;
+        Some((sym.name.clone(), curr_addr - sym.address))
+    }
+
+    fn find_symbol(&self, curr_addr: usize) -> Option<&Symbol> {
+        let index = match self
+            .symbols
+            .binary_search_by_key(&curr_addr, |sym| sym.address)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let sym = &self.symbols[index];
+        // A symbol whose extent isn't known only covers its own address
+        if curr_addr >= sym.address + sym.size.max(1) {
+            return None;
+        }
+        Some(sym)
+    }
+
+    /// Returns the [start, end) address range of the named function.
+    pub fn get_function_range(&self, func_name: &str) -> Option<(usize, usize)> {
+        if let Some(func) = self.find_function(func_name) {
+            return Some((func.address, func.address + func.text_length));
+        }
+        let sym = self.find_symbols(func_name).next()?;
+        Some((sym.address, sym.address + sym.size))
+    }
+
+    /// Returns the [start, end) address range of the function containing curr_addr.
+    pub fn get_function_range_from_addr(&self, curr_addr: usize) -> Option<(usize, usize)> {
+        if let Some(func) = self.get_function_at(curr_addr) {
+            return Some((func.address, func.address + func.text_length));
+        }
+        let sym = self.find_symbol(curr_addr)?;
+        Some((sym.address, sym.address + sym.size))
+    }
+
+    /// Returns the DWARF function whose code contains curr_addr.
+    pub fn get_function_at(&self, curr_addr: usize) -> Option<&Function> {
+        self.unit(self.unit_index_at(curr_addr)?)
+            .get_function_at(curr_addr)
+    }
+
+    /// Names of all functions in the symbol table, both in full and as their last path component
+    /// (e.g. demo::main and main), sorted and deduplicated. Functions only in the DWARF, like
+    /// static functions without a symbol, are left out rather than parse every unit.
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .symbols
+            .iter()
+            .flat_map(|sym| vec![sym.name.clone(), short_name(&sym.name).to_string()])
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Paths of the source files (compilation units).
+    pub fn file_names(&self) -> Vec<String> {
+        self.units.iter().map(|unit| unit.name.clone()).collect()
+    }
+
+    /// The parameters (or, if parameters is false, the locals) in scope at curr_addr in the
+    /// function executing there. inline_depth selects a call inlined into that function: 0 is the
+    /// function itself, 1 a call inlined into it, and so on. Variables in inner blocks come before
+    /// the outer ones they may shadow.
+    pub fn get_local_variables(
+        &self,
+        curr_addr: usize,
+        inline_depth: usize,
+        parameters: bool,
+    ) -> Vec<&Variable> {
+        let func = match self.get_function_at(curr_addr) {
+            Some(func) => func,
+            None => return Vec::new(),
+        };
+        let vars = if parameters {
+            &func.parameters
+        } else {
+            &func.variables
+        };
+        let mut vars: Vec<&Variable> = vars
+            .iter()
+            .filter(|var| var.scope.inline_depth == inline_depth && var.scope.contains(curr_addr))
+            .collect();
+        vars.sort_by(|a, b| b.scope.depth.cmp(&a.scope.depth));
+        vars
+    }
+
+    /// Parameters and locals in scope at curr_addr (see get_local_variables), then the globals of
+    /// the compilation unit curr_addr is in.
+    fn visible_variables(&self, curr_addr: usize, inline_depth: usize) -> Vec<&Variable> {
+        let mut vars = self.get_local_variables(curr_addr, inline_depth, false);
+        vars.extend(self.get_local_variables(curr_addr, inline_depth, true));
+        vars.sort_by(|a, b| b.scope.depth.cmp(&a.scope.depth));
+        if let Some(index) = self.unit_index_at(curr_addr) {
+            vars.extend(self.unit(index).file.global_variables.iter());
+        }
+        vars
+    }
+
+    /// Names of the variables visible at curr_addr: parameters and locals in scope, and the
+    /// globals of its compilation unit.
+    pub fn variable_names(&self, curr_addr: usize, inline_depth: usize) -> Vec<String> {
+        self.visible_variables(curr_addr, inline_depth)
+            .iter()
+            .map(|var| var.name.clone())
+            .collect()
+    }
+
+    /// Looks up the variable a name refers to at curr_addr: the innermost parameter or local of
+    /// that name in scope, or else a global, preferring the one in curr_addr's compilation unit.
+    pub fn get_variable(
+        &self,
+        curr_addr: usize,
+        inline_depth: usize,
+        name: &str,
+    ) -> Option<&Variable> {
+        if let Some(var) = self
+            .visible_variables(curr_addr, inline_depth)
+            .into_iter()
+            .find(|var| var.name == name)
+        {
+            return Some(var);
+        }
+        self.get_global_variable(name)
+    }
+
+    /// Looks up a global variable by name.
+    pub fn get_global_variable(&self, name: &str) -> Option<&Variable> {
+        self.all_units()
+            .flat_map(|unit| unit.file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Every compilation unit's functions, variables and lines, along with its types by offset.
+    /// This parses every unit.
+    pub fn units<'a>(&'a self) -> impl Iterator<Item = (&'a File, &'a HashMap<usize, Type>)> + 'a {
+        self.all_units().map(|unit| (&unit.file, &unit.types))
+    }
+
+    /// The functions in the ELF symbol table, in address order.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Returns the line of the line table row containing curr_addr, along with the [start, end)
+    /// addresses of the code for that line there.
+    pub fn get_line_range(&self, curr_addr: usize) -> Option<(Line, usize, usize)> {
+        let unit = self.unit(self.unit_index_at(curr_addr)?);
+        let position = match unit
+            .rows
+            .binary_search_by_key(&curr_addr, |&(address, _)| address)
+        {
+            Ok(position) => position,
+            Err(0) => return None,
+            Err(position) => position - 1,
+        };
+        let (start, number) = unit.rows[position];
+        // The code for the line runs until a row for another line, or the end of the function
+        let function_end = unit
+            .get_function_at(start)
+            .map(|func| func.address + func.text_length);
+        let end = unit.rows[position..]
+            .iter()
+            .find(|&&(_, row_number)| row_number != number)
+            .map(|&(address, _)| address);
+        let end = match (end, function_end) {
+            (Some(end), Some(function_end)) => std::cmp::min(end, function_end),
+            (Some(end), None) | (None, Some(end)) => end,
+            (None, None) => start,
+        };
+        let line = Line {
+            file: unit.file.name.clone(),
+            number,
+            address: start,
+        };
+        Some((line, start, end))
+    }
+
+    /// The functions executing at curr_addr, innermost first: any calls inlined there, then the
+    /// function containing curr_addr. Each comes with its current line, which for a function that
+    /// a call was inlined into is the line of that call.
+    pub fn get_inline_frames(&self, curr_addr: usize) -> Vec<(String, Option<Line>)> {
+        let mut frames = Vec::new();
+        let mut iter = match self.addr2line.as_ref() {
+            Some(context) => match context.find_frames(curr_addr.try_into().unwrap()) {
+                Ok(iter) => iter,
+                Err(_) => return frames,
+            },
+            None => return frames,
+        };
+        while let Ok(Some(frame)) = iter.next() {
+            let function = match frame.function.as_ref().and_then(|f| f.demangle().ok()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let line = frame.location.and_then(|location| {
+                Some(Line {
+                    file: location.file?.to_string(),
+                    number: location.line?.try_into().unwrap(),
+                    address: curr_addr,
+                })
+            });
+            frames.push((function, line));
         }
+        frames
     }
 
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
+            .as_ref()?
             .find_location(curr_addr.try_into().unwrap())
             .ok()??;
         Some(Line {
@@ -104,16 +640,17 @@ impl DwarfData {
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
+            .as_ref()?
             .find_frames(curr_addr.try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        Some(frame.function?.demangle().ok()?.to_string())
     }
 
-    #[allow(dead_code)]
+    /// Prints everything known about every compilation unit (see --dump-dwarf).
     pub fn print(&self) {
-        for file in &self.files {
+        for file in self.all_units().map(|unit| &unit.file) {
             println!("------");
             println!("{}", file.name);
             println!("------");
@@ -132,6 +669,12 @@ impl DwarfData {
                     "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
                     func.name, func.line_number, func.address, func.text_length
                 );
+                for var in &func.parameters {
+                    println!(
+                        "    * Parameter: {} ({}, located at {}, declared at line {})",
+                        var.name, var.entity_type.name, var.location, var.line_number
+                    );
+                }
                 for var in &func.variables {
                     println!(
                         "    * Variable: {} ({}, located at {}, declared at line {})",
@@ -148,10 +691,123 @@ impl DwarfData {
     }
 }
 
+/// Creates the addr2line context from the same (decompressed) sections as the rest of the DWARF.
+/// addr2line has its own version of gimli, so the sections are loaded again for it.
+fn load_addr2line(
+    sections: &Sections,
+    little_endian: bool,
+) -> Result<Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>, Error> {
+    let endian = if little_endian {
+        addr2line::gimli::RunTimeEndian::Little
+    } else {
+        addr2line::gimli::RunTimeEndian::Big
+    };
+    let load = |name: &str| -> Result<_, Error> {
+        let data = sections
+            .data(name)
+            .map_err(gimli_wrapper::Error::from)?
+            .unwrap_or(Cow::Borrowed(&[][..]));
+        Ok(addr2line::gimli::EndianRcSlice::new(
+            Rc::from(&*data),
+            endian,
+        ))
+    };
+    let context = Context::from_sections(
+        load(".debug_abbrev")?.into(),
+        load(".debug_addr")?.into(),
+        load(".debug_info")?.into(),
+        load(".debug_line")?.into(),
+        load(".debug_line_str")?.into(),
+        load(".debug_ranges")?.into(),
+        load(".debug_rnglists")?.into(),
+        load(".debug_str")?.into(),
+        load(".debug_str_offsets")?.into(),
+        addr2line::gimli::EndianRcSlice::new(Rc::from(&[][..]), endian),
+    )
+    .map_err(gimli_wrapper::Error::from)?;
+    Ok(context)
+}
+
+/// Collects the function symbols from .symtab and .dynsym, sorted by address.
+fn load_symbols(object: &object::File) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = object
+        .symbols()
+        .chain(object.dynamic_symbols())
+        .filter(|(_, sym)| {
+            sym.kind() == SymbolKind::Text && !sym.is_undefined() && sym.address() != 0
+        })
+        .filter_map(|(_, sym)| {
+            Some(Symbol {
+                name: demangle(sym.name().filter(|name| !name.is_empty())?),
+                address: sym.address().try_into().ok()?,
+                size: sym.size().try_into().ok()?,
+            })
+        })
+        .collect();
+    sort_symbols(&mut symbols);
+
+    // Symbols without a size (like _start, or _init and _fini) run up to the next symbol, but not
+    // past the end of their section
+    let sections: Vec<(usize, usize)> = object
+        .sections()
+        .filter_map(|section| {
+            let start: usize = section.address().try_into().ok()?;
+            let size: usize = section.size().try_into().ok()?;
+            Some((start, start + size))
+        })
+        .collect();
+    for index in 0..symbols.len() {
+        let address = symbols[index].address;
+        if symbols[index].size != 0 {
+            continue;
+        }
+        let section_end = match sections
+            .iter()
+            .find(|(start, end)| *start <= address && address < *end)
+        {
+            Some((_, end)) => *end,
+            None => continue,
+        };
+        let end = symbols[index + 1..]
+            .iter()
+            .map(|sym| sym.address)
+            .find(|next| *next > address)
+            .map_or(section_end, |next| next.min(section_end));
+        symbols[index].size = end - address;
+    }
+    symbols
+}
+
+/// Demangles a Rust (legacy or v0) or C++ symbol, leaving other names as they are. Rust hashes
+/// are left off, so crate::module::func comes out the way it's written in the source.
+pub fn demangle(name: &str) -> String {
+    addr2line::demangle_auto(Cow::from(name), None).into_owned()
+}
+
+/// Whether name refers to the function at path: either all of it, or its last components (so
+/// module::func and func both match crate::module::func). C++ parameter lists are ignored.
+fn path_matches(path: &str, name: &str) -> bool {
+    let path = path.split('(').next().unwrap_or(path);
+    path == name || (path.ends_with(name) && path[..path.len() - name.len()].ends_with("::"))
+}
+
+/// The last component of a function's path, without any C++ parameter list: the part of the
+/// name that any name path_matches accepts for it must end with.
+fn short_name(path: &str) -> &str {
+    let path = path.split('(').next().unwrap_or(path);
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+fn sort_symbols(symbols: &mut Vec<Symbol>) {
+    symbols.sort_by(|a, b| a.address.cmp(&b.address).then_with(|| a.name.cmp(&b.name)));
+    symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
 }
 
 impl Type {
@@ -159,14 +815,90 @@ impl Type {
         Type {
             name: name,
             size: size,
+            kind: TypeKind::Base,
         }
     }
 }
 
+/// How a type's bytes are laid out. Types refer to other types by their offset in .debug_info,
+/// which DwarfData::get_type looks up.
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    /// Integers, floating point numbers, characters and booleans, told apart by the type's name
+    Base,
+    /// A pointer or reference, and the type it points to (None for void *)
+    Pointer(Option<usize>),
+    /// A struct, union or class. Types from Rust compilation units are printed Rust-style, and
+    /// their generic parameters are used by the Vec and String pretty-printers.
+    Struct {
+        members: Vec<Member>,
+        template_params: Vec<usize>,
+        rust: bool,
+    },
+    /// A Rust enum: the member holding the discriminant (absent if there is only one variant),
+    /// and the variants
+    Enum {
+        discriminant: Option<Member>,
+        variants: Vec<Variant>,
+    },
+    Array {
+        element: usize,
+        count: usize,
+    },
+    /// A typedef, or a const or volatile qualified type
+    Alias(usize),
+}
+
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Base
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    /// Offset from the start of the enclosing struct
+    pub offset: usize,
+    pub entity_type: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// Value of the discriminant that selects this variant, or None for the variant selected
+    /// when no other one matches (e.g. Some, for an Option<&T> that isn't null)
+    pub discriminant: Option<u64>,
+    /// The variant's fields, as a member whose type is a struct named after the variant
+    pub member: Member,
+}
+
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Offset from the DWARF frame base of a function whose frame base is rbp, as rustc emits
+    /// when frame pointers are kept
+    BasePointerOffset(isize),
+    /// Offset from the DWARF frame base of a function whose frame base is rsp, as rustc emits
+    StackPointerOffset(isize),
+    /// Given by a location list, as optimizing compilers emit; shown as <optimized out>
+    OptimizedOut,
+}
+
+impl Location {
+    /// Resolves the location to an address, given the rbp and rsp of the frame the variable lives
+    /// in. Returns None for a variable that is optimized out.
+    pub fn address(&self, base_ptr: usize, stack_ptr: usize) -> Option<usize> {
+        Some(match *self {
+            Location::Address(addr) => addr,
+            // Offsets are from the DWARF frame base, which gcc sets to the CFA: the value of rsp
+            // before the call, i.e. rbp + 16 once the prologue has pushed rbp.
+            Location::FramePointerOffset(offset) => (base_ptr as isize + 16 + offset) as usize,
+            Location::BasePointerOffset(offset) => (base_ptr as isize + offset) as usize,
+            Location::StackPointerOffset(offset) => (stack_ptr as isize + offset) as usize,
+            Location::OptimizedOut => return None,
+        })
+    }
 }
 
 impl fmt::Display for Location {
@@ -174,6 +906,9 @@ impl fmt::Display for Location {
         match *self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::BasePointerOffset(offset) => write!(f, "BasePointerOffset({})", offset),
+            Location::StackPointerOffset(offset) => write!(f, "StackPointerOffset({})", offset),
+            Location::OptimizedOut => write!(f, "OptimizedOut"),
         }
     }
 }
@@ -191,15 +926,53 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub scope: Scope,
+}
+
+/// Where in its function a variable is visible. Globals and variables declared at the top level
+/// of a function have the default scope, which covers the whole function.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    /// PC ranges of the innermost lexical block or inlined call enclosing the declaration
+    pub ranges: Vec<(usize, usize)>,
+    /// Number of lexical blocks and inlined calls enclosing the declaration
+    pub depth: usize,
+    /// Number of inlined calls enclosing the declaration; 0 for the function's own variables
+    pub inline_depth: usize,
+}
+
+impl Scope {
+    pub fn contains(&self, addr: usize) -> bool {
+        self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|&(low, high)| addr >= low && addr < high)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Function {
     pub name: String,
+    /// Demangled linkage name (e.g. crate::module::func), or the plain name if there is none
+    pub qualified_name: String,
     pub address: usize,
+    /// First address past the prologue; 0 if the line table doesn't say
+    pub body_address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
-    pub variables: Vec<Variable>,
+    pub parameters: Vec<Variable>,
+    pub variables: Vec<Variable>, // Locals, not including parameters
+    /// None for functions that return nothing (void)
+    pub return_type: Option<Type>,
+}
+
+impl Function {
+    /// Whether name refers to this function: its plain name, or its qualified name with any
+    /// number of leading components left off.
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || path_matches(&self.qualified_name, name)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -210,6 +983,14 @@ pub struct File {
     pub lines: Vec<Line>,
 }
 
+// An entry from the ELF symbol table
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     pub file: String,
@@ -223,4 +1004,215 @@ impl fmt::Display for Line {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::DwarfData;
+    use crate::sections::Sections;
+    use std::borrow::Cow;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+    use std::time::Instant;
+
+    /// Loads the test binary itself, which is built with debugging information.
+    fn load(path: &str) -> DwarfData {
+        DwarfData::from_file(path, "/usr/lib/debug").expect("could not load debugging information")
+    }
+
+    fn current_exe() -> String {
+        env::current_exe().unwrap().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn indexes_agree_with_the_units() {
+        let data = load(&current_exe());
+        assert!(data.has_debug_info());
+        let mut checked = 0;
+        for unit in data.all_units() {
+            for func in unit
+                .file
+                .functions
+                .iter()
+                .filter(|func| func.address != 0 && func.text_length != 0)
+            {
+                let found = data.get_function_at(func.address).unwrap();
+                assert_eq!(found.address, func.address, "{}", func.qualified_name);
+                let last = func.address + func.text_length - 1;
+                assert_eq!(data.get_function_at(last).unwrap().address, func.address);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn looks_up_functions_by_name() {
+        let data = load(&current_exe());
+        let addr = data.get_addr_for_symbol("dwarf_data::find_range").unwrap();
+        assert_eq!(data.get_addr_for_function(None, "find_range"), Some(addr));
+        assert_eq!(
+            data.get_addr_for_function(None, "deet::dwarf_data::find_range"),
+            Some(addr)
+        );
+        assert_eq!(data.get_function_at(addr).unwrap().name, "find_range");
+        let (start, end) = data.get_function_range("find_range").unwrap();
+        assert_eq!(start, addr);
+        assert_eq!(
+            data.get_function_range_from_addr(end - 1),
+            Some((start, end))
+        );
+        assert_eq!(data.get_addr_for_function(None, "no_such_function"), None);
+    }
+
+    /// Compiles samples/function_calls.c with the Makefile's flags and extra_flags, returning the
+    /// path of the executable.
+    fn compile_sample(name: &str, extra_flags: &[&str]) -> String {
+        let output = env::temp_dir().join(format!("deet-{}-{}", name, std::process::id()));
+        let source = format!("{}/samples/function_calls.c", env!("CARGO_MANIFEST_DIR"));
+        let status = Command::new("cc")
+            .args(&["-O0", "-g", "-no-pie", "-fno-omit-frame-pointer"])
+            .args(extra_flags)
+            .arg("-o")
+            .arg(&output)
+            .arg(&source)
+            .status()
+            .expect("couldn't run cc");
+        assert!(status.success(), "compiling {} failed", name);
+        output.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn reads_compressed_debug_sections() {
+        let plain_path = compile_sample("plain", &[]);
+        let plain = load(&plain_path);
+        let functions = ["main", "func1", "func2", "func3"];
+        // gcc -gz makes SHF_COMPRESSED sections, -gz=zlib-gnu the older .zdebug_* ones
+        for (name, flag, section) in &[
+            ("gz", "-gz", ".debug_info"),
+            ("gz-gnu", "-gz=zlib-gnu", ".zdebug_info"),
+        ] {
+            let path = compile_sample(name, &[flag]);
+            let contents = fs::read(&path).unwrap();
+            let sections = Sections::parse(&contents);
+            assert!(sections.contains(section), "{}: no {}", name, section);
+            match sections.data(".debug_info").unwrap() {
+                Some(Cow::Owned(_)) => {}
+                _ => panic!("{}: .debug_info wasn't decompressed", name),
+            }
+
+            let data = load(&path);
+            assert!(data.has_debug_info(), "{}", name);
+            for function in &functions {
+                let addr = data.get_addr_for_function(None, function);
+                assert!(addr.is_some(), "{}: {} not found", name, function);
+                assert_eq!(addr, plain.get_addr_for_function(None, function));
+            }
+            for line in 5..=25 {
+                let addr = data.get_addr_for_line(None, line);
+                assert_eq!(addr, plain.get_addr_for_line(None, line), "{}", name);
+                let found = addr.and_then(|addr| data.get_line_from_addr(addr));
+                let expected = addr.and_then(|addr| plain.get_line_from_addr(addr));
+                assert_eq!(
+                    found.map(|line| line.number),
+                    expected.map(|line| line.number)
+                );
+            }
+            let func2 = data.get_addr_for_function(None, "func2").unwrap();
+            assert_eq!(data.get_line_from_addr(func2).unwrap().number, 9);
+            fs::remove_file(&path).ok();
+        }
+        fs::remove_file(&plain_path).ok();
+    }
+
+    #[test]
+    fn bounds_symbols_without_a_size() {
+        let path = compile_sample("nodebug", &["-g0"]);
+        let data = load(&path);
+        assert!(!data.has_debug_info());
+        // _fini (from crti.o) has no size, and is the last function in the executable
+        let fini = data.get_addr_for_symbol("_fini").unwrap();
+        assert_eq!(data.symbols().last().unwrap().name, "_fini");
+        assert_eq!(
+            data.get_symbol_from_addr(fini + 4),
+            Some(("_fini".to_string(), 4))
+        );
+        assert_eq!(data.get_symbol_from_addr(fini + 0x1000_0000), None);
+        let main = data.get_addr_for_symbol("main").unwrap();
+        assert_eq!(
+            data.get_symbol_from_addr(main),
+            Some(("main".to_string(), 0))
+        );
+        fs::remove_file(&path).ok();
+    }
+
+    /// Times loading a binary and looking things up in it. Run with
+    ///
+    ///     DEET_BENCH_BINARY=<binary> cargo test --release load_benchmark -- --ignored --nocapture
+    ///
+    /// The test binary itself is used if DEET_BENCH_BINARY isn't set.
+    #[test]
+    #[ignore]
+    fn load_benchmark() {
+        let path = env::var("DEET_BENCH_BINARY").unwrap_or_else(|_| current_exe());
+        let start = Instant::now();
+        let data = load(&path);
+        println!("load:                {:?}", start.elapsed());
+        println!("  compilation units: {}", data.units.len());
+        println!("  symbols:           {}", data.symbols.len());
 
+        let addresses: Vec<usize> = data.symbols.iter().map(|sym| sym.address).collect();
+        let start = Instant::now();
+        let found = addresses
+            .iter()
+            .filter(|addr| data.get_function_at(**addr).is_some())
+            .count();
+        println!(
+            "pc -> function:      {:?} for {} addresses ({} in DWARF)",
+            start.elapsed(),
+            addresses.len(),
+            found
+        );
+
+        let start = Instant::now();
+        let found = addresses
+            .iter()
+            .filter(|addr| data.get_function_at(**addr).is_some())
+            .count();
+        println!(
+            "  again, parsed:     {:?} ({} found)",
+            start.elapsed(),
+            found
+        );
+
+        let names: Vec<String> = data.symbols.iter().map(|sym| sym.name.clone()).collect();
+        let start = Instant::now();
+        let found = names
+            .iter()
+            .filter(|name| data.get_addr_for_function(None, name).is_some())
+            .count();
+        println!(
+            "name -> address:     {:?} for {} names ({} found)",
+            start.elapsed(),
+            names.len(),
+            found
+        );
+
+        let start = Instant::now();
+        let units = data.all_units().count();
+        println!(
+            "parse all units:     {:?} ({} units)",
+            start.elapsed(),
+            units
+        );
+
+        let start = Instant::now();
+        let found = (1..10000)
+            .filter(|line| data.get_addr_for_line(None, *line).is_some())
+            .count();
+        println!(
+            "line -> address:     {:?} for 10000 lines ({} found)",
+            start.elapsed(),
+            found
+        );
+    }
+}
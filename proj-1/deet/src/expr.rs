@@ -0,0 +1,207 @@
+//! Parses the expressions that print and call evaluate when they call functions in the
+//! inferior: f(1, 'a', x, &y, g(2)). Arguments are integer and character literals, variables
+//! (passed by value), addresses of variables, and further calls.
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    /// An integer or character literal
+    Int(i64),
+    Variable(String),
+    /// &variable
+    AddressOf(String),
+    /// A function and its arguments
+    Call(String, Vec<Expr>),
+}
+
+/// Parses text as a single expression, or returns an error message saying where it went wrong.
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let expr = parser.expr()?;
+    parser.skip_spaces();
+    if parser.pos != text.len() {
+        return Err(parser.syntax_error());
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes c if it is next, returning whether it was.
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn syntax_error(&self) -> String {
+        format!("A syntax error in expression, near `{}'.", self.rest())
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('&') => {
+                self.pos += 1;
+                self.skip_spaces();
+                Ok(Expr::AddressOf(self.identifier()?))
+            }
+            Some('\'') => self.character(),
+            Some(c) if c.is_ascii_digit() || c == '-' => self.integer(),
+            Some(_) => {
+                let name = self.identifier()?;
+                self.skip_spaces();
+                if !self.eat('(') {
+                    return Ok(Expr::Variable(name));
+                }
+                let mut args = Vec::new();
+                self.skip_spaces();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.expr()?);
+                        self.skip_spaces();
+                        if self.eat(')') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.syntax_error());
+                        }
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            None => Err(self.syntax_error()),
+        }
+    }
+
+    /// A C identifier, or a Rust path such as module::func.
+    fn identifier(&mut self) -> Result<String, String> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return Err(self.syntax_error());
+        }
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .unwrap_or_else(|| rest.len());
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    /// A decimal or (with 0x) hexadecimal integer, optionally negative.
+    fn integer(&mut self) -> Result<Expr, String> {
+        let rest = self.rest();
+        let negative = rest.starts_with('-');
+        let digits = if negative { &rest[1..] } else { rest };
+        let (digits, radix) = if digits.starts_with("0x") || digits.starts_with("0X") {
+            (&digits[2..], 16)
+        } else {
+            (digits, 10)
+        };
+        let len = digits
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or_else(|| digits.len());
+        // Hexadecimal constants may use all 64 bits, as addresses do
+        let value = u64::from_str_radix(&digits[..len], radix)
+            .ok()
+            .filter(|value| radix == 16 || *value <= i64::max_value() as u64)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid number \"{}\".",
+                    &rest[..rest.len() - digits.len() + len]
+                )
+            })? as i64;
+        self.pos += rest.len() - digits.len() + len;
+        Ok(Expr::Int(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        }))
+    }
+
+    /// A character literal such as 'a' or '\n', which is its character code.
+    fn character(&mut self) -> Result<Expr, String> {
+        self.pos += 1;
+        let c = match self.peek() {
+            Some('\\') => {
+                self.pos += 1;
+                let escaped = self.peek().ok_or_else(|| self.syntax_error())?;
+                match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    '\\' | '\'' | '"' => escaped,
+                    _ => return Err(self.syntax_error()),
+                }
+            }
+            Some(c) if c != '\'' => c,
+            _ => return Err(self.syntax_error()),
+        };
+        self.pos += self.peek().map_or(0, char::len_utf8);
+        if !self.eat('\'') {
+            return Err("Unmatched single quote.".to_string());
+        }
+        Ok(Expr::Int(c as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_calls() {
+        assert_eq!(
+            parse("f(1, -2, 0x10, 'a', '\\n', x, &y)"),
+            Ok(Expr::Call(
+                "f".to_string(),
+                vec![
+                    Expr::Int(1),
+                    Expr::Int(-2),
+                    Expr::Int(16),
+                    Expr::Int(97),
+                    Expr::Int(10),
+                    Expr::Variable("x".to_string()),
+                    Expr::AddressOf("y".to_string()),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse(" module::f ( g() ) "),
+            Ok(Expr::Call(
+                "module::f".to_string(),
+                vec![Expr::Call("g".to_string(), Vec::new())]
+            ))
+        );
+        assert_eq!(parse("x"), Ok(Expr::Variable("x".to_string())));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(parse("f(1,").is_err());
+        assert!(parse("f(1 2)").is_err());
+        assert!(parse("f(1))").is_err());
+        assert!(parse("f('ab')").is_err());
+        assert!(parse("f(12z)").is_err());
+        assert!(parse("").is_err());
+    }
+}
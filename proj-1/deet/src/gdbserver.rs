@@ -0,0 +1,638 @@
+//! A stub for the GDB remote serial protocol, so that gdb, lldb or any other RSP client can debug
+//! a program started by deet (`deet --gdbserver :1234 prog`, then `target remote :1234` in gdb).
+//!
+//! Only the core of the protocol is implemented: reading and writing registers (g/G) and memory
+//! (m/M), continuing and single-stepping (c/s, and C/S to pass a signal), software breakpoints
+//! (Z0/z0) and the stop reason (?), as well as interrupting the running program with ^C. Other
+//! packets get the empty reply, which tells the client they aren't supported.
+
+use crate::inferior::{Inferior, Status};
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::WaitPidFlag;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Byte a client sends outside of packets to stop the running program
+pub const INTERRUPT: u8 = 0x03;
+/// How often to check whether the program has stopped while watching for INTERRUPT
+const INTERRUPT_POLL_MS: libc::c_int = 10;
+
+/// The registers of gdb's amd64 g packet, in order, with their sizes in bytes. The floating point
+/// and vector registers that follow are left out; clients treat them as unavailable.
+const REGISTERS: &[(&str, usize)] = &[
+    ("rax", 8),
+    ("rbx", 8),
+    ("rcx", 8),
+    ("rdx", 8),
+    ("rsi", 8),
+    ("rdi", 8),
+    ("rbp", 8),
+    ("rsp", 8),
+    ("r8", 8),
+    ("r9", 8),
+    ("r10", 8),
+    ("r11", 8),
+    ("r12", 8),
+    ("r13", 8),
+    ("r14", 8),
+    ("r15", 8),
+    ("rip", 8),
+    ("eflags", 4),
+    ("cs", 4),
+    ("ss", 4),
+    ("ds", 4),
+    ("es", 4),
+    ("fs", 4),
+    ("gs", 4),
+];
+
+/// Linux signal numbers and the numbers gdb uses for the same signals in stop replies and C/S
+/// packets. They only agree for the first few.
+const SIGNAL_NUMBERS: &[(Signal, u8)] = &[
+    (Signal::SIGHUP, 1),
+    (Signal::SIGINT, 2),
+    (Signal::SIGQUIT, 3),
+    (Signal::SIGILL, 4),
+    (Signal::SIGTRAP, 5),
+    (Signal::SIGABRT, 6),
+    (Signal::SIGFPE, 8),
+    (Signal::SIGKILL, 9),
+    (Signal::SIGBUS, 10),
+    (Signal::SIGSEGV, 11),
+    (Signal::SIGSYS, 12),
+    (Signal::SIGPIPE, 13),
+    (Signal::SIGALRM, 14),
+    (Signal::SIGTERM, 15),
+    (Signal::SIGURG, 16),
+    (Signal::SIGSTOP, 17),
+    (Signal::SIGTSTP, 18),
+    (Signal::SIGCONT, 19),
+    (Signal::SIGCHLD, 20),
+    (Signal::SIGTTIN, 21),
+    (Signal::SIGTTOU, 22),
+    (Signal::SIGIO, 23),
+    (Signal::SIGXCPU, 24),
+    (Signal::SIGXFSZ, 25),
+    (Signal::SIGVTALRM, 26),
+    (Signal::SIGPROF, 27),
+    (Signal::SIGWINCH, 28),
+    (Signal::SIGUSR1, 30),
+    (Signal::SIGUSR2, 31),
+    (Signal::SIGPWR, 32),
+];
+
+pub fn gdb_signal(signal: Signal) -> u8 {
+    SIGNAL_NUMBERS
+        .iter()
+        .find(|(sig, _)| *sig == signal)
+        .map(|(_, number)| *number)
+        .unwrap_or(143) // GDB_SIGNAL_UNKNOWN
+}
+
+pub fn from_gdb_signal(number: u8) -> Option<Signal> {
+    SIGNAL_NUMBERS
+        .iter()
+        .find(|(_, n)| *n == number)
+        .map(|(sig, _)| *sig)
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses the "addr,len" that starts m and M packets.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((addr, len))
+}
+
+/// The fields of regs holding REGISTERS, in order.
+fn register_fields(regs: &mut libc::user_regs_struct) -> [&mut u64; 24] {
+    [
+        &mut regs.rax,
+        &mut regs.rbx,
+        &mut regs.rcx,
+        &mut regs.rdx,
+        &mut regs.rsi,
+        &mut regs.rdi,
+        &mut regs.rbp,
+        &mut regs.rsp,
+        &mut regs.r8,
+        &mut regs.r9,
+        &mut regs.r10,
+        &mut regs.r11,
+        &mut regs.r12,
+        &mut regs.r13,
+        &mut regs.r14,
+        &mut regs.r15,
+        &mut regs.rip,
+        &mut regs.eflags,
+        &mut regs.cs,
+        &mut regs.ss,
+        &mut regs.ds,
+        &mut regs.es,
+        &mut regs.fs,
+        &mut regs.gs,
+    ]
+}
+
+/// Lays regs out as in a g packet.
+pub fn encode_registers(mut regs: libc::user_regs_struct) -> Vec<u8> {
+    REGISTERS
+        .iter()
+        .zip(register_fields(&mut regs).iter())
+        .flat_map(|((_, size), value)| value.to_le_bytes()[..*size].to_vec())
+        .collect()
+}
+
+/// Sets regs from the start of a G packet (or g reply). Returns None if it is too short.
+pub fn decode_registers(bytes: &[u8], regs: &mut libc::user_regs_struct) -> Option<()> {
+    let mut offset = 0;
+    for ((_, size), field) in REGISTERS.iter().zip(register_fields(regs).iter_mut()) {
+        let mut value = [0u8; 8];
+        value[..*size].copy_from_slice(bytes.get(offset..offset + size)?);
+        **field = u64::from_le_bytes(value);
+        offset += size;
+    }
+    Some(())
+}
+
+/// The packet layer, on either end of a connection: framing, checksums and acknowledgements.
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Connection> {
+        Ok(Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    /// The socket's file descriptor, for writing to it where nothing else may be done (see
+    /// interrupt).
+    pub fn raw_fd(&self) -> RawFd {
+        self.writer.as_raw_fd()
+    }
+
+    /// Reads a byte, or None if the client has disconnected.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Reads the next packet and acknowledges it, returning its data. Returns None once the
+    /// client has disconnected.
+    pub fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip anything between packets: acks of our replies, and interrupt requests (^C)
+            // that came too late to stop the program
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut sum = [0u8; 2];
+            self.reader.read_exact(&mut sum)?;
+            let sum = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+            if sum == Some(checksum(&data)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    /// Sends a packet, resending it until the client acknowledges it.
+    pub fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        loop {
+            self.writer.write_all(packet.as_bytes())?;
+            loop {
+                match self.read_byte()? {
+                    None | Some(b'+') => return Ok(()),
+                    Some(b'-') => break,
+                    Some(_) => continue,
+                }
+            }
+        }
+    }
+}
+
+/// Serves one client on the listener, debugging infer, until the client detaches, kills the
+/// inferior or disconnects. An inferior that is still running when the client disconnects is
+/// killed.
+pub fn serve(infer: Inferior, listener: &TcpListener) -> io::Result<()> {
+    let (stream, peer) = listener.accept()?;
+    println!("Remote debugging from host {}", peer.ip());
+    let mut connection = Connection::new(stream)?;
+    let mut server = Server {
+        infer: Some(infer),
+        client: connection.raw_fd(),
+        stop_reply: format!("S{:02x}", gdb_signal(Signal::SIGTRAP)),
+    };
+    while let Some(packet) = connection.read_packet()? {
+        match server.handle(&packet) {
+            Some(reply) => connection.write_packet(&reply)?,
+            None => {
+                // k has no reply, D replies OK
+                if packet.starts_with('D') {
+                    connection.write_packet("OK")?;
+                }
+                return Ok(());
+            }
+        }
+    }
+    if let Some(mut infer) = server.infer.take() {
+        infer.kill().ok();
+    }
+    Ok(())
+}
+
+struct Server {
+    /// None once the inferior has exited
+    infer: Option<Inferior>,
+    /// The client's socket, watched for INTERRUPT while the inferior runs
+    client: RawFd,
+    /// Reply to "?": why the inferior last stopped
+    stop_reply: String,
+}
+
+impl Server {
+    /// Returns the reply to the packet, or None if the session is over.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let (kind, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match kind {
+            "?" => self.stop_reply.clone(),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "c" => self.resume(false, None, args),
+            "s" => self.resume(true, None, args),
+            "C" | "S" => {
+                let mut parts = args.splitn(2, ';');
+                let signal = parts
+                    .next()
+                    .and_then(|sig| u8::from_str_radix(sig, 16).ok());
+                let addr = parts.next().unwrap_or("");
+                match signal {
+                    Some(signal) => self.resume(kind == "S", from_gdb_signal(signal), addr),
+                    None => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => self.breakpoint(kind == "Z", args),
+            "k" => {
+                if let Some(mut infer) = self.infer.take() {
+                    infer.kill().ok();
+                }
+                return None;
+            }
+            "D" => {
+                if let Some(infer) = self.infer.take() {
+                    if infer.detach().is_err() {
+                        return Some("E01".to_string());
+                    }
+                }
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        let pid = self.infer.as_ref().map_or(0, |infer| infer.pid().as_raw());
+        match query.split(':').next().unwrap_or("") {
+            "Supported" => "PacketSize=4000".to_string(),
+            // We started the process, so the client should kill it rather than detach on quit
+            "Attached" => "0".to_string(),
+            "C" => format!("QC{:x}", pid),
+            "fThreadInfo" => format!("m{:x}", pid),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        match self.infer.as_ref().map(Inferior::get_registers) {
+            Some(Ok(regs)) => to_hex(&encode_registers(regs)),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_registers(&self, hex: &str) -> String {
+        let infer = match &self.infer {
+            Some(infer) => infer,
+            None => return "E01".to_string(),
+        };
+        let regs = from_hex(hex).and_then(|bytes| {
+            let mut regs = infer.get_registers().ok()?;
+            decode_registers(&bytes, &mut regs)?;
+            Some(regs)
+        });
+        match regs.map(|regs| infer.set_registers(regs)) {
+            Some(Ok(())) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        match (parse_range(args), &self.infer) {
+            (Some((addr, len)), Some(infer)) => match infer.read_memory(addr, len) {
+                Ok(bytes) => to_hex(&bytes),
+                Err(_) => "E01".to_string(),
+            },
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, ':');
+        let range = parts.next().and_then(parse_range);
+        let bytes = parts.next().and_then(from_hex);
+        match (range, bytes, &mut self.infer) {
+            (Some((addr, len)), Some(bytes), Some(infer)) if bytes.len() == len => {
+                match infer.write_memory(addr, &bytes) {
+                    Ok(()) => "OK".to_string(),
+                    Err(_) => "E01".to_string(),
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Handles Z0 and z0 (software breakpoints). Other breakpoint types are unsupported.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        if parts.next() != Some("0") {
+            return String::new();
+        }
+        let addr = parts
+            .next()
+            .and_then(|addr| usize::from_str_radix(addr, 16).ok());
+        let (addr, infer) = match (addr, &mut self.infer) {
+            (Some(addr), Some(infer)) => (addr, infer),
+            _ => return "E01".to_string(),
+        };
+        let result = if insert {
+            infer.breakpoint(&addr)
+        } else {
+            infer.remove_breakpoint(addr)
+        };
+        match result {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E01".to_string(),
+        }
+    }
+
+    /// Continues or steps the inferior, from addr if one is given, and returns the stop reply.
+    fn resume(&mut self, step: bool, signal: Option<Signal>, addr: &str) -> String {
+        let infer = match &mut self.infer {
+            Some(infer) => infer,
+            None => return "E01".to_string(),
+        };
+        if !addr.is_empty() {
+            let regs = usize::from_str_radix(addr, 16)
+                .ok()
+                .and_then(|addr| Some((addr, infer.get_registers().ok()?)));
+            match regs {
+                Some((addr, mut regs)) => {
+                    regs.rip = addr as u64;
+                    if infer.set_registers(regs).is_err() {
+                        return "E01".to_string();
+                    }
+                }
+                None => return "E01".to_string(),
+            }
+        }
+        let client = self.client;
+        let status = infer.get_registers().and_then(|regs| {
+            if !step && !infer.has_breakpoint(regs.rip as usize) {
+                return infer
+                    .goon(signal)
+                    .and_then(|_| wait_interruptibly(infer, client));
+            }
+            // Continuing from a breakpoint: step off it first, so it isn't hit again straight
+            // away
+            match infer.step_instruction(signal)? {
+                Status::Stopped(Signal::SIGTRAP, _) if !step => infer
+                    .goon(None)
+                    .and_then(|_| wait_interruptibly(infer, client)),
+                status => Ok(status),
+            }
+        });
+        self.stop_reply = match status {
+            Ok(Status::Stopped(signal, _)) => format!("S{:02x}", gdb_signal(signal)),
+            Ok(Status::Exited(code)) => {
+                self.infer = None;
+                format!("W{:02x}", code & 0xff)
+            }
+            Ok(Status::Signaled(signal)) => {
+                self.infer = None;
+                format!("X{:02x}", gdb_signal(signal))
+            }
+            Ok(_) | Err(_) => return "E01".to_string(),
+        };
+        self.stop_reply.clone()
+    }
+}
+
+/// Waits for infer to stop, as Inferior::wait does, stopping it with SIGSTOP if the client sends
+/// INTERRUPT meanwhile. That stop is reported as SIGINT, which is what clients expect.
+fn wait_interruptibly(infer: &Inferior, client: RawFd) -> Result<Status, nix::Error> {
+    let mut interrupted = false;
+    loop {
+        match infer.wait(Some(WaitPidFlag::WNOHANG))? {
+            Status::Continued => {}
+            Status::Stopped(Signal::SIGSTOP, rip) if interrupted => {
+                return Ok(Status::Stopped(Signal::SIGINT, rip))
+            }
+            status => return Ok(status),
+        }
+        let mut poll = libc::pollfd {
+            fd: client,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, INTERRUPT_POLL_MS) } <= 0 {
+            continue;
+        }
+        // Nothing else is sent between packets but acks, which can be dropped
+        let mut byte = 0u8;
+        match unsafe { libc::recv(client, &mut byte as *mut u8 as *mut libc::c_void, 1, 0) } {
+            1 if byte == INTERRUPT && !interrupted => {
+                signal::kill(infer.pid(), Signal::SIGSTOP)?;
+                interrupted = true;
+            }
+            1 => {}
+            // The client has gone: stop the inferior, so that serve can kill it
+            _ => {
+                signal::kill(infer.pid(), Signal::SIGSTOP)?;
+                return infer.wait(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger_command::ProgramArgs;
+    use crate::inferior::RunConfig;
+    use std::thread;
+
+    /// Just enough of an RSP client to drive the server.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0u8];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        /// Sends a packet and returns the data of the reply.
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        fn send(&mut self, data: &str) {
+            write!(self.stream, "${}#{:02x}", data, checksum(data.as_bytes())).unwrap();
+            assert_eq!(self.read_byte(), b'+');
+        }
+
+        fn reply(&mut self) -> String {
+            assert_eq!(self.read_byte(), b'$');
+            let mut reply = Vec::new();
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    byte => reply.push(byte),
+                }
+            }
+            let sum = [self.read_byte(), self.read_byte()];
+            let sum = u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap();
+            assert_eq!(sum, checksum(&reply));
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(reply).unwrap()
+        }
+    }
+
+    fn rip(registers: &str) -> usize {
+        let bytes = from_hex(&registers[16 * 16..17 * 16]).unwrap();
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&bytes);
+        u64::from_le_bytes(value) as usize
+    }
+
+    #[test]
+    fn serves_a_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // ptrace requests must come from the thread that started the inferior, so the server
+        // thread starts it
+        let server = thread::spawn(move || {
+            let infer = Inferior::new("/bin/true", &RunConfig::default()).unwrap();
+            serve(infer, &listener)
+        });
+        let mut client = Client {
+            stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+        };
+
+        assert_eq!(client.request("qSupported"), "PacketSize=4000");
+        assert_eq!(client.request("?"), "S05");
+        let registers = client.request("g");
+        let size: usize = REGISTERS.iter().map(|(_, size)| size).sum();
+        assert_eq!(registers.len(), size * 2);
+        assert_eq!(client.request(&format!("G{}", registers)), "OK");
+        assert_eq!(client.request("g"), registers);
+
+        let rip = rip(&registers);
+        let code = client.request(&format!("m{:x},4", rip));
+        assert_eq!(code.len(), 8);
+        // The breakpoint's int3 doesn't show in memory reads, and writes go around it
+        assert_eq!(client.request(&format!("Z0,{:x},1", rip)), "OK");
+        assert_eq!(client.request(&format!("m{:x},4", rip)), code);
+        assert_eq!(
+            client.request(&format!("M{:x},1:{}", rip, &code[..2])),
+            "OK"
+        );
+        assert_eq!(client.request(&format!("z0,{:x},1", rip)), "OK");
+        assert_eq!(client.request(&format!("m{:x},4", rip)), code);
+        assert_eq!(client.request(&format!("z0,{:x},1", rip)), "E01");
+
+        assert_eq!(client.request("s"), "S05");
+        assert_ne!(self::rip(&client.request("g")), rip);
+        assert_eq!(client.request("c"), "W00");
+        assert_eq!(client.request("?"), "W00");
+        assert_eq!(client.request("g"), "E01");
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn interrupts_the_running_program() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let config = RunConfig {
+                program_args: ProgramArgs {
+                    args: vec!["10".to_string()],
+                    ..ProgramArgs::default()
+                },
+                ..RunConfig::default()
+            };
+            let infer = Inferior::new("/bin/sleep", &config).unwrap();
+            serve(infer, &listener)
+        });
+        let mut client = Client {
+            stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+        };
+
+        client.send("c");
+        thread::sleep(std::time::Duration::from_millis(200));
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(client.reply(), "S02");
+        assert!(client.request("g").len() > 16);
+        client.send("k");
+        server.join().unwrap().unwrap();
+    }
+}
@@ -6,109 +6,158 @@
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
 use gimli;
+use gimli::Reader as _;
 use gimli::{UnitOffset, UnitSectionOffset};
-use object::Object;
 use std::borrow;
+use std::rc::Rc;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    self, File, Function, Line, Location, Member, Scope, Type, TypeKind, Variable, Variant,
+};
+use crate::sections::Sections;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
-    // Load a section and return as `Cow<[u8]>`.
-    let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
-        Ok(object
-            .section_data_by_name(id.name())
-            .unwrap_or(borrow::Cow::Borrowed(&[][..])))
+/// The DWARF sections of a binary, kept so that compilation units can be parsed as they are
+/// needed.
+pub type Dwarf = gimli::Dwarf<gimli::EndianRcSlice<gimli::RunTimeEndian>>;
+
+/// What is known about a compilation unit without parsing it.
+pub struct UnitSummary {
+    pub name: String,
+    /// Offset of the unit in .debug_info
+    pub offset: usize,
+    /// Address ranges of the unit's code
+    pub ranges: Vec<(usize, usize)>,
+}
+
+pub fn load_dwarf(sections: &Sections, endian: gimli::RunTimeEndian) -> Result<Dwarf, Error> {
+    // Load a section (decompressing it if need be) into an `Rc<[u8]>`, so the sections outlive
+    // the mapped file. Missing sections are empty.
+    let load_section = |id: gimli::SectionId| -> Result<_, Error> {
+        let data = sections
+            .data(id.name())?
+            .unwrap_or(borrow::Cow::Borrowed(&[][..]));
+        Ok(gimli::EndianRcSlice::new(Rc::from(&*data), endian))
     };
     // Load a supplementary section. We don't have a supplementary object file,
     // so always return an empty slice.
-    let load_section_sup = |_| Ok(borrow::Cow::Borrowed(&[][..]));
+    let load_section_sup = |_| Ok(gimli::EndianRcSlice::new(Rc::from(&[][..]), endian));
 
     // Load all of the sections.
-    let dwarf_cow = gimli::Dwarf::load(&load_section, &load_section_sup)?;
-
-    // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
-    let borrow_section: &dyn for<'a> Fn(
-        &'a borrow::Cow<[u8]>,
-    ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
-
-    // Create `EndianSlice`s for all of the sections.
-    let dwarf = dwarf_cow.borrow(&borrow_section);
-
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
-
-    let mut compilation_units: Vec<File> = Vec::new();
+    gimli::Dwarf::load(&load_section, &load_section_sup)
+}
 
-    // Iterate over the compilation units.
+/// Reads the name and address ranges of every compilation unit, from the unit's first DIE only.
+pub fn load_units(dwarf: &Dwarf) -> Result<Vec<UnitSummary>, Error> {
+    let mut units = Vec::new();
     let mut iter = dwarf.units();
     while let Some(header) = iter.next()? {
+        let offset = header.offset().0;
         let unit = dwarf.unit(header)?;
-
-        // Iterate over the Debugging Information Entries (DIEs) in the unit.
-        let mut depth = 0;
         let mut entries = unit.entries();
-        while let Some((delta_depth, entry)) = entries.next_dfs()? {
-            depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
-            match entry.tag() {
-                gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    compilation_units.push(File {
-                        name,
-                        global_variables: Vec::new(),
-                        functions: Vec::new(),
-                        lines: Vec::new(),
-                    });
-                }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
+        let entry = match entries.next_dfs()? {
+            Some((_, entry)) => entry,
+            None => continue,
+        };
+        let name = match entry.attr_value(gimli::DW_AT_name)? {
+            Some(value) => dwarf
+                .attr_string(&unit, value)?
+                .to_string_lossy()?
+                .to_string(),
+            None => "<unknown>".to_string(),
+        };
+        let mut ranges = Vec::new();
+        let mut iter = dwarf.die_ranges(&unit, entry)?;
+        while let Some(range) = iter.next()? {
+            ranges.push((
+                range.begin.try_into().unwrap(),
+                range.end.try_into().unwrap(),
+            ));
+        }
+        units.push(UnitSummary {
+            name,
+            offset,
+            ranges,
+        });
+    }
+    Ok(units)
+}
+
+/// Parses the compilation unit at offset in .debug_info (see load_units), returning its
+/// functions, variables and lines, and its types keyed by offset.
+pub fn load_unit(dwarf: &Dwarf, offset: usize) -> Result<(File, HashMap<usize, Type>), Error> {
+    let header = dwarf
+        .debug_info
+        .header_from_offset(gimli::DebugInfoOffset(offset))?;
+    let unit = dwarf.unit(header)?;
+
+    // Define a mapping from type offsets to type structs
+    let offset_to_type = load_types(dwarf, &unit)?;
+
+    let mut file = File::default();
+    // Iterate over the Debugging Information Entries (DIEs) in the unit.
+    let mut depth = 0;
+    // Lexical blocks and inlined calls enclosing the current DIE, with the depth of each
+    let mut scopes: Vec<(isize, Scope)> = Vec::new();
+    // Depth of the function the current DIE is in, and whether its frame base is rsp
+    let mut function: Option<(isize, Option<u16>)> = None;
+    // Depth of the type the current DIE is in; its member functions are only declarations
+    let mut type_depth: Option<isize> = None;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while scopes
+            .last()
+            .map_or(false, |(scope_depth, _)| *scope_depth >= depth)
+        {
+            scopes.pop();
+        }
+        if function.map_or(false, |(function_depth, _)| function_depth >= depth) {
+            function = None;
+        }
+        if type_depth.map_or(false, |type_depth| type_depth >= depth) {
+            type_depth = None;
+        }
+        if type_depth.is_some() {
+            continue;
+        }
+        // Inlined and out-of-line instances of inline functions take their name, type and
+        // line from the abstract instance instead of repeating them, and the definitions of
+        // methods take them from the declaration in their type
+        let origins = origins(entry, &unit);
+        // Update the offset_to_type mapping for types
+        // Update the variable list for formal params/variables
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                file.name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
+                    if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
+                        name
                     } else {
                         "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
-                gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
-                    let mut attrs = entry.attrs();
+                    }
+                } else {
+                    "<unknown>".to_string()
+                };
+            }
+            gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_enumeration_type => {
+                type_depth = Some(depth);
+            }
+            gimli::DW_TAG_subprogram => {
+                let mut func: Function = Default::default();
+                let mut frame_base = None;
+                for die in std::iter::once(entry).chain(origins.iter()) {
+                    let mut attrs = die.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
                         //println!("   {}: {:?}", attr.name(), val);
                         match attr.name() {
-                            gimli::DW_AT_name => {
+                            gimli::DW_AT_name if func.name.is_empty() => {
                                 if let Ok(DebugValue::Str(name)) = val {
                                     func.name = name;
                                 }
@@ -124,32 +173,76 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.address = low_pc.try_into().unwrap();
                                 }
                             }
-                            gimli::DW_AT_decl_line => {
+                            gimli::DW_AT_decl_line if func.line_number == 0 => {
                                 if let Ok(DebugValue::Uint(line_number)) = val {
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name
+                                if func.qualified_name.is_empty() =>
+                            {
+                                if let Ok(DebugValue::Str(linkage_name)) = val {
+                                    func.qualified_name = dwarf_data::demangle(&linkage_name);
+                                }
+                            }
+                            gimli::DW_AT_frame_base => {
+                                frame_base = frame_base_register(&attr, &unit);
+                            }
+                            gimli::DW_AT_type if func.return_type.is_none() => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    func.return_type = offset_to_type.get(&offset).cloned();
+                                }
+                            }
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
                 }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
+                if func.qualified_name.is_empty() {
+                    func.qualified_name = func.name.clone();
+                }
+                function = Some((depth, frame_base));
+                file.functions.push(func);
+            }
+            gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine => {
+                let mut ranges = Vec::new();
+                let mut iter = dwarf.die_ranges(&unit, entry)?;
+                while let Some(range) = iter.next()? {
+                    ranges.push((
+                        range.begin.try_into().unwrap(),
+                        range.end.try_into().unwrap(),
+                    ));
+                }
+                let parent = match scopes.last() {
+                    Some((_, scope)) => scope.clone(),
+                    None => Scope::default(),
+                };
+                let inlined = entry.tag() == gimli::DW_TAG_inlined_subroutine;
+                scopes.push((
+                    depth,
+                    Scope {
+                        ranges,
+                        depth: parent.depth + 1,
+                        inline_depth: parent.inline_depth + if inlined { 1 } else { 0 },
+                    },
+                ));
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                let mut name = String::new();
+                let mut entity_type: Option<Type> = None;
+                let mut location: Option<Location> = None;
+                let mut line_number = 0;
+                for die in std::iter::once(entry).chain(origins.iter()) {
+                    let mut attrs = die.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
                         //println!("   {}: {:?}", attr.name(), val);
                         match attr.name() {
-                            gimli::DW_AT_name => {
+                            gimli::DW_AT_name if name.is_empty() => {
                                 if let Ok(DebugValue::Str(attr_name)) = val {
                                     name = attr_name;
                                 }
                             }
-                            gimli::DW_AT_type => {
+                            gimli::DW_AT_type if entity_type.is_none() => {
                                 if let Ok(DebugValue::Size(offset)) = val {
                                     if let Some(dtype) = offset_to_type.get(&offset).clone() {
                                         entity_type = Some(dtype.clone());
@@ -157,11 +250,19 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                                let frame_base = function.and_then(|(_, register)| register);
+                                location = match (get_location(&attr, &unit, dwarf), frame_base) {
+                                    (Some(Location::FramePointerOffset(offset)), Some(6)) => {
+                                        Some(Location::BasePointerOffset(offset))
+                                    }
+                                    (Some(Location::FramePointerOffset(offset)), Some(7)) => {
+                                        Some(Location::StackPointerOffset(offset))
+                                    }
+                                    (Some(loc), _) => Some(loc),
+                                    (None, _) => location,
+                                };
                             }
-                            gimli::DW_AT_decl_line => {
+                            gimli::DW_AT_decl_line if line_number == 0 => {
                                 if let Ok(DebugValue::Uint(num)) = val {
                                     line_number = num;
                                 }
@@ -169,78 +270,115 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
-                        if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                }
+                if entity_type.is_some() && location.is_some() {
+                    let var = Variable {
+                        name,
+                        entity_type: entity_type.unwrap(),
+                        location: location.unwrap(),
+                        line_number: line_number.try_into().unwrap(),
+                        scope: match scopes.last() {
+                            Some((_, scope)) => scope.clone(),
+                            None => Scope::default(),
+                        },
+                    };
+                    if function.is_none() {
+                        file.global_variables.push(var);
+                    } else {
+                        let func = file.functions.last_mut().unwrap();
+                        if entry.tag() == gimli::DW_TAG_formal_parameter {
+                            func.parameters.push(var);
+                        } else {
+                            func.variables.push(var);
                         }
                     }
                 }
-                // NOTE: :You may consider supporting other types by extending this
-                // match statement
-                _ => {}
             }
+            // NOTE: :You may consider supporting other types by extending this
+            // match statement
+            _ => {}
         }
+    }
 
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
-            // Iterate over the line program rows.
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
+    // Get line numbers
+    if let Some(program) = unit.line_program.clone() {
+        // This unit's functions by address, to find where each one's prologue ends: at the
+        // first row after its start
+        let mut function_ranges: Vec<(usize, usize, usize)> = file
+            .functions
+            .iter()
+            .enumerate()
+            .filter(|(_, func)| func.address != 0)
+            .map(|(index, func)| (func.address, func.address + func.text_length, index))
+            .collect();
+        function_ranges.sort();
+        // Whether each file index in the line table refers to the unit's own source file, whose
+        // lines are the ones kept
+        let mut in_unit: HashMap<u64, bool> = HashMap::new();
+        // The line table names files by full path, while the unit's name may be relative to its
+        // compilation directory
+        let mut unit_path = path::PathBuf::new();
+        if let Some(ref comp_dir) = unit.comp_dir {
+            unit_path.push(comp_dir.to_string_lossy()?.as_ref());
+        }
+        unit_path.push(&file.name);
+        // Iterate over the line program rows.
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            let address: usize = row.address().try_into().unwrap();
+            let containing = match function_ranges.binary_search(&(address, 0, 0)) {
+                Ok(_) | Err(0) => None,
+                Err(index) => Some(function_ranges[index - 1]),
+            };
+            if let Some((_, end, index)) = containing {
+                let func = &mut file.functions[index];
+                if address < end && func.body_address == 0 {
+                    func.body_address = address;
+                }
+            }
+            if row.end_sequence() {
+                continue;
+            }
+            let in_unit = match in_unit.get(&row.file_index()) {
+                Some(in_unit) => *in_unit,
+                None => {
+                    // Determine the path. A relative directory (as gcc writes for a source file
+                    // given by relative path) is relative to the compilation directory.
                     let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
+                    if let Some(ref comp_dir) = unit.comp_dir {
+                        path.push(comp_dir.to_string_lossy()?.as_ref());
+                    }
+                    if let Some(row_file) = row.file(header) {
+                        if let Some(dir) = row_file.directory(header) {
+                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy()?.as_ref());
                         }
                         path.push(
                             dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
+                                .attr_string(&unit, row_file.path_name())?
+                                .to_string_lossy()?
                                 .as_ref(),
                         );
                     }
-
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
-
-                    // Determine line/column. DWARF line/column is never 0, so we use that
-                    // but other applications may want to display this differently.
-                    let line = row.line().unwrap_or(0);
-
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
-                        });
-                    }
+                    let matches = path == unit_path;
+                    in_unit.insert(row.file_index(), matches);
+                    matches
                 }
+            };
+
+            // Determine line/column. DWARF line/column is never 0, so we use that
+            // but other applications may want to display this differently.
+            let line = row.line().unwrap_or(0);
+
+            if in_unit {
+                file.lines.push(Line {
+                    file: file.name.clone(),
+                    number: line.try_into().unwrap(),
+                    address,
+                });
             }
         }
     }
-    Ok(compilation_units)
+    Ok((file, offset_to_type))
 }
 
 #[derive(Debug, Clone)]
@@ -284,14 +422,344 @@ impl From<std::fmt::Error> for Error {
     }
 }
 
-impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
-    Endian: gimli::Endianity + Send + Sync
-{
+impl<Endian> Reader for gimli::EndianRcSlice<Endian> where Endian: gimli::Endianity {}
+
+trait Reader: gimli::Reader<Offset = usize> {}
+
+/// Returns the DIEs that entry's DW_AT_abstract_origin or DW_AT_specification refers to, and so
+/// on: e.g. an inlined method's abstract instance, then its declaration.
+fn origins<'unit, R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &'unit gimli::Unit<R>,
+) -> Vec<gimli::DebuggingInformationEntry<'unit, 'unit, R>> {
+    let mut origins = Vec::new();
+    let mut next = origin_offset(entry);
+    // A few levels are all compilers produce; the limit guards against cycles
+    while let (Some(offset), true) = (next, origins.len() < 4) {
+        let origin = match unit.header.entry(&unit.abbreviations, offset) {
+            Ok(origin) => origin,
+            Err(_) => break,
+        };
+        next = origin_offset(&origin);
+        origins.push(origin);
+    }
+    origins
+}
+
+fn origin_offset<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Option<UnitOffset> {
+    for name in &[gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        if let Ok(Some(gimli::AttributeValue::UnitRef(offset))) = entry.attr_value(*name) {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Whether a DW_AT_frame_base is rsp (DW_OP_reg7), as rustc emits, rather than the CFA.
+fn frame_base_register<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+) -> Option<u16> {
+    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+        let mut pc = data.0.clone();
+        if let Ok(gimli::Operation::Register { register }) =
+            gimli::Operation::parse(&mut pc, unit.encoding())
+        {
+            return Some(register.0);
+        }
+    }
+    None
 }
 
-trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
+/// The structs, and the parts of Rust enums, enclosing a DIE while load_types reads members.
+enum TypeParent {
+    /// The offset of an array, whose subranges give its length
+    Array(usize),
+    /// The offset of a struct
+    Struct(usize),
+    /// The offset of a Rust enum, and of the member its DW_AT_discr refers to
+    VariantPart(usize, Option<usize>),
+    /// The offset of a Rust enum, and the discriminant value selecting the variant
+    Variant(usize, Option<u64>),
+}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// Collects every type in unit, keyed by offset. This is done in a pass of its own so that
+/// variables can refer to types declared after them, and members to types declared anywhere in
+/// the unit.
+fn load_types<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+) -> Result<HashMap<usize, Type>, Error> {
+    let mut types: HashMap<usize, Type> = HashMap::new();
+    let mut rust = false;
+    let mut depth = 0;
+    let mut parents: Vec<(isize, TypeParent)> = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while parents
+            .last()
+            .map_or(false, |(parent_depth, _)| *parent_depth >= depth)
+        {
+            parents.pop();
+        }
+        let offset = section_offset(entry.offset(), unit);
+        let name = match entry.attr_value(gimli::DW_AT_name)? {
+            Some(value) => dwarf
+                .attr_string(unit, value)?
+                .to_string_lossy()?
+                .to_string(),
+            None => String::new(),
+        };
+        let size = entry
+            .attr(gimli::DW_AT_byte_size)?
+            .and_then(|attr| attr.udata_value())
+            .unwrap_or(0) as usize;
+        let target = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(target)) => Some(section_offset(target, unit)),
+            Some(gimli::AttributeValue::DebugInfoRef(target)) => Some(target.0),
+            _ => None,
+        };
+        let parent = parents.last().map(|(_, parent)| parent);
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                if let Some(gimli::AttributeValue::Language(language)) =
+                    entry.attr_value(gimli::DW_AT_language)?
+                {
+                    rust = language == gimli::DW_LANG_Rust;
+                }
+            }
+            gimli::DW_TAG_base_type | gimli::DW_TAG_enumeration_type => {
+                types.insert(offset, Type::new(name, size));
+            }
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => {
+                let size = if size == 0 { 8 } else { size };
+                let kind = TypeKind::Pointer(target);
+                types.insert(offset, Type { name, size, kind });
+            }
+            gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => {
+                let kind = match target {
+                    Some(target) => TypeKind::Alias(target),
+                    None => TypeKind::Base,
+                };
+                let name = if name.is_empty() && target.is_none() {
+                    "void".to_string()
+                } else {
+                    name
+                };
+                types.insert(offset, Type { name, size, kind });
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+                let kind = TypeKind::Struct {
+                    members: Vec::new(),
+                    template_params: Vec::new(),
+                    rust,
+                };
+                types.insert(offset, Type { name, size, kind });
+                parents.push((depth, TypeParent::Struct(offset)));
+            }
+            gimli::DW_TAG_array_type => {
+                if let Some(element) = target {
+                    let kind = TypeKind::Array { element, count: 0 };
+                    types.insert(offset, Type { name, size, kind });
+                    parents.push((depth, TypeParent::Array(offset)));
+                }
+            }
+            gimli::DW_TAG_subrange_type => {
+                if let Some(TypeParent::Array(array)) = parent {
+                    let count = match entry.attr(gimli::DW_AT_count)? {
+                        Some(attr) => attr.udata_value(),
+                        None => entry
+                            .attr(gimli::DW_AT_upper_bound)?
+                            .and_then(|attr| attr.udata_value())
+                            .map(|upper_bound| upper_bound + 1),
+                    };
+                    // Multidimensional arrays are read as one long array
+                    if let Some(TypeKind::Array { count: total, .. }) =
+                        types.get_mut(array).map(|array| &mut array.kind)
+                    {
+                        let count = count.unwrap_or(0) as usize;
+                        *total = if *total == 0 { count } else { *total * count };
+                    }
+                }
+            }
+            gimli::DW_TAG_variant_part => {
+                if let Some(TypeParent::Struct(owner)) = parent {
+                    let owner = *owner;
+                    let discriminant = match entry.attr_value(gimli::DW_AT_discr)? {
+                        Some(gimli::AttributeValue::UnitRef(member)) => {
+                            Some(section_offset(member, unit))
+                        }
+                        _ => None,
+                    };
+                    if let Some(owner_type) = types.get_mut(&owner) {
+                        owner_type.kind = TypeKind::Enum {
+                            discriminant: None,
+                            variants: Vec::new(),
+                        };
+                    }
+                    parents.push((depth, TypeParent::VariantPart(owner, discriminant)));
+                }
+            }
+            gimli::DW_TAG_variant => {
+                if let Some(TypeParent::VariantPart(owner, _)) = parent {
+                    let owner = *owner;
+                    let value =
+                        entry
+                            .attr(gimli::DW_AT_discr_value)?
+                            .and_then(|attr| match attr.value() {
+                                gimli::AttributeValue::Sdata(value) => Some(value as u64),
+                                _ => attr.udata_value(),
+                            });
+                    parents.push((depth, TypeParent::Variant(owner, value)));
+                }
+            }
+            gimli::DW_TAG_member => {
+                // Static members take up no space in the struct
+                if entry.attr(gimli::DW_AT_external)?.is_some()
+                    || entry.attr(gimli::DW_AT_declaration)?.is_some()
+                {
+                    continue;
+                }
+                let entity_type = match target {
+                    Some(target) => target,
+                    None => continue,
+                };
+                // Union members have no location, and overlap at 0
+                let member_offset = entry
+                    .attr(gimli::DW_AT_data_member_location)?
+                    .and_then(|attr| attr.udata_value())
+                    .unwrap_or(0) as usize;
+                let member = Member {
+                    name,
+                    offset: member_offset,
+                    entity_type,
+                };
+                let (owner, variant) = match parent {
+                    Some(TypeParent::Struct(owner)) => (*owner, None),
+                    Some(TypeParent::VariantPart(owner, discriminant)) => {
+                        if *discriminant != Some(offset) {
+                            continue;
+                        }
+                        (*owner, None)
+                    }
+                    Some(TypeParent::Variant(owner, value)) => (*owner, Some(*value)),
+                    _ => continue,
+                };
+                match (types.get_mut(&owner).map(|owner| &mut owner.kind), variant) {
+                    (Some(TypeKind::Struct { members, .. }), None) => members.push(member),
+                    (Some(TypeKind::Enum { discriminant, .. }), None) => {
+                        *discriminant = Some(member)
+                    }
+                    (Some(TypeKind::Enum { variants, .. }), Some(value)) => {
+                        variants.push(Variant {
+                            discriminant: value,
+                            member,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            gimli::DW_TAG_template_type_parameter => {
+                if let (Some(TypeParent::Struct(owner)), Some(target)) = (parent, target) {
+                    if let Some(TypeKind::Struct {
+                        template_params, ..
+                    }) = types.get_mut(owner).map(|owner| &mut owner.kind)
+                    {
+                        template_params.push(target);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Name the types that are only described by what they refer to, like int * and char [16],
+    // and size the ones whose size comes from what they refer to, like typedefs and arrays
+    let derived: Vec<(usize, String, usize)> = types
+        .iter()
+        .filter(|(_, entity_type)| entity_type.name.is_empty() || entity_type.size == 0)
+        .map(|(offset, _)| {
+            (
+                *offset,
+                derived_name(&types, Some(*offset), 0),
+                derived_size(&types, *offset, 0),
+            )
+        })
+        .collect();
+    for (offset, name, size) in derived {
+        let entity_type = types.get_mut(&offset).unwrap();
+        if entity_type.name.is_empty() {
+            entity_type.name = name;
+        }
+        if entity_type.size == 0 {
+            entity_type.size = size;
+        }
+    }
+    Ok(types)
+}
+
+/// Types referring to each other deeper than this are assumed to form a cycle.
+const MAX_TYPE_DEPTH: usize = 8;
+
+fn derived_name(types: &HashMap<usize, Type>, offset: Option<usize>, depth: usize) -> String {
+    let entity_type = match offset.and_then(|offset| types.get(&offset)) {
+        Some(entity_type) => entity_type,
+        None => return "void".to_string(),
+    };
+    if !entity_type.name.is_empty() || depth > MAX_TYPE_DEPTH {
+        return entity_type.name.clone();
+    }
+    match entity_type.kind {
+        TypeKind::Pointer(target) => format!("{} *", derived_name(types, target, depth + 1)),
+        TypeKind::Array { element, count } => format!(
+            "{} [{}]",
+            derived_name(types, Some(element), depth + 1),
+            count
+        ),
+        TypeKind::Alias(target) => derived_name(types, Some(target), depth + 1),
+        _ => "<anonymous>".to_string(),
+    }
+}
+
+fn derived_size(types: &HashMap<usize, Type>, offset: usize, depth: usize) -> usize {
+    let entity_type = match types.get(&offset) {
+        Some(entity_type) => entity_type,
+        None => return 0,
+    };
+    if entity_type.size != 0 || depth > MAX_TYPE_DEPTH {
+        return entity_type.size;
+    }
+    match entity_type.kind {
+        TypeKind::Array { element, count } => derived_size(types, element, depth + 1) * count,
+        TypeKind::Alias(target) => derived_size(types, target, depth + 1),
+        _ => 0,
+    }
+}
+
+/// Converts an offset within unit to one within .debug_info, which is what DW_AT_type values
+/// resolve to (see get_attr_value) and what types are keyed by.
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+    }
+}
+
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
+    // A location list (.debug_loc, or .debug_loclists in DWARF 5) places the variable
+    // differently at different PCs, which deet can't follow
+    if let gimli::AttributeValue::LocationListsRef(_)
+    | gimli::AttributeValue::DebugLocListsIndex(_) = attr.value()
+    {
+        return Some(Location::OptimizedOut);
+    }
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
         let mut pc = data.0.clone();
@@ -304,6 +772,11 @@ fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) ->
                     gimli::Operation::Address { address } => {
                         return Some(Location::Address(address.try_into().unwrap()));
                     }
+                    // DW_OP_addrx, which DWARF 5 compilers use for globals
+                    gimli::Operation::AddressIndex { index } => {
+                        let address = dwarf.address(unit, index).ok()?;
+                        return Some(Location::Address(address.try_into().unwrap()));
+                    }
                     _ => {}
                 }
             }
@@ -344,8 +817,20 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        // DWARF 5 strings in .debug_line_str, or found through .debug_str_offsets
+        gimli::AttributeValue::DebugLineStrRef(_)
+        | gimli::AttributeValue::DebugStrOffsetsIndex(_) => Ok(DebugValue::Str(
+            dwarf
+                .attr_string(unit, value)?
+                .to_string_lossy()?
+                .to_string(),
+        )),
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
+        // A DWARF 5 address in .debug_addr
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Ok(DebugValue::Uint(dwarf.address(unit, index)?))
+        }
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
 
         gimli::AttributeValue::String(s) => {
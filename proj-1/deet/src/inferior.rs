@@ -3,14 +3,19 @@ use nix::sys::signal;
 use nix::sys::signal::Signal::SIGTRAP;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
 use std::mem::size_of;
+use std::os::unix::io::RawFd;
 use std::os::unix::process::CommandExt;
-use std::process::Child;
 use std::process::Command;
 
-use crate::dwarf_data::DwarfData;
+use crate::debugger_command::ProgramArgs;
+use crate::dwarf_data::{DwarfData, Line};
+use crate::remote::Remote;
+use crate::solib::{self, Library};
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -24,39 +29,214 @@ pub enum Status {
     /// process.
     Signaled(signal::Signal),
 
+    /// Indicates inferior stopped on entry to a system call (while syscalls are being traced).
+    /// Contains the system call number and the current instruction pointer.
+    SyscallEntry(usize, usize),
+
+    /// Indicates inferior stopped on return from a system call. Contains the system call number
+    /// and the current instruction pointer.
+    SyscallExit(usize, usize),
+
     Continued,
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
+    // deet ignores SIGINT, and ignored signals stay ignored across exec
+    unsafe { signal::signal(signal::Signal::SIGINT, signal::SigHandler::SigDfl) }.or(Err(
+        std::io::Error::new(std::io::ErrorKind::Other, "resetting SIGINT failed"),
+    ))?;
     ptrace::traceme().or(Err(std::io::Error::new(
         std::io::ErrorKind::Other,
         "ptrace TRACEME failed",
     )))
 }
+/// Starts a new session with stdin (the inferior's tty) as its controlling terminal, so that job
+/// control and ^C on that terminal go to the inferior.
+fn child_take_tty() -> Result<(), std::io::Error> {
+    nix::unistd::setsid().or(Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "setsid failed",
+    )))?;
+    // This fails if the terminal already belongs to another session (e.g. a shell is running in
+    // it). The inferior can still read and write it, just without job control.
+    unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) };
+    Ok(())
+}
+
+/// How to start the inferior, as configured by run, set args, set/unset environment and tty.
+#[derive(Default)]
+pub struct RunConfig {
+    pub program_args: ProgramArgs,
+    /// Changes to the environment deet was started with: Some sets a variable, None removes it
+    pub environment: BTreeMap<String, Option<String>>,
+    /// Start from an empty environment instead (after "unset environment" with no name)
+    pub clear_environment: bool,
+    /// Terminal for the inferior's stdin, stdout and stderr, unless they are redirected
+    pub tty: Option<String>,
+}
+
+fn open_file(path: &str, options: &OpenOptions) -> Option<File> {
+    match options.open(path) {
+        Ok(file) => Some(file),
+        Err(e) => {
+            println!("Could not open {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// A stack frame found by Inferior::unwind. A call inlined into a function gets a frame of its
+/// own, sharing the registers of the function's frame.
+#[derive(Clone)]
+pub struct Frame {
+    pub function: String,
+    pub line: Option<Line>,
+    pub instruction_ptr: usize,
+    pub base_ptr: usize,
+    /// rsp in this frame, for functions (like rustc's) whose variables are relative to it
+    pub stack_ptr: usize,
+    /// 0 for a real call, otherwise how many inlined calls deep the function is
+    pub inline_depth: usize,
+    /// Whether this is a caller's frame (not the innermost function, or one inlined into it),
+    /// whose instruction_ptr is a return address
+    pub caller: bool,
+}
+
+impl Frame {
+    /// The address to look the frame's function, line and variables up at. In a caller, that is
+    /// the call before the return address, which may be on the line after the call (or past the
+    /// end of the function, if the call doesn't return).
+    pub fn lookup_ptr(&self) -> usize {
+        if self.caller {
+            self.instruction_ptr - 1
+        } else {
+            self.instruction_ptr
+        }
+    }
+
+    /// e.g. "func (samples/function_calls.c:5)", or "func+0x1a (0x401136)" without DWARF.
+    /// Addresses in shared libraries are looked up in the library's symbols.
+    pub fn describe(&self, debug_data: &DwarfData, libraries: &[Library]) -> String {
+        let lookup_ptr = self.lookup_ptr();
+        let (debug_data, bias) = solib::debug_data_at(debug_data, libraries, lookup_ptr);
+        match &self.line {
+            Some(line) => format!("{} ({})", self.function, line),
+            None => match debug_data.get_symbol_from_addr(lookup_ptr - bias) {
+                Some((name, offset)) => {
+                    let offset = offset + (self.instruction_ptr - lookup_ptr);
+                    format!("{}+{:#x} ({:#x})", name, offset, self.instruction_ptr)
+                }
+                None => format!("{} ({:#x})", self.function, self.instruction_ptr),
+            },
+        }
+    }
+}
+
+/// A function call in progress in the inferior: where it returns to, and the registers from
+/// before it.
+pub struct Call {
+    return_addr: usize,
+    saved: libc::user_regs_struct,
+    saved_fp: Option<libc::user_fpregs_struct>,
+}
+
+/// How far the innermost function has got through setting up its frame pointer (see
+/// Inferior::prologue_step).
+enum PrologueStep {
+    /// Nothing has run yet: the return address is at rsp
+    Entry,
+    /// rbp has been pushed, but not yet set to rsp
+    Pushed,
+}
+
+/// How a function called in the inferior with start_call ended.
+pub enum CallOutcome {
+    /// The function returned: rax, and the low 8 bytes of xmm0 (where floating point values are
+    /// returned)
+    Returned(u64, u64),
+    /// The inferior stopped before the function returned (at a breakpoint, or on a signal), or
+    /// exited. The call was abandoned, with the registers restored if it is still alive.
+    Abandoned(Status),
+}
+
+/// Registers that hold the first six integer arguments of a call, in the System V x86-64 ABI
+pub const ARGUMENT_REGISTERS: usize = 6;
+
+/// Bytes below rsp that a function may use without moving rsp, which a call mustn't overwrite
+const RED_ZONE: usize = 128;
+
+/// An int3 written over the inferior's code. Permanent breakpoints and one-shot ones (for tbreak,
+/// until and advance) at the same address share it, so orig_byte is only saved once.
 #[derive(Clone)]
 pub struct Breakpoint {
-    addr: usize,
     orig_byte: u8,
+    /// Set by breakpoint: stays until the inferior exits
+    permanent: bool,
+    /// How many one-shot breakpoints are set here; each is removed by remove_one_shot_breakpoint
+    one_shots: usize,
 }
 
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
     breakpoints: HashMap<usize, Breakpoint>,
+    /// Whether goon stops at system calls (see set_trace_syscalls)
+    trace_syscalls: bool,
+    /// Set for a process run by a remote stub (see connect), which then does everything ptrace
+    /// would
+    remote: Option<Remote>,
+    /// Whether the last resume was a single step. A remote stub doesn't say whether a SIGTRAP
+    /// came from an int3 or a step, which wait needs to know.
+    stepped: Cell<bool>,
+    /// Whether the inferior is between the entry and exit stops of a system call. The two stops
+    /// look the same, so (like strace) wait tells them apart by counting.
+    in_syscall: Cell<bool>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
     /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
+    pub fn new(target: &str, config: &RunConfig) -> Option<Inferior> {
         // TODO: implement me!
         //can not put it like this: let cmd = Command::new(program).args(args)
         //because, Command::new() create a new instance of Command
         //but it is not owned by anyone, it is a temporary variable
         //what args return is a reference to the temporary variable. it will not live long enough.
         let mut cmd = Command::new(target);
-        cmd.args(args);
+        cmd.args(&config.program_args.args);
+        if config.clear_environment {
+            cmd.env_clear();
+        }
+        for (name, value) in &config.environment {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+        if let Some(tty) = &config.tty {
+            let terminal = open_file(tty, OpenOptions::new().read(true).write(true))?;
+            cmd.stdin(terminal.try_clone().ok()?);
+            cmd.stdout(terminal.try_clone().ok()?);
+            cmd.stderr(terminal);
+            unsafe {
+                cmd.pre_exec(child_take_tty);
+            }
+        }
+        if let Some(path) = &config.program_args.stdin {
+            cmd.stdin(open_file(path, OpenOptions::new().read(true))?);
+        }
+        if let Some(path) = &config.program_args.stdout {
+            let append = config.program_args.append;
+            cmd.stdout(open_file(
+                path,
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(append)
+                    .truncate(!append),
+            )?);
+        }
         unsafe {
             cmd.pre_exec(child_traceme);
         }
@@ -76,24 +256,123 @@ impl Inferior {
                 return None;
             }
         }
+        ptrace::setoptions(pid, PTRACE_OPTIONS).ok()?;
         Some(Inferior {
-            child,
+            pid,
             breakpoints: HashMap::new(),
+            trace_syscalls: false,
+            remote: None,
+            stepped: Cell::new(false),
+            in_syscall: Cell::new(false),
         })
     }
-    pub fn goon(&self) -> Result<Status, nix::Error> {
-        //get register id. see whether it has been stop.
 
-        ptrace::cont(self.pid(), None)?;
+    /// Connects to a GDB remote protocol stub (like gdbserver) at address ("host:port") and
+    /// debugs the process it controls. Returns None if the connection fails.
+    pub fn connect(address: &str) -> Option<Inferior> {
+        match Remote::connect(address) {
+            Ok(remote) => Some(Inferior {
+                pid: Pid::from_raw(remote.pid()),
+                breakpoints: HashMap::new(),
+                trace_syscalls: false,
+                remote: Some(remote),
+                stepped: Cell::new(false),
+                in_syscall: Cell::new(false),
+            }),
+            Err(e) => {
+                println!("{}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    /// The socket connected to the remote stub, for a process run by one.
+    pub fn remote_fd(&self) -> Option<RawFd> {
+        self.remote.as_ref().map(Remote::raw_fd)
+    }
+
+    /// Whether this is a process run by a remote stub.
+    pub fn is_remote(&self) -> bool {
+        self.remote.is_some()
+    }
+    /// Resumes the inferior, delivering signal to it if given.
+    pub fn goon(&self, signal: Option<signal::Signal>) -> Result<Status, nix::Error> {
+        //get register id. see whether it has been stop.
+        self.stepped.set(false);
+        if let Some(remote) = &self.remote {
+            if self.trace_syscalls {
+                return Err(nix::Error::UnsupportedOperation);
+            }
+            remote.resume(false, signal)?;
+        } else if self.trace_syscalls {
+            ptrace::syscall(self.pid(), signal)?;
+        } else {
+            // A system call it is in the middle of finishes without an exit stop
+            self.in_syscall.set(false);
+            ptrace::cont(self.pid(), signal)?;
+        }
         Ok(Status::Continued)
     }
+    /// Makes goon stop the inferior at every system call entry and exit, which wait reports as
+    /// SyscallEntry and SyscallExit.
+    pub fn set_trace_syscalls(&mut self, trace_syscalls: bool) {
+        self.trace_syscalls = trace_syscalls;
+    }
+    pub fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        match &self.remote {
+            Some(remote) => remote.get_registers(),
+            None => ptrace::getregs(self.pid()),
+        }
+    }
+    pub fn set_registers(&self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        match &self.remote {
+            Some(remote) => remote.set_registers(regs),
+            None => ptrace::setregs(self.pid(), regs),
+        }
+    }
+    /// The x87/MMX/SSE registers, which nix doesn't wrap.
+    pub fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        if self.is_remote() {
+            return Err(nix::Error::UnsupportedOperation);
+        }
+        let mut regs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.pid().as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut regs as *mut libc::user_fpregs_struct,
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        Ok(regs)
+    }
+    pub fn set_fp_registers(&self, regs: &libc::user_fpregs_struct) -> Result<(), nix::Error> {
+        if self.is_remote() {
+            return Err(nix::Error::UnsupportedOperation);
+        }
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_SETFPREGS,
+                self.pid().as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                regs as *const libc::user_fpregs_struct,
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        Ok(())
+    }
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
     }
     pub fn kill(&mut self) -> Result<Status, nix::Error> {
         println!("Killing running inferior (pid {})", self.pid());
-        self.child.kill().unwrap();
+        if let Some(remote) = &self.remote {
+            remote.kill()?;
+            return Ok(Status::Exited(0));
+        }
+        signal::kill(self.pid, signal::Signal::SIGKILL)?;
         match self.wait(None)? {
             Status::Signaled(_) => Ok(Status::Exited(0)),
             other => Err(nix::Error::InvalidUtf8),
@@ -104,99 +383,390 @@ impl Inferior {
         //     Err(e) => ,
         // }
     }
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        //println!("Hello world");
-        let reg = ptrace::getregs(self.pid())?;
-        println!("reg.rip as usize {}", reg.rip as usize);
-        // let line_number = debug_data.get_line_from_addr(reg.rip as usize).unwrap();
-        // let function_name = debug_data.get_function_from_addr(reg.rip as usize).unwrap();
-        // println!("{} ({})", function_name, line_number);
-        let mut instruction_ptr = reg.rip as usize;
-        let mut base_ptr = reg.rbp;
-        while true {
-            let line_number = debug_data.get_line_from_addr(instruction_ptr);
-            let function_name = debug_data.get_function_from_addr(instruction_ptr);
-            match line_number {
-                Some(line) => match function_name {
-                    Some(function) => {
-                        println!("{} ({})", function, line);
-                        if function == "main" {
-                            break;
-                        }
-                    }
-                    None => {
-                        return Ok(());
-                    }
-                },
-                None => {
-                    return Ok(());
+    /// Removes all breakpoints and lets the inferior run on untraced.
+    pub fn detach(mut self) -> Result<(), nix::Error> {
+        self.clear_breakpoints()?;
+        match &self.remote {
+            Some(remote) => remote.detach(),
+            None => ptrace::detach(self.pid(), None),
+        }
+    }
+    /// Forks the stopped inferior by having it run a fork system call in place of its next
+    /// instruction, the way gdb's checkpoint does. Both processes are put back as they were, and
+    /// the copy is returned stopped under ptrace.
+    pub fn checkpoint(&mut self) -> Result<(Inferior, Vec<signal::Signal>), nix::Error> {
+        if self.is_remote() {
+            return Err(nix::Error::UnsupportedOperation);
+        }
+        let regs = self.get_registers()?;
+        let rip = regs.rip as usize;
+        let mut orig_code = Vec::new();
+        for (offset, byte) in SYSCALL_INSTRUCTION.iter().enumerate() {
+            orig_code.push(self.write_byte(rip + offset, *byte)?);
+        }
+        let mut fork_regs = regs;
+        fork_regs.rax = libc::SYS_fork as u64;
+        self.set_registers(fork_regs)?;
+        ptrace::setoptions(
+            self.pid,
+            PTRACE_OPTIONS | ptrace::Options::PTRACE_O_TRACEFORK,
+        )?;
+        let mut signals = Vec::new();
+        let forked = self.step_over_fork(&mut signals);
+        ptrace::setoptions(self.pid, PTRACE_OPTIONS)?;
+        for (offset, byte) in orig_code.iter().enumerate() {
+            self.write_byte(rip + offset, *byte)?;
+        }
+        self.set_registers(regs)?;
+
+        let mut copy = Inferior {
+            pid: forked?,
+            breakpoints: self.breakpoints.clone(),
+            trace_syscalls: self.trace_syscalls,
+            remote: None,
+            stepped: Cell::new(false),
+            in_syscall: Cell::new(false),
+        };
+        // The copy was stopped with the fork also patched in, and with fork's return value in rax
+        copy.wait(None)?;
+        ptrace::setoptions(copy.pid, PTRACE_OPTIONS)?;
+        for (offset, byte) in orig_code.iter().enumerate() {
+            copy.write_byte(rip + offset, *byte)?;
+        }
+        copy.set_registers(regs)?;
+        Ok((copy, signals))
+    }
+
+    /// Single-steps the fork system call set up by checkpoint, returning the new process's pid.
+    /// Signals that arrive meanwhile are added to signals, for the caller to deliver later.
+    fn step_over_fork(&self, signals: &mut Vec<signal::Signal>) -> Result<Pid, nix::Error> {
+        // The fork event stop comes first; stepping on from it finishes the system call
+        let forked = loop {
+            ptrace::step(self.pid, None)?;
+            match waitpid(self.pid, None)? {
+                WaitStatus::PtraceEvent(_pid, _signal, event)
+                    if event == libc::PTRACE_EVENT_FORK =>
+                {
+                    break Pid::from_raw(ptrace::getevent(self.pid)? as i32);
                 }
+                // A signal arrived before the system call ran, typically SIGCHLD from an earlier
+                // copy of a checkpoint being killed. The step is retried.
+                WaitStatus::Stopped(_pid, signal) if signal != SIGTRAP => signals.push(signal),
+                _ => return Err(nix::Error::Sys(nix::errno::Errno::ECHILD)),
+            }
+        };
+        loop {
+            ptrace::step(self.pid, None)?;
+            match waitpid(self.pid, None)? {
+                WaitStatus::Stopped(_pid, signal) if signal != SIGTRAP => signals.push(signal),
+                _ => return Ok(forked),
             }
+        }
+    }
 
-            instruction_ptr =
-                ptrace::read(self.pid(), (base_ptr + 8) as ptrace::AddressType)? as usize;
-            base_ptr = ptrace::read(self.pid(), base_ptr as ptrace::AddressType)? as u64;
+    pub fn print_backtrace(
+        &self,
+        debug_data: &DwarfData,
+        libraries: &[Library],
+    ) -> Result<(), nix::Error> {
+        for (index, frame) in self.unwind(debug_data, libraries)?.iter().enumerate() {
+            println!("#{} {}", index, frame.describe(debug_data, libraries));
         }
         Ok(())
     }
 
+    /// Walks the chain of saved frame pointers from the current frame up to main. Frames in
+    /// shared libraries are looked up in the library's debugging information.
+    pub fn unwind(
+        &self,
+        debug_data: &DwarfData,
+        libraries: &[Library],
+    ) -> Result<Vec<Frame>, nix::Error> {
+        let reg = self.get_registers()?;
+        let mut frames = Vec::new();
+        let mut instruction_ptr = reg.rip as usize;
+        let mut base_ptr = reg.rbp as usize;
+        let mut stack_ptr = reg.rsp as usize;
+        let mut prologue = self.prologue_step(debug_data, libraries, instruction_ptr);
+        loop {
+            let caller = !frames.is_empty();
+            let lookup_ptr = if caller {
+                instruction_ptr - 1
+            } else {
+                instruction_ptr
+            };
+            let (data, bias) = solib::debug_data_at(debug_data, libraries, lookup_ptr);
+            let mut functions = data.get_inline_frames(lookup_ptr - bias);
+            if functions.is_empty() {
+                // No DWARF for this address, fall back to the ELF symbol table
+                match data.get_symbol_from_addr(lookup_ptr - bias) {
+                    Some((name, _offset)) => functions.push((name, None)),
+                    None => break,
+                }
+            }
+            let count = functions.len();
+            for (index, (function, line)) in functions.into_iter().enumerate() {
+                frames.push(Frame {
+                    function,
+                    line,
+                    instruction_ptr,
+                    base_ptr,
+                    stack_ptr,
+                    inline_depth: count - 1 - index,
+                    caller,
+                });
+            }
+            if frames.last().unwrap().function == "main" {
+                break;
+            }
+
+            // Where the caller's rbp is (or would be) saved, with the return address above it.
+            // Until the innermost function has run push %rbp, that is just below rsp, and the
+            // caller's rbp is still in the register.
+            let (frame_ptr, pushed) = match prologue.take() {
+                Some(PrologueStep::Entry) => (stack_ptr - 8, false),
+                Some(PrologueStep::Pushed) => (stack_ptr, true),
+                None if base_ptr == 0 => break,
+                None => (base_ptr, true),
+            };
+            // Code built without frame pointers (like most Rust) leaves rbp holding anything, so
+            // a chain that runs into unreadable memory just ends the backtrace
+            instruction_ptr = match self.read_word(frame_ptr + 8) {
+                Ok(return_addr) => return_addr as usize,
+                Err(_) => break,
+            };
+            if pushed {
+                base_ptr = match self.read_word(frame_ptr) {
+                    Ok(caller_base_ptr) => caller_base_ptr as usize,
+                    Err(_) => break,
+                };
+            }
+            stack_ptr = frame_ptr + 16;
+        }
+        Ok(frames)
+    }
+
+    /// Whether instruction_ptr is at the start of its function, before the usual push %rbp;
+    /// mov %rsp,%rbp has set up its frame (e.g. at a breakpoint set on a function without
+    /// DWARF), and how much of that has run.
+    fn prologue_step(
+        &self,
+        debug_data: &DwarfData,
+        libraries: &[Library],
+        instruction_ptr: usize,
+    ) -> Option<PrologueStep> {
+        const ENDBR64: [u8; 4] = [0xf3, 0x0f, 0x1e, 0xfa];
+        const PUSH_RBP: u8 = 0x55;
+        let (debug_data, bias) = solib::debug_data_at(debug_data, libraries, instruction_ptr);
+        let (start, _) = debug_data.get_function_range_from_addr(instruction_ptr - bias)?;
+        let start = start + bias;
+        let len = instruction_ptr - start;
+        if len > ENDBR64.len() + 1 {
+            return None;
+        }
+        let code = self.read_memory(start, len).ok()?;
+        let code = if code.starts_with(&ENDBR64) {
+            &code[ENDBR64.len()..]
+        } else {
+            &code[..]
+        };
+        match code {
+            [] => Some(PrologueStep::Entry),
+            [PUSH_RBP] => Some(PrologueStep::Pushed),
+            _ => None,
+        }
+    }
+
     pub fn breakpoint(&mut self, addr: &usize) -> Result<(), nix::Error> {
-        if self.breakpoints.contains_key(addr) {
-            return Ok(());
+        self.insert_breakpoint(*addr)?.permanent = true;
+        Ok(())
+    }
+
+    /// Sets a breakpoint that is taken out again with remove_one_shot_breakpoint, leaving any
+    /// other breakpoint at the same address in place.
+    pub fn one_shot_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        self.insert_breakpoint(addr)?.one_shots += 1;
+        Ok(())
+    }
+
+    pub fn remove_one_shot_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        let bp = match self.breakpoints.get_mut(&addr) {
+            Some(bp) if bp.one_shots > 0 => bp,
+            _ => return Err(nix::Error::Sys(nix::errno::Errno::EBADE)),
+        };
+        bp.one_shots -= 1;
+        if bp.one_shots == 0 && !bp.permanent {
+            let orig_byte = bp.orig_byte;
+            self.breakpoints.remove(&addr);
+            self.write_byte(addr, orig_byte)?;
         }
-        let orig_val = self.write_byte(addr.clone(), 0xcc)?;
-        let b = Breakpoint {
-            addr: addr.clone(),
-            orig_byte: orig_val,
+        Ok(())
+    }
+
+    /// Removes the permanent breakpoint at addr, leaving any one-shot breakpoints there in place.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        let bp = match self.breakpoints.get_mut(&addr) {
+            Some(bp) if bp.permanent => bp,
+            _ => return Err(nix::Error::Sys(nix::errno::Errno::EBADE)),
         };
-        //TODO: Might need to handle if k exist.
-        self.breakpoints.insert(addr.clone(), b);
+        bp.permanent = false;
+        if bp.one_shots == 0 {
+            let orig_byte = bp.orig_byte;
+            self.breakpoints.remove(&addr);
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
 
+    /// Removes every breakpoint, permanent or one-shot.
+    pub fn clear_breakpoints(&mut self) -> Result<(), nix::Error> {
+        for (addr, bp) in std::mem::take(&mut self.breakpoints) {
+            self.write_byte(addr, bp.orig_byte)?;
+        }
         Ok(())
     }
+
+    /// Writes an int3 at addr unless one is already there, returning its entry.
+    fn insert_breakpoint(&mut self, addr: usize) -> Result<&mut Breakpoint, nix::Error> {
+        if !self.breakpoints.contains_key(&addr) {
+            let orig_byte = self.write_byte(addr, 0xcc)?;
+            self.breakpoints.insert(
+                addr,
+                Breakpoint {
+                    orig_byte,
+                    permanent: false,
+                    one_shots: 0,
+                },
+            );
+        }
+        Ok(self.breakpoints.get_mut(&addr).unwrap())
+    }
+
     pub fn continue_from_breakpoint(&mut self, addr: &usize) -> Result<(), nix::Error> {
-        //write back to addr
-        self.recover(addr)?;
-        //rip rewind
-        //println!("get reg");
-        let mut regs = ptrace::getregs(self.pid())?;
-        //println!("pre {}", regs.rip);
-        regs.rip = regs.rip - 1;
-        //println!("set reg");
-        ptrace::setregs(self.pid(), regs)?;
-        //step
-        //println!("step");
-        ptrace::step(self.pid(), None)?;
-        self.wait(None)?;
-        // let regs = ptrace::getregs(self.pid())?;
-        // println!("post {}", regs.rip);
-        //write back
-        //println!("breakpoint");
-        self.breakpoint(addr)?;
+        //rip was already rewound onto the breakpoint when the trap was reported (see wait)
+        self.step_over_breakpoint(*addr, None)?;
         Ok(())
     }
-    pub fn recover(&mut self, addr: &usize) -> Result<(), nix::Error> {
-        //println!("recover address: {}", addr);
-        let bp = match self.breakpoints.get(addr) {
-            Some(bp) => bp,
-            None => {
-                return Err(nix::Error::Sys(nix::errno::Errno::EBADE));
+
+    /// Executes the original instruction at the breakpoint at addr, then puts the int3 back.
+    fn step_over_breakpoint(
+        &mut self,
+        addr: usize,
+        signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
+        let orig_byte = self.breakpoints[&addr].orig_byte;
+        self.write_byte(addr, orig_byte)?;
+        self.step(signal)?;
+        let status = self.wait(None)?;
+        if let Status::Stopped(..) = status {
+            self.write_byte(addr, 0xcc)?;
+        }
+        Ok(status)
+    }
+
+    /// Executes a single instruction. If the inferior is stopped on a breakpoint, the original
+    /// instruction is executed and the breakpoint is re-inserted afterwards.
+    pub fn step_instruction(
+        &mut self,
+        signal: Option<signal::Signal>,
+    ) -> Result<Status, nix::Error> {
+        let rip = self.get_registers()?.rip as usize;
+        if !self.breakpoints.contains_key(&rip) {
+            self.step(signal)?;
+            return self.wait(None);
+        }
+        self.step_over_breakpoint(rip, signal)
+    }
+
+    /// Starts a call to the function at addr with integer arguments (at most ARGUMENT_REGISTERS
+    /// of them), following the System V x86-64 ABI, and continues the inferior into it. The
+    /// function returns to return_addr, where a breakpoint catches it, so that must be code that
+    /// the function won't run itself, such as the program's entry point. Once the inferior has
+    /// stopped, finish_call restores the registers.
+    pub fn start_call(
+        &mut self,
+        addr: usize,
+        args: &[u64],
+        return_addr: usize,
+    ) -> Result<Call, nix::Error> {
+        let saved = self.get_registers()?;
+        let saved_fp = self.get_fp_registers().ok();
+        let mut regs = saved;
+        // Leave the red zone alone, and align the stack so that it is 16-byte aligned just
+        // before the call pushes the return address, as the ABI requires
+        let stack_ptr = ((saved.rsp as usize - RED_ZONE) & !0xf) - size_of::<u64>();
+        self.write_memory(stack_ptr, &(return_addr as u64).to_le_bytes())?;
+        let mut arg_regs = [
+            &mut regs.rdi,
+            &mut regs.rsi,
+            &mut regs.rdx,
+            &mut regs.rcx,
+            &mut regs.r8,
+            &mut regs.r9,
+        ];
+        for (reg, arg) in arg_regs.iter_mut().zip(args) {
+            **reg = *arg;
+        }
+        regs.rsp = stack_ptr as u64;
+        regs.rip = addr as u64;
+        // Variadic functions (like printf) take the number of vector registers used in al
+        regs.rax = 0;
+        // Keeps the kernel from restarting a system call the inferior was stopped in
+        regs.orig_rax = u64::max_value();
+        self.set_registers(regs)?;
+        self.one_shot_breakpoint(return_addr)?;
+        self.goon(None)?;
+        Ok(Call {
+            return_addr,
+            saved,
+            saved_fp,
+        })
+    }
+
+    /// Ends a call started with start_call, given the status the inferior stopped (or exited)
+    /// with: the function returned if it stopped at the return address, and the call is
+    /// abandoned otherwise. Either way, the registers are restored if the inferior is alive.
+    pub fn finish_call(&mut self, call: Call, status: Status) -> Result<CallOutcome, nix::Error> {
+        let outcome = match status {
+            Status::Stopped(SIGTRAP, rip) if rip == call.return_addr => {
+                let rax = self.get_registers()?.rax;
+                let xmm0 = self.get_fp_registers().map_or(0, |fp| {
+                    u64::from(fp.xmm_space[0]) | u64::from(fp.xmm_space[1]) << 32
+                });
+                CallOutcome::Returned(rax, xmm0)
             }
+            Status::Exited(_) | Status::Signaled(_) => return Ok(CallOutcome::Abandoned(status)),
+            status => CallOutcome::Abandoned(status),
         };
-        let a = bp.clone();
-        self.breakpoints.remove(addr);
-        self.write_byte(a.addr.clone(), a.orig_byte.clone())?;
-        Ok(())
+        self.remove_one_shot_breakpoint(call.return_addr)?;
+        self.set_registers(call.saved)?;
+        if let Some(saved_fp) = call.saved_fp {
+            self.set_fp_registers(&saved_fp)?;
+        }
+        Ok(outcome)
+    }
+
+    /// Executes a single instruction, without regard for breakpoints.
+    fn step(&self, signal: Option<signal::Signal>) -> Result<(), nix::Error> {
+        self.stepped.set(true);
+        self.in_syscall.set(false);
+        match &self.remote {
+            Some(remote) => remote.resume(true, signal),
+            None => ptrace::step(self.pid(), signal),
+        }
     }
-    pub fn find_break_point(&self, debug_data: &DwarfData) -> Option<usize> {
-        let reg = ptrace::getregs(self.pid()).expect("get regegister failed");
+
+    pub fn has_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints.contains_key(&addr)
+    }
+    pub fn find_break_point(&self, debug_data: &DwarfData) -> Result<Option<usize>, nix::Error> {
+        let reg = self.get_registers()?;
         let rip = reg.rip as usize;
-        let bp_addr_cand = rip - 1;
-        //println!("current_location: {}", bp_addr_cand);
-        if self.breakpoints.contains_key(&bp_addr_cand) {
-            return Some(bp_addr_cand);
+        //println!("current_location: {}", rip);
+        if self.breakpoints.contains_key(&rip) {
+            return Ok(Some(rip));
         }
-        None
+        Ok(None)
     }
     pub fn _step_one(&self, _addr: &usize) -> Result<(), nix::Error> {
         ptrace::step(self.pid(), SIGTRAP)?;
@@ -207,24 +777,138 @@ impl Inferior {
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
     pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        if let Some(remote) = &self.remote {
+            return match remote.wait()? {
+                // A breakpoint's int3 was hit rather than stepped over: rewind as below
+                Status::Stopped(SIGTRAP, rip)
+                    if !self.stepped.get() && self.breakpoints.contains_key(&(rip - 1)) =>
+                {
+                    let mut regs = remote.get_registers()?;
+                    regs.rip -= 1;
+                    remote.set_registers(regs)?;
+                    Ok(Status::Stopped(SIGTRAP, rip - 1))
+                }
+                status => Ok(status),
+            };
+        }
         Ok(match waitpid(self.pid(), options)? {
             WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
             WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
+            // Only with WNOHANG: the inferior hasn't stopped
+            WaitStatus::StillAlive => Status::Continued,
             WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
+                let mut regs = ptrace::getregs(self.pid())?;
+                // After an int3, rip points just past the breakpoint. Rewind it so the inferior
+                // reports (and later resumes from) the breakpoint address itself.
+                if signal == SIGTRAP
+                    && ptrace::getsiginfo(self.pid())?.si_code == SI_KERNEL
+                    && self.breakpoints.contains_key(&(regs.rip as usize - 1))
+                {
+                    regs.rip -= 1;
+                    ptrace::setregs(self.pid(), regs)?;
+                }
                 Status::Stopped(signal, regs.rip as usize)
             }
+            WaitStatus::PtraceSyscall(_pid) => {
+                let regs = ptrace::getregs(self.pid())?;
+                // rax can't tell entry from exit: a call that fails with ENOSYS returns the
+                // -ENOSYS the kernel puts there on entry
+                let entering = !self.in_syscall.get();
+                self.in_syscall.set(entering);
+                if entering {
+                    Status::SyscallEntry(regs.orig_rax as usize, regs.rip as usize)
+                } else {
+                    Status::SyscallExit(regs.orig_rax as usize, regs.rip as usize)
+                }
+            }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 }
 
+/// Options every inferior is traced with: syscall stops are reported as PtraceSyscall rather than
+/// as a SIGTRAP.
+const PTRACE_OPTIONS: ptrace::Options = ptrace::Options::PTRACE_O_TRACESYSGOOD;
+
+/// The syscall instruction, which checkpoint writes over the inferior's code to make it fork.
+const SYSCALL_INSTRUCTION: [u8; 2] = [0x0f, 0x05];
+
+/// si_code of the SIGTRAP raised by an int3 instruction (as opposed to a single step).
+const SI_KERNEL: i32 = 0x80;
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
 impl Inferior {
+    /// Reads len bytes of the inferior's memory starting at addr. Bytes currently replaced by a
+    /// breakpoint's 0xcc are reported with their original value. If the range runs into unmapped
+    /// memory, only the readable prefix is returned.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut bytes = match &self.remote {
+            Some(remote) => remote.read_memory(addr, len)?,
+            None => self.read_words(addr, len)?,
+        };
+        for (bp_addr, bp) in self.breakpoints.iter() {
+            if *bp_addr >= addr && *bp_addr < addr + bytes.len() {
+                bytes[bp_addr - addr] = bp.orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Reads memory with ptrace, a word at a time, for read_memory.
+    fn read_words(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let start = align_addr_to_word(addr);
+        let mut bytes = Vec::new();
+        let mut word_addr = start;
+        while word_addr < addr + len {
+            match self.read_word(word_addr) {
+                Ok(word) => bytes.extend_from_slice(&word.to_le_bytes()),
+                Err(e) if bytes.len() <= addr - start => return Err(e),
+                Err(_) => break,
+            }
+            word_addr += size_of::<usize>();
+        }
+        let end = std::cmp::min(bytes.len(), addr - start + len);
+        Ok(bytes[addr - start..end].to_vec())
+    }
+
+    /// Reads the word at addr, which needn't be aligned.
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
+        if let Some(remote) = &self.remote {
+            let bytes = remote.read_memory(addr, size_of::<u64>())?;
+            let bytes = bytes.as_slice().try_into();
+            return bytes
+                .map(u64::from_le_bytes)
+                .or(Err(nix::Error::Sys(nix::errno::Errno::EFAULT)));
+        }
+        Ok(ptrace::read(self.pid(), addr as ptrace::AddressType)? as u64)
+    }
+
+    /// Writes bytes to the inferior's memory at addr. Where a breakpoint is set, the byte it
+    /// restores on removal is updated instead, so the breakpoint stays in place.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        for (offset, byte) in bytes.iter().enumerate() {
+            match self.breakpoints.get_mut(&(addr + offset)) {
+                Some(bp) => bp.orig_byte = *byte,
+                None => {
+                    self.write_byte(addr + offset, *byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        if let Some(remote) = &self.remote {
+            let orig_byte = remote.read_memory(addr, 1)?[0];
+            remote.write_memory(addr, &[val])?;
+            return Ok(orig_byte);
+        }
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
         let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
@@ -0,0 +1,109 @@
+//! Stopping a running inferior: with Ctrl+C while deet waits for it, or with the interrupt command
+//! while it runs in the background (see continue &). A local process is sent SIGSTOP, and a
+//! remote stub the ^C byte of the GDB remote protocol. The rest of the time deet ignores SIGINT;
+//! at the prompt, ^C is read as a key press instead.
+
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::unistd::Pid;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use crate::gdbserver;
+use crate::inferior::Inferior;
+
+/// Process the SIGINT handler stops, or 0
+static PROCESS: AtomicI32 = AtomicI32::new(0);
+/// Connection to the stub the SIGINT handler interrupts, or -1
+static REMOTE: AtomicI32 = AtomicI32::new(-1);
+/// Whether PROCESS is in deet's process group, so that ^C on the terminal reaches it as well
+static SHARES_TERMINAL: AtomicBool = AtomicBool::new(false);
+/// Set once the inferior has been asked to stop, until the stop is seen (see take_request)
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// How to stop an inferior.
+#[derive(Clone, Copy)]
+pub enum Target {
+    Process(Pid),
+    /// The (raw) socket connected to the stub
+    Remote(RawFd),
+}
+
+impl Target {
+    /// How to stop infer from outside, without going through the terminal.
+    pub fn of(infer: &Inferior) -> Target {
+        match infer.remote_fd() {
+            Some(fd) => Target::Remote(fd),
+            None => Target::Process(infer.pid()),
+        }
+    }
+}
+
+/// Asks target to stop, as the interrupt command does. The stop is then reported by wait like any
+/// other; take_request tells it apart from a signal of the program's own. This only does what is
+/// safe in a signal handler.
+pub fn request(target: Target) {
+    REQUESTED.store(true, Ordering::SeqCst);
+    match target {
+        Target::Process(pid) => unsafe {
+            libc::kill(pid.as_raw(), libc::SIGSTOP);
+        },
+        Target::Remote(fd) => unsafe {
+            libc::write(
+                fd,
+                &gdbserver::INTERRUPT as *const u8 as *const libc::c_void,
+                1,
+            );
+        },
+    }
+}
+
+/// Returns whether the inferior was asked to stop since the last call, and clears the request.
+pub fn take_request() -> bool {
+    REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+extern "C" fn handle_sigint(_: libc::c_int, info: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    let fd = REMOTE.load(Ordering::SeqCst);
+    let pid = PROCESS.load(Ordering::SeqCst);
+    let from_terminal = unsafe { (*info).si_code } != libc::SI_USER;
+    if fd >= 0 {
+        request(Target::Remote(fd));
+    } else if from_terminal && SHARES_TERMINAL.load(Ordering::SeqCst) {
+        // The inferior got the SIGINT too, and sending it SIGSTOP as well would stop it twice
+        REQUESTED.store(true, Ordering::SeqCst);
+    } else if pid > 0 {
+        request(Target::Process(Pid::from_raw(pid)));
+    }
+}
+
+/// Makes Ctrl+C stop infer until the returned guard is dropped.
+pub fn catch(infer: &Inferior) -> Guard {
+    match Target::of(infer) {
+        Target::Remote(fd) => REMOTE.store(fd, Ordering::SeqCst),
+        Target::Process(pid) => {
+            let shares_terminal = unsafe { libc::getpgid(pid.as_raw()) == libc::getpgrp() };
+            SHARES_TERMINAL.store(shares_terminal, Ordering::SeqCst);
+            PROCESS.store(pid.as_raw(), Ordering::SeqCst);
+        }
+    }
+    let action = SigAction::new(
+        SigHandler::SigAction(handle_sigint),
+        SaFlags::SA_RESTART | SaFlags::SA_SIGINFO,
+        SigSet::empty(),
+    );
+    unsafe { signal::sigaction(Signal::SIGINT, &action) }.expect("Error handling SIGINT");
+    Guard
+}
+
+/// Goes back to ignoring SIGINT when dropped (see catch).
+pub struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe { signal::signal(Signal::SIGINT, SigHandler::SigIgn) }
+            .expect("Error disabling SIGINT handling");
+        PROCESS.store(0, Ordering::SeqCst);
+        REMOTE.store(-1, Ordering::SeqCst);
+        SHARES_TERMINAL.store(false, Ordering::SeqCst);
+    }
+}
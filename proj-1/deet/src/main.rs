@@ -1,8 +1,21 @@
+mod completer;
+mod debug_file;
 mod debugger;
 mod debugger_command;
+mod disassembler;
 mod dwarf_data;
+mod expr;
+mod gdbserver;
 mod gimli_wrapper;
 mod inferior;
+mod interrupt;
+mod record;
+mod remote;
+mod sections;
+mod solib;
+mod syscall;
+mod tui;
+mod value;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
@@ -10,15 +23,81 @@ use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let usage = format!(
+        "Usage: {} [--tui] [--dump-dwarf] [--local-init] [--debug-dir <dir>] \
+         <target program>\n       {} --strace [--debug-dir <dir>] <target program> \
+         [<args>...]\n       {} --gdbserver [<host>]:<port> [--debug-dir <dir>] \
+         <target program> [<args>...]",
+        args[0], args[0], args[0]
+    );
+    let mut target = None;
+    let mut debug_dir = debug_file::DEFAULT_DEBUG_DIR.to_string();
+    let mut tui = false;
+    let mut local_init = false;
+    let mut dump_dwarf = false;
+    let mut strace = false;
+    let mut gdbserver = None;
+    let mut program_args = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // In --strace and --gdbserver modes, the rest of the command line after the program
+            // is the program's, even where it looks like one of deet's options
+            _ if target.is_some() && (strace || gdbserver.is_some()) => {
+                program_args.push(arg.to_string())
+            }
+            "--tui" => tui = true,
+            "--local-init" => local_init = true,
+            "--dump-dwarf" => dump_dwarf = true,
+            "--strace" => strace = true,
+            "--gdbserver" => match iter.next() {
+                Some(address) => gdbserver = Some(address.to_string()),
+                None => {
+                    println!("{}", usage);
+                    std::process::exit(1);
+                }
+            },
+            "--debug-dir" => match iter.next() {
+                Some(dir) => debug_dir = dir.to_string(),
+                None => {
+                    println!("{}", usage);
+                    std::process::exit(1);
+                }
+            },
+            _ if target.is_none() => target = Some(arg),
+            _ => {
+                println!("{}", usage);
+                std::process::exit(1);
+            }
+        }
     }
-    let target = &args[1];
+    let target = match target {
+        Some(target) => target,
+        None => {
+            println!("{}", usage);
+            std::process::exit(1);
+        }
+    };
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(target, &debug_dir);
+    if dump_dwarf {
+        debugger.dump_dwarf();
+    }
+    if strace {
+        std::process::exit(debugger.strace(program_args));
+    }
+    if let Some(address) = gdbserver {
+        std::process::exit(debugger.gdbserver(&address, program_args));
+    }
+    if tui {
+        debugger.enable_tui();
+    }
+    if local_init {
+        debugger.trust_local_init();
+    }
+    debugger.run();
 }
@@ -0,0 +1,183 @@
+//! Process record for reverse execution. While recording, the inferior runs one instruction at a
+//! time, and before each step its registers and the memory the instruction is about to overwrite
+//! are logged, so that going backwards is a matter of putting them back.
+//!
+//! Only the inferior's own stores are logged. Memory written by the kernel (e.g. the buffer
+//! filled by read(2)) isn't restored, and output that was written stays written; running forward
+//! again after going back re-executes those system calls.
+
+use crate::disassembler::MAX_INSTRUCTION_LEN;
+use crate::inferior::{Inferior, Status};
+use iced_x86::{Decoder, DecoderOptions, InstructionInfoFactory, Mnemonic, OpAccess, Register};
+use nix::sys::signal::Signal;
+use std::collections::VecDeque;
+
+/// Like gdb's default "record full insn-number-max": the oldest instructions are dropped beyond
+/// this.
+const RECORD_LIMIT: usize = 200_000;
+
+struct RecordEntry {
+    regs: libc::user_regs_struct,
+    /// Only saved for instructions that write x87, MMX or SSE registers, to keep the log small
+    fp_regs: Option<libc::user_fpregs_struct>,
+    /// Bytes the instruction overwrote, and where
+    memory: Vec<(usize, Vec<u8>)>,
+    /// Call depth when the instruction ran
+    depth: isize,
+}
+
+pub struct Recorder {
+    log: VecDeque<RecordEntry>,
+    /// Calls minus returns executed since recording started, so that reverse-next can tell
+    /// which frame an instruction ran in
+    depth: isize,
+    info_factory: InstructionInfoFactory,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            log: VecDeque::new(),
+            depth: 0,
+            info_factory: InstructionInfoFactory::new(),
+        }
+    }
+
+    /// Number of instructions that can be stepped back over.
+    pub fn len(&self) -> usize {
+        self.log.len()
+    }
+
+    pub fn depth(&self) -> isize {
+        self.depth
+    }
+
+    /// Address and call depth of each recorded instruction, most recent (where step_back would
+    /// go to) first.
+    pub fn history(&self) -> impl Iterator<Item = (usize, isize)> + '_ {
+        self.log
+            .iter()
+            .rev()
+            .map(|entry| (entry.regs.rip as usize, entry.depth))
+    }
+
+    /// Executes a single instruction, logging what it overwrites first.
+    pub fn step(
+        &mut self,
+        inferior: &mut Inferior,
+        signal: Option<Signal>,
+    ) -> Result<Status, nix::Error> {
+        let regs = inferior.get_registers()?;
+        let code = inferior.read_memory(regs.rip as usize, MAX_INSTRUCTION_LEN)?;
+        let instruction = Decoder::with_ip(64, &code, regs.rip, DecoderOptions::NONE).decode();
+        let info = self.info_factory.info(&instruction);
+
+        let mut memory = Vec::new();
+        for used in info.used_memory() {
+            if !is_write(used.access()) {
+                continue;
+            }
+            // Repeated string instructions don't know their total size, but single-stepping
+            // runs one iteration at a time
+            let size = match used.memory_size().size() {
+                0 => instruction.memory_size().size(),
+                size => size,
+            };
+            if let Some(addr) = used.virtual_address(0, |reg, _, _| register_value(&regs, reg)) {
+                if let Ok(bytes) = inferior.read_memory(addr as usize, size) {
+                    memory.push((addr as usize, bytes));
+                }
+            }
+        }
+        let writes_fp = info.used_registers().iter().any(|used| {
+            let reg = used.register();
+            is_write(used.access())
+                && (reg.is_st() || reg.is_mm() || reg.is_xmm() || reg.is_ymm() || reg.is_zmm())
+        });
+        let fp_regs = if writes_fp {
+            Some(inferior.get_fp_registers()?)
+        } else {
+            None
+        };
+
+        self.log.push_back(RecordEntry {
+            regs,
+            fp_regs,
+            memory,
+            depth: self.depth,
+        });
+        if self.log.len() > RECORD_LIMIT {
+            self.log.pop_front();
+        }
+        let status = inferior.step_instruction(signal)?;
+        match status {
+            // A signal arrived before the instruction could run
+            Status::Stopped(signal, _) if signal != Signal::SIGTRAP => {
+                self.log.pop_back();
+            }
+            _ => match instruction.mnemonic() {
+                Mnemonic::Call => self.depth += 1,
+                Mnemonic::Ret => self.depth -= 1,
+                _ => {}
+            },
+        }
+        Ok(status)
+    }
+
+    /// Undoes the last recorded instruction. Returns the new instruction pointer, or None if
+    /// there is no more history.
+    pub fn step_back(&mut self, inferior: &mut Inferior) -> Result<Option<usize>, nix::Error> {
+        let entry = match self.log.pop_back() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        for (addr, bytes) in entry.memory.iter().rev() {
+            inferior.write_memory(*addr, bytes)?;
+        }
+        if let Some(fp_regs) = &entry.fp_regs {
+            inferior.set_fp_registers(fp_regs)?;
+        }
+        inferior.set_registers(entry.regs)?;
+        self.depth = entry.depth;
+        Ok(Some(entry.regs.rip as usize))
+    }
+}
+
+fn is_write(access: OpAccess) -> bool {
+    match access {
+        OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Value of a register used in an address, for UsedMemory::virtual_address.
+fn register_value(regs: &libc::user_regs_struct, reg: Register) -> Option<u64> {
+    match reg {
+        Register::ES | Register::CS | Register::SS | Register::DS => return Some(0),
+        Register::FS => return Some(regs.fs_base),
+        Register::GS => return Some(regs.gs_base),
+        _ => {}
+    }
+    Some(match reg.full_register() {
+        Register::RAX => regs.rax,
+        Register::RBX => regs.rbx,
+        Register::RCX => regs.rcx,
+        Register::RDX => regs.rdx,
+        Register::RSI => regs.rsi,
+        Register::RDI => regs.rdi,
+        Register::RBP => regs.rbp,
+        Register::RSP => regs.rsp,
+        Register::R8 => regs.r8,
+        Register::R9 => regs.r9,
+        Register::R10 => regs.r10,
+        Register::R11 => regs.r11,
+        Register::R12 => regs.r12,
+        Register::R13 => regs.r13,
+        Register::R14 => regs.r14,
+        Register::R15 => regs.r15,
+        Register::RIP => regs.rip,
+        _ => return None,
+    })
+}
@@ -0,0 +1,259 @@
+//! The client side of the GDB remote serial protocol: lets an Inferior stand for a process run by
+//! gdbserver (or deet --gdbserver) somewhere else, e.g. inside a container.
+//!
+//! The stub does the ptrace work. Breakpoints are still int3s that deet writes into memory itself,
+//! as for a local process, so any stub that can read and write memory will do.
+
+use crate::gdbserver::{self, Connection};
+use crate::inferior::Status;
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use std::cell::RefCell;
+use std::io;
+use std::net::TcpStream;
+use std::os::unix::io::RawFd;
+
+/// Largest memory read asked for in one m packet, which keeps replies within the packet size
+/// stubs usually support.
+const MAX_READ_LEN: usize = 0x400;
+
+/// Lost connections and error replies are both reported as EIO.
+fn remote_error() -> nix::Error {
+    nix::Error::Sys(Errno::EIO)
+}
+
+pub struct Remote {
+    connection: RefCell<Connection>,
+    pid: i32,
+    /// The reply to "?" sent on connecting, which the first wait returns
+    initial_stop: RefCell<Option<String>>,
+}
+
+impl Remote {
+    /// Connects to a stub listening on address ("host:port").
+    pub fn connect(address: &str) -> io::Result<Remote> {
+        let remote = Remote {
+            connection: RefCell::new(Connection::new(TcpStream::connect(address)?)?),
+            pid: 0,
+            initial_stop: RefCell::new(None),
+        };
+        let request = |packet| {
+            remote
+                .request(packet)
+                .map_err(|_| io::Error::new(io::ErrorKind::ConnectionAborted, "connection lost"))
+        };
+        request("qSupported")?;
+        // The current thread is "QC<tid>", or "QCp<pid>.<tid>" from a multiprocess stub
+        let pid = request("qC")?
+            .trim_start_matches("QC")
+            .trim_start_matches('p')
+            .split('.')
+            .next()
+            .and_then(|pid| i32::from_str_radix(pid, 16).ok())
+            .unwrap_or(0);
+        let stop = request("?")?;
+        Ok(Remote {
+            pid,
+            initial_stop: RefCell::new(Some(stop)),
+            ..remote
+        })
+    }
+
+    /// The connection's socket, to which interrupt writes ^C.
+    pub fn raw_fd(&self) -> RawFd {
+        self.connection.borrow().raw_fd()
+    }
+
+    /// The remote process's id, or 0 if the stub didn't say.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Sends a packet and returns the data of the reply.
+    fn request(&self, packet: &str) -> Result<String, nix::Error> {
+        let mut connection = self.connection.borrow_mut();
+        connection
+            .write_packet(packet)
+            .map_err(|_| remote_error())?;
+        match connection.read_packet() {
+            Ok(Some(reply)) => Ok(reply),
+            _ => Err(remote_error()),
+        }
+    }
+
+    /// Sends a packet that is answered with OK.
+    fn command(&self, packet: &str) -> Result<(), nix::Error> {
+        match self.request(packet)?.as_str() {
+            "OK" => Ok(()),
+            _ => Err(remote_error()),
+        }
+    }
+
+    pub fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        let bytes = gdbserver::from_hex(&self.request("g")?).ok_or_else(remote_error)?;
+        let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        gdbserver::decode_registers(&bytes, &mut regs).ok_or_else(remote_error)?;
+        Ok(regs)
+    }
+
+    pub fn set_registers(&self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        // Stubs usually send the floating point registers too, which G must pass back unchanged
+        let mut bytes = gdbserver::from_hex(&self.request("g")?).ok_or_else(remote_error)?;
+        let general = gdbserver::encode_registers(regs);
+        if bytes.len() < general.len() {
+            return Err(remote_error());
+        }
+        bytes[..general.len()].copy_from_slice(&general);
+        self.command(&format!("G{}", gdbserver::to_hex(&bytes)))
+    }
+
+    /// Reads len bytes at addr. If the range runs into unmapped memory, only the readable prefix
+    /// is returned.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::new();
+        while bytes.len() < len {
+            let chunk_addr = addr + bytes.len();
+            let chunk_len = std::cmp::min(len - bytes.len(), MAX_READ_LEN);
+            let reply = self.request(&format!("m{:x},{:x}", chunk_addr, chunk_len))?;
+            let chunk = match gdbserver::from_hex(&reply) {
+                Some(chunk) if !reply.starts_with('E') && !chunk.is_empty() => chunk,
+                _ if bytes.is_empty() => return Err(nix::Error::Sys(Errno::EFAULT)),
+                _ => break,
+            };
+            let short = chunk.len() < chunk_len;
+            bytes.extend(chunk);
+            if short {
+                break;
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn write_memory(&self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        self.command(&format!(
+            "M{:x},{:x}:{}",
+            addr,
+            bytes.len(),
+            gdbserver::to_hex(bytes)
+        ))
+    }
+
+    /// Continues or single-steps the process. The stop reply is read by wait.
+    pub fn resume(&self, step: bool, signal: Option<Signal>) -> Result<(), nix::Error> {
+        let packet = match (step, signal) {
+            (false, None) => "c".to_string(),
+            (true, None) => "s".to_string(),
+            (false, Some(signal)) => format!("C{:02x}", gdbserver::gdb_signal(signal)),
+            (true, Some(signal)) => format!("S{:02x}", gdbserver::gdb_signal(signal)),
+        };
+        self.connection
+            .borrow_mut()
+            .write_packet(&packet)
+            .map_err(|_| remote_error())
+    }
+
+    /// Waits for the stop reply to the last resume (or, the first time, to the "?" sent on
+    /// connecting).
+    pub fn wait(&self) -> Result<Status, nix::Error> {
+        let mut reply = self.initial_stop.borrow_mut().take();
+        loop {
+            let reply = match reply.take() {
+                Some(reply) => reply,
+                None => match self.connection.borrow_mut().read_packet() {
+                    Ok(Some(reply)) => reply,
+                    _ => return Err(remote_error()),
+                },
+            };
+            let number = reply
+                .get(1..3)
+                .and_then(|number| u8::from_str_radix(number, 16).ok());
+            let status = match (reply.chars().next(), number) {
+                // Output the stub forwards from the process
+                (Some('O'), _) if reply != "OK" => {
+                    let output = gdbserver::from_hex(&reply[1..]).unwrap_or_default();
+                    print!("{}", String::from_utf8_lossy(&output));
+                    continue;
+                }
+                (Some('S'), Some(signal)) | (Some('T'), Some(signal)) => {
+                    let signal = gdbserver::from_gdb_signal(signal).unwrap_or(Signal::SIGTRAP);
+                    Status::Stopped(signal, self.get_registers()?.rip as usize)
+                }
+                (Some('W'), Some(exit_code)) => Status::Exited(exit_code as i32),
+                (Some('X'), Some(signal)) => {
+                    Status::Signaled(gdbserver::from_gdb_signal(signal).unwrap_or(Signal::SIGKILL))
+                }
+                _ => return Err(remote_error()),
+            };
+            return Ok(status);
+        }
+    }
+
+    /// Has the stub kill the process. There is no reply.
+    pub fn kill(&self) -> Result<(), nix::Error> {
+        self.connection
+            .borrow_mut()
+            .write_packet("k")
+            .map_err(|_| remote_error())
+    }
+
+    /// Has the stub let the process run on untraced.
+    pub fn detach(&self) -> Result<(), nix::Error> {
+        self.command("D")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gdbserver;
+    use crate::inferior::{Inferior, RunConfig, Status};
+    use nix::sys::signal::Signal;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// inc rax; jmp back to the inc
+    const LOOP: [u8; 5] = [0x48, 0xff, 0xc0, 0xeb, 0xfb];
+
+    #[test]
+    fn debugs_a_remote_process() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        // ptrace requests must come from the thread that started the inferior, so the server
+        // thread starts it
+        let server = thread::spawn(move || {
+            let infer = Inferior::new("/bin/true", &RunConfig::default()).unwrap();
+            gdbserver::serve(infer, &listener)
+        });
+        let mut infer = Inferior::connect(&address).unwrap();
+        assert!(infer.is_remote());
+        let rip = match infer.wait(None).unwrap() {
+            Status::Stopped(Signal::SIGTRAP, rip) => rip,
+            _ => panic!("not stopped at the start"),
+        };
+        assert_eq!(infer.get_registers().unwrap().rip as usize, rip);
+
+        // Replace the first instructions with a loop and break on its jump
+        infer.write_memory(rip, &LOOP).unwrap();
+        let mut regs = infer.get_registers().unwrap();
+        regs.rax = 0;
+        infer.set_registers(regs).unwrap();
+        infer.breakpoint(&(rip + 3)).unwrap();
+        assert_eq!(infer.read_memory(rip, LOOP.len()).unwrap(), LOOP);
+        for count in 1..=3 {
+            if infer.has_breakpoint(infer.get_registers().unwrap().rip as usize) {
+                infer.step_instruction(None).unwrap();
+            }
+            infer.goon(None).unwrap();
+            match infer.wait(None).unwrap() {
+                Status::Stopped(Signal::SIGTRAP, addr) => assert_eq!(addr, rip + 3),
+                _ => panic!("breakpoint not hit"),
+            }
+            let regs = infer.get_registers().unwrap();
+            assert_eq!(regs.rip as usize, rip + 3);
+            assert_eq!(regs.rax, count);
+        }
+
+        infer.remove_breakpoint(rip + 3).unwrap();
+        infer.kill().unwrap();
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+//! Reads the contents of ELF sections straight from the section headers, decompressing debug
+//! sections that are stored compressed: SHF_COMPRESSED sections (as produced by `gcc -gz` or
+//! `objcopy --compress-debug-sections`) and the older .zdebug_* ones.
+//!
+//! Only 64-bit little-endian files are understood, as deet only debugs x86-64 programs.
+
+use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{self, Read};
+
+const SHT_NOBITS: u32 = 8;
+const SHF_COMPRESSED: u64 = 0x800;
+const ELFCOMPRESS_ZLIB: u32 = 1;
+/// Size of an Elf64_Chdr, which precedes the data of an SHF_COMPRESSED section
+const CHDR_SIZE: usize = 24;
+
+struct Header {
+    flags: u64,
+    offset: usize,
+    size: usize,
+}
+
+pub struct Sections<'data> {
+    data: &'data [u8],
+    headers: HashMap<String, Header>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decompresses a zlib stream that should come out to size bytes.
+fn inflate(compressed: &[u8], size: usize, name: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(compressed).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(invalid_data(format!(
+            "{} decompressed to the wrong size",
+            name
+        )));
+    }
+    Ok(data)
+}
+
+impl<'data> Sections<'data> {
+    /// Reads the section headers of the ELF file in data. A file that isn't a 64-bit
+    /// little-endian ELF file, or whose headers are truncated, has no sections.
+    pub fn parse(data: &'data [u8]) -> Sections<'data> {
+        Sections {
+            data,
+            headers: Sections::parse_headers(data).unwrap_or_default(),
+        }
+    }
+
+    fn parse_headers(data: &[u8]) -> Option<HashMap<String, Header>> {
+        if data.get(..6)? != b"\x7fELF\x02\x01" {
+            return None;
+        }
+        let shoff: usize = read_u64(data, 0x28)?.try_into().ok()?;
+        let shentsize = read_u16(data, 0x3a)? as usize;
+        let shnum = read_u16(data, 0x3c)? as usize;
+        let shstrndx = read_u16(data, 0x3e)? as usize;
+        let header_at = |index: usize| -> Option<(u32, u32, u64, usize, usize)> {
+            let offset = shoff + index * shentsize;
+            Some((
+                read_u32(data, offset)?,
+                read_u32(data, offset + 4)?,
+                read_u64(data, offset + 8)?,
+                read_u64(data, offset + 24)?.try_into().ok()?,
+                read_u64(data, offset + 32)?.try_into().ok()?,
+            ))
+        };
+        let (_, _, _, strtab_offset, strtab_size) = header_at(shstrndx)?;
+        let strtab = data.get(strtab_offset..strtab_offset + strtab_size)?;
+
+        let mut headers = HashMap::new();
+        for index in 0..shnum {
+            let (name, kind, flags, offset, size) = header_at(index)?;
+            let name = strtab.get(name as usize..)?;
+            let name = &name[..name.iter().position(|b| *b == 0)?];
+            let size = if kind == SHT_NOBITS { 0 } else { size };
+            data.get(offset..offset + size)?;
+            headers.insert(
+                String::from_utf8_lossy(name).to_string(),
+                Header {
+                    flags,
+                    offset,
+                    size,
+                },
+            );
+        }
+        Some(headers)
+    }
+
+    /// Finds the named section, or for a .debug_* section that isn't there, the .zdebug_* one.
+    /// Returns the section's name along with its header.
+    fn find(&self, name: &str) -> Option<(String, &Header)> {
+        if let Some(header) = self.headers.get(name) {
+            return Some((name.to_string(), header));
+        }
+        if name.starts_with(".debug_") {
+            let zname = format!(".z{}", &name[1..]);
+            let header = self.headers.get(&zname)?;
+            return Some((zname, header));
+        }
+        None
+    }
+
+    /// Whether the file has the named section, and it isn't empty.
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name)
+            .map_or(false, |(_, header)| header.size != 0)
+    }
+
+    /// Returns the contents of the named section, decompressed if need be, or None if the file
+    /// has no such section.
+    pub fn data(&self, name: &str) -> io::Result<Option<Cow<'data, [u8]>>> {
+        let (name, header) = match self.find(name) {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        let data = &self.data[header.offset..header.offset + header.size];
+        if header.flags & SHF_COMPRESSED != 0 {
+            let truncated = || invalid_data(format!("{} is truncated", name));
+            let kind = read_u32(data, 0).ok_or_else(truncated)?;
+            let size: usize = read_u64(data, 8)
+                .ok_or_else(truncated)?
+                .try_into()
+                .map_err(|_| truncated())?;
+            if kind != ELFCOMPRESS_ZLIB {
+                return Err(invalid_data(format!(
+                    "{} uses unsupported compression {}",
+                    name, kind
+                )));
+            }
+            let compressed = data.get(CHDR_SIZE..).ok_or_else(truncated)?;
+            return Ok(Some(Cow::Owned(inflate(compressed, size, &name)?)));
+        }
+        if name.starts_with(".zdebug_") && data.starts_with(b"ZLIB") {
+            // "ZLIB", then the uncompressed size as a big-endian 64-bit number
+            let size = data
+                .get(4..12)
+                .and_then(|size| size.try_into().ok())
+                .map(u64::from_be_bytes)
+                .and_then(|size| size.try_into().ok())
+                .ok_or_else(|| invalid_data(format!("{} is truncated", name)))?;
+            return Ok(Some(Cow::Owned(inflate(&data[12..], size, &name)?)));
+        }
+        Ok(Some(Cow::Borrowed(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sections;
+
+    #[test]
+    fn finds_no_sections_in_other_files() {
+        let sections = Sections::parse(b"#!/bin/sh\n");
+        assert!(!sections.contains(".debug_info"));
+        assert!(sections.data(".debug_info").unwrap().is_none());
+        // Section headers past the end of the file
+        let mut truncated = b"\x7fELF\x02\x01".to_vec();
+        truncated.resize(0x40, 0);
+        truncated[0x29] = 0x10;
+        truncated[0x3c] = 1;
+        assert!(!Sections::parse(&truncated).contains(".debug_info"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Finds the shared libraries loaded into a running (local) inferior, so that breakpoints can be
+//! set on functions in them. The dynamic linker calls _dl_debug_state every time it has mapped
+//! or unmapped libraries (which is how gdb notices them too), so deet breaks there and reads
+//! /proc/<pid>/maps again.
+
+use crate::dwarf_data::DwarfData;
+use nix::unistd::Pid;
+use object::{Object, ObjectSegment};
+use std::convert::TryInto;
+use std::fs;
+
+/// Function the dynamic linker calls after changing the set of loaded libraries
+pub const DEBUG_STATE_HOOK: &str = "_dl_debug_state";
+/// Auxiliary vector entry holding the address the dynamic linker was loaded at
+const AT_BASE: u64 = 7;
+/// Auxiliary vector entry holding the address of the program's entry point
+const AT_ENTRY: u64 = 9;
+const PAGE_SIZE: usize = 4096;
+
+/// A shared library mapped into the inferior.
+pub struct Library {
+    pub path: String,
+    /// Where the library's first page is mapped
+    pub start: usize,
+    /// What to add to an address in the library's file to get its address in the inferior
+    pub bias: usize,
+    pub debug_data: DwarfData,
+}
+
+impl Library {
+    /// Reads the symbols and debugging information of the library at path, which is mapped at
+    /// start. Returns None if the file can't be read.
+    pub fn load(path: &str, start: usize, debug_dir: &str) -> Option<Library> {
+        let bias = start.checked_sub(lowest_address(path)?)?;
+        let debug_data = DwarfData::from_file(path, debug_dir).ok()?;
+        Some(Library {
+            path: path.to_string(),
+            start,
+            bias,
+            debug_data,
+        })
+    }
+
+    /// Looks a function up by name, returning the address in the inferior where breaking on it
+    /// stops (see DwarfData::skip_prologue).
+    pub fn find_function(&self, name: &str) -> Option<usize> {
+        let addr = self.debug_data.get_addr_for_function(None, name)?;
+        Some(self.debug_data.skip_prologue(addr) + self.bias)
+    }
+
+    /// Looks a function up by name, returning the address in the inferior where it starts.
+    pub fn function_address(&self, name: &str) -> Option<usize> {
+        Some(self.debug_data.get_addr_for_function(None, name)? + self.bias)
+    }
+
+    /// Looks up a function in the library's ELF symbol table, returning its address in the
+    /// inferior.
+    pub fn find_symbol(&self, name: &str) -> Option<usize> {
+        Some(self.debug_data.get_addr_for_symbol(name)? + self.bias)
+    }
+}
+
+/// Finds the library mapped at addr, if there is one. Libraries are mapped above the executable.
+pub fn library_at(libraries: &[Library], addr: usize) -> Option<&Library> {
+    libraries
+        .iter()
+        .filter(|lib| lib.start <= addr)
+        .max_by_key(|lib| lib.start)
+}
+
+/// Returns the debugging information to look addr up in, along with the bias to subtract from
+/// it first: that of the library mapped at addr, or else the executable's debug_data.
+pub fn debug_data_at<'a>(
+    debug_data: &'a DwarfData,
+    libraries: &'a [Library],
+    addr: usize,
+) -> (&'a DwarfData, usize) {
+    match library_at(libraries, addr) {
+        Some(lib) => (&lib.debug_data, lib.bias),
+        None => (debug_data, 0),
+    }
+}
+
+/// The lowest address a segment of the ELF file at path is loaded at, rounded down to a page;
+/// 0 for just about every shared library.
+fn lowest_address(path: &str) -> Option<usize> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    let object = object::File::parse(&*mmap).ok()?;
+    let lowest = object.segments().map(|segment| segment.address()).min()?;
+    let lowest: usize = lowest.try_into().ok()?;
+    Some(lowest & !(PAGE_SIZE - 1))
+}
+
+/// Returns the address the dynamic linker was loaded at, from the inferior's auxiliary vector,
+/// or None for a statically linked program.
+pub fn interpreter_base(pid: Pid) -> Option<usize> {
+    auxv_entry(pid, AT_BASE).filter(|base| *base != 0)
+}
+
+/// Returns the address of the program's entry point (_start) in the inferior, which is where
+/// it is mapped for a position-independent executable too.
+pub fn entry_point(pid: Pid) -> Option<usize> {
+    auxv_entry(pid, AT_ENTRY)
+}
+
+/// Looks up an entry in the inferior's auxiliary vector.
+fn auxv_entry(pid: Pid, kind: u64) -> Option<usize> {
+    let auxv = fs::read(format!("/proc/{}/auxv", pid)).ok()?;
+    auxv.chunks_exact(16)
+        .map(|entry| {
+            (
+                u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                u64::from_le_bytes(entry[8..].try_into().unwrap()),
+            )
+        })
+        .find(|(entry_kind, _)| *entry_kind == kind)
+        .and_then(|(_, value)| value.try_into().ok())
+}
+
+/// Returns the files mapped into the inferior from their start, with the address each is
+/// mapped at, in address order. This includes the executable and the dynamic linker.
+pub fn mapped_files(pid: Pid) -> Vec<(usize, String)> {
+    let maps = match fs::read_to_string(format!("/proc/{}/maps", pid)) {
+        Ok(maps) => maps,
+        Err(_) => return Vec::new(),
+    };
+    let mut files: Vec<(usize, String)> = Vec::new();
+    for line in maps.lines() {
+        // start-end perms offset dev inode path
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || !fields[5].starts_with('/') {
+            continue;
+        }
+        let start = fields[0].split('-').next().unwrap_or("");
+        if let (Ok(start), Ok(0)) = (
+            usize::from_str_radix(start, 16),
+            usize::from_str_radix(fields[2], 16),
+        ) {
+            let path = fields[5..].join(" ");
+            if !files.iter().any(|(_, file)| *file == path) {
+                files.push((start, path));
+            }
+        }
+    }
+    files
+}
@@ -0,0 +1,507 @@
+//! Names and argument decoding of x86-64 Linux system calls, for catch syscall and --strace.
+//!
+//! At a syscall stop the number is in orig_rax and the arguments are in rdi, rsi, rdx, r10, r8
+//! and r9. Those registers are preserved by the kernel, so the arguments can be decoded again on
+//! exit, when buffers filled in by the call (like read's) hold its result.
+
+use crate::inferior::Inferior;
+use nix::errno::Errno;
+
+/// How an argument is printed:
+/// d: int, l: long (offsets and lengths), u: unsigned integer, x: hex (pointers and flags),
+/// o: octal (modes), s: NUL-terminated string, b: buffer whose length is the next argument,
+/// r: buffer the call fills in, whose length is the call's return value (so it is only shown on
+/// return, and only the pointer before).
+type ArgFormats = &'static str;
+
+/// Strings and buffers are cut off after this many bytes, like strace's default -s 32.
+const MAX_STRING_LEN: usize = 32;
+
+const SYSCALLS: &[(usize, &str, ArgFormats)] = &[
+    (0, "read", "dru"),
+    (1, "write", "dbu"),
+    (2, "open", "sxo"),
+    (3, "close", "d"),
+    (4, "stat", "sx"),
+    (5, "fstat", "dx"),
+    (6, "lstat", "sx"),
+    (7, "poll", "xud"),
+    (8, "lseek", "dld"),
+    (9, "mmap", "xuxxdx"),
+    (10, "mprotect", "xux"),
+    (11, "munmap", "xu"),
+    (12, "brk", "x"),
+    (13, "rt_sigaction", "dxxu"),
+    (14, "rt_sigprocmask", "dxxu"),
+    (15, "rt_sigreturn", ""),
+    (16, "ioctl", "dxx"),
+    (17, "pread64", "drul"),
+    (18, "pwrite64", "dbul"),
+    (19, "readv", "dxd"),
+    (20, "writev", "dxd"),
+    (21, "access", "so"),
+    (22, "pipe", "x"),
+    (23, "select", "dxxxx"),
+    (24, "sched_yield", ""),
+    (25, "mremap", "xuuxx"),
+    (26, "msync", "xux"),
+    (27, "mincore", "xux"),
+    (28, "madvise", "xud"),
+    (29, "shmget", "dux"),
+    (30, "shmat", "dxx"),
+    (31, "shmctl", "ddx"),
+    (32, "dup", "d"),
+    (33, "dup2", "dd"),
+    (34, "pause", ""),
+    (35, "nanosleep", "xx"),
+    (36, "getitimer", "dx"),
+    (37, "alarm", "u"),
+    (38, "setitimer", "dxx"),
+    (39, "getpid", ""),
+    (40, "sendfile", "ddxu"),
+    (41, "socket", "ddd"),
+    (42, "connect", "dxd"),
+    (43, "accept", "dxx"),
+    (44, "sendto", "dbuxxd"),
+    (45, "recvfrom", "druxxx"),
+    (46, "sendmsg", "dxx"),
+    (47, "recvmsg", "dxx"),
+    (48, "shutdown", "dd"),
+    (49, "bind", "dxd"),
+    (50, "listen", "dd"),
+    (51, "getsockname", "dxx"),
+    (52, "getpeername", "dxx"),
+    (53, "socketpair", "dddx"),
+    (54, "setsockopt", "dddxd"),
+    (55, "getsockopt", "dddxx"),
+    (56, "clone", "xxxxx"),
+    (57, "fork", ""),
+    (58, "vfork", ""),
+    (59, "execve", "sxx"),
+    (60, "exit", "d"),
+    (61, "wait4", "dxxx"),
+    (62, "kill", "dd"),
+    (63, "uname", "x"),
+    (64, "semget", "ddx"),
+    (65, "semop", "dxu"),
+    (66, "semctl", "dddx"),
+    (67, "shmdt", "x"),
+    (68, "msgget", "dx"),
+    (69, "msgsnd", "dxux"),
+    (70, "msgrcv", "dxudx"),
+    (71, "msgctl", "ddx"),
+    (72, "fcntl", "ddx"),
+    (73, "flock", "dd"),
+    (74, "fsync", "d"),
+    (75, "fdatasync", "d"),
+    (76, "truncate", "sl"),
+    (77, "ftruncate", "dl"),
+    (78, "getdents", "dxu"),
+    (79, "getcwd", "xu"),
+    (80, "chdir", "s"),
+    (81, "fchdir", "d"),
+    (82, "rename", "ss"),
+    (83, "mkdir", "so"),
+    (84, "rmdir", "s"),
+    (85, "creat", "so"),
+    (86, "link", "ss"),
+    (87, "unlink", "s"),
+    (88, "symlink", "ss"),
+    (89, "readlink", "sru"),
+    (90, "chmod", "so"),
+    (91, "fchmod", "do"),
+    (92, "chown", "sdd"),
+    (93, "fchown", "ddd"),
+    (94, "lchown", "sdd"),
+    (95, "umask", "o"),
+    (96, "gettimeofday", "xx"),
+    (97, "getrlimit", "dx"),
+    (98, "getrusage", "dx"),
+    (99, "sysinfo", "x"),
+    (100, "times", "x"),
+    (101, "ptrace", "ddxx"),
+    (102, "getuid", ""),
+    (103, "syslog", "dxd"),
+    (104, "getgid", ""),
+    (105, "setuid", "d"),
+    (106, "setgid", "d"),
+    (107, "geteuid", ""),
+    (108, "getegid", ""),
+    (109, "setpgid", "dd"),
+    (110, "getppid", ""),
+    (111, "getpgrp", ""),
+    (112, "setsid", ""),
+    (113, "setreuid", "dd"),
+    (114, "setregid", "dd"),
+    (115, "getgroups", "dx"),
+    (116, "setgroups", "dx"),
+    (117, "setresuid", "ddd"),
+    (118, "getresuid", "xxx"),
+    (119, "setresgid", "ddd"),
+    (120, "getresgid", "xxx"),
+    (121, "getpgid", "d"),
+    (122, "setfsuid", "d"),
+    (123, "setfsgid", "d"),
+    (124, "getsid", "d"),
+    (125, "capget", "xx"),
+    (126, "capset", "xx"),
+    (127, "rt_sigpending", "xu"),
+    (128, "rt_sigtimedwait", "xxxu"),
+    (129, "rt_sigqueueinfo", "ddx"),
+    (130, "rt_sigsuspend", "xu"),
+    (131, "sigaltstack", "xx"),
+    (132, "utime", "sx"),
+    (133, "mknod", "sox"),
+    (134, "uselib", "s"),
+    (135, "personality", "x"),
+    (136, "ustat", "xx"),
+    (137, "statfs", "sx"),
+    (138, "fstatfs", "dx"),
+    (139, "sysfs", "dxx"),
+    (140, "getpriority", "dd"),
+    (141, "setpriority", "ddd"),
+    (142, "sched_setparam", "dx"),
+    (143, "sched_getparam", "dx"),
+    (144, "sched_setscheduler", "ddx"),
+    (145, "sched_getscheduler", "d"),
+    (146, "sched_get_priority_max", "d"),
+    (147, "sched_get_priority_min", "d"),
+    (148, "sched_rr_get_interval", "dx"),
+    (149, "mlock", "xu"),
+    (150, "munlock", "xu"),
+    (151, "mlockall", "x"),
+    (152, "munlockall", ""),
+    (153, "vhangup", ""),
+    (154, "modify_ldt", "dxu"),
+    (155, "pivot_root", "ss"),
+    (156, "_sysctl", "x"),
+    (157, "prctl", "dxxxx"),
+    (158, "arch_prctl", "xx"),
+    (159, "adjtimex", "x"),
+    (160, "setrlimit", "dx"),
+    (161, "chroot", "s"),
+    (162, "sync", ""),
+    (163, "acct", "s"),
+    (164, "settimeofday", "xx"),
+    (165, "mount", "sssxx"),
+    (166, "umount2", "sx"),
+    (167, "swapon", "sx"),
+    (168, "swapoff", "s"),
+    (169, "reboot", "xxxx"),
+    (170, "sethostname", "su"),
+    (171, "setdomainname", "su"),
+    (172, "iopl", "d"),
+    (173, "ioperm", "xxd"),
+    (174, "create_module", "su"),
+    (175, "init_module", "xus"),
+    (176, "delete_module", "sx"),
+    (177, "get_kernel_syms", "x"),
+    (178, "query_module", "sdxux"),
+    (179, "quotactl", "dsdx"),
+    (180, "nfsservctl", "dxx"),
+    (181, "getpmsg", "xxxxx"),
+    (182, "putpmsg", "xxxxx"),
+    (183, "afs_syscall", "xxxxx"),
+    (184, "tuxcall", "xxx"),
+    (185, "security", "xxx"),
+    (186, "gettid", ""),
+    (187, "readahead", "dlu"),
+    (188, "setxattr", "ssxux"),
+    (189, "lsetxattr", "ssxux"),
+    (190, "fsetxattr", "dsxux"),
+    (191, "getxattr", "ssxu"),
+    (192, "lgetxattr", "ssxu"),
+    (193, "fgetxattr", "dsxu"),
+    (194, "listxattr", "sxu"),
+    (195, "llistxattr", "sxu"),
+    (196, "flistxattr", "dxu"),
+    (197, "removexattr", "ss"),
+    (198, "lremovexattr", "ss"),
+    (199, "fremovexattr", "ds"),
+    (200, "tkill", "dd"),
+    (201, "time", "x"),
+    (202, "futex", "xddxxd"),
+    (203, "sched_setaffinity", "dux"),
+    (204, "sched_getaffinity", "dux"),
+    (205, "set_thread_area", "x"),
+    (206, "io_setup", "ux"),
+    (207, "io_destroy", "x"),
+    (208, "io_getevents", "xddxx"),
+    (209, "io_submit", "xdx"),
+    (210, "io_cancel", "xxx"),
+    (211, "get_thread_area", "x"),
+    (212, "lookup_dcookie", "xxu"),
+    (213, "epoll_create", "d"),
+    (214, "epoll_ctl_old", "dddx"),
+    (215, "epoll_wait_old", "dxdd"),
+    (216, "remap_file_pages", "xuxux"),
+    (217, "getdents64", "dxu"),
+    (218, "set_tid_address", "x"),
+    (219, "restart_syscall", ""),
+    (220, "semtimedop", "dxux"),
+    (221, "fadvise64", "dlud"),
+    (222, "timer_create", "dxx"),
+    (223, "timer_settime", "ddxx"),
+    (224, "timer_gettime", "dx"),
+    (225, "timer_getoverrun", "d"),
+    (226, "timer_delete", "d"),
+    (227, "clock_settime", "dx"),
+    (228, "clock_gettime", "dx"),
+    (229, "clock_getres", "dx"),
+    (230, "clock_nanosleep", "ddxx"),
+    (231, "exit_group", "d"),
+    (232, "epoll_wait", "dxdd"),
+    (233, "epoll_ctl", "dddx"),
+    (234, "tgkill", "ddd"),
+    (235, "utimes", "sx"),
+    (236, "vserver", "xxxxx"),
+    (237, "mbind", "xudxux"),
+    (238, "set_mempolicy", "dxu"),
+    (239, "get_mempolicy", "xxuxx"),
+    (240, "mq_open", "sxox"),
+    (241, "mq_unlink", "s"),
+    (242, "mq_timedsend", "dbuux"),
+    (243, "mq_timedreceive", "dxuxx"),
+    (244, "mq_notify", "dx"),
+    (245, "mq_getsetattr", "dxx"),
+    (246, "kexec_load", "xuxx"),
+    (247, "waitid", "ddxdx"),
+    (248, "add_key", "ssxud"),
+    (249, "request_key", "sssd"),
+    (250, "keyctl", "dxxxx"),
+    (251, "ioprio_set", "ddd"),
+    (252, "ioprio_get", "dd"),
+    (253, "inotify_init", ""),
+    (254, "inotify_add_watch", "dsx"),
+    (255, "inotify_rm_watch", "dd"),
+    (256, "migrate_pages", "duxx"),
+    (257, "openat", "dsxo"),
+    (258, "mkdirat", "dso"),
+    (259, "mknodat", "dsox"),
+    (260, "fchownat", "dsddx"),
+    (261, "futimesat", "dsx"),
+    (262, "newfstatat", "dsxx"),
+    (263, "unlinkat", "dsx"),
+    (264, "renameat", "dsds"),
+    (265, "linkat", "dsdsx"),
+    (266, "symlinkat", "sds"),
+    (267, "readlinkat", "dsru"),
+    (268, "fchmodat", "dso"),
+    (269, "faccessat", "dso"),
+    (270, "pselect6", "dxxxxx"),
+    (271, "ppoll", "xuxxu"),
+    (272, "unshare", "x"),
+    (273, "set_robust_list", "xu"),
+    (274, "get_robust_list", "dxx"),
+    (275, "splice", "dxdxux"),
+    (276, "tee", "ddux"),
+    (277, "sync_file_range", "dllx"),
+    (278, "vmsplice", "dxux"),
+    (279, "move_pages", "duxxxx"),
+    (280, "utimensat", "dsxx"),
+    (281, "epoll_pwait", "dxddxu"),
+    (282, "signalfd", "dxu"),
+    (283, "timerfd_create", "dx"),
+    (284, "eventfd", "u"),
+    (285, "fallocate", "ddll"),
+    (286, "timerfd_settime", "dxxx"),
+    (287, "timerfd_gettime", "dx"),
+    (288, "accept4", "dxxx"),
+    (289, "signalfd4", "dxux"),
+    (290, "eventfd2", "ux"),
+    (291, "epoll_create1", "x"),
+    (292, "dup3", "ddx"),
+    (293, "pipe2", "xx"),
+    (294, "inotify_init1", "x"),
+    (295, "preadv", "dxddd"),
+    (296, "pwritev", "dxddd"),
+    (297, "rt_tgsigqueueinfo", "dddx"),
+    (298, "perf_event_open", "xdddx"),
+    (299, "recvmmsg", "dxuxx"),
+    (300, "fanotify_init", "xx"),
+    (301, "fanotify_mark", "dxxds"),
+    (302, "prlimit64", "ddxx"),
+    (303, "name_to_handle_at", "dsxxx"),
+    (304, "open_by_handle_at", "dxx"),
+    (305, "clock_adjtime", "dx"),
+    (306, "syncfs", "d"),
+    (307, "sendmmsg", "dxux"),
+    (308, "setns", "dx"),
+    (309, "getcpu", "xxx"),
+    (310, "process_vm_readv", "dxuxux"),
+    (311, "process_vm_writev", "dxuxux"),
+    (312, "kcmp", "dddxx"),
+    (313, "finit_module", "dsx"),
+    (314, "sched_setattr", "dxx"),
+    (315, "sched_getattr", "dxux"),
+    (316, "renameat2", "dsdsx"),
+    (317, "seccomp", "uxx"),
+    (318, "getrandom", "xux"),
+    (319, "memfd_create", "sx"),
+    (320, "kexec_file_load", "ddusx"),
+    (321, "bpf", "dxu"),
+    (322, "execveat", "dsxxx"),
+    (323, "userfaultfd", "x"),
+    (324, "membarrier", "dx"),
+    (325, "mlock2", "xux"),
+    (326, "copy_file_range", "dxdxux"),
+    (327, "preadv2", "dxdddx"),
+    (328, "pwritev2", "dxdddx"),
+    (329, "pkey_mprotect", "xuxd"),
+    (330, "pkey_alloc", "xx"),
+    (331, "pkey_free", "d"),
+    (332, "statx", "dsxxx"),
+    (333, "io_pgetevents", "xddxxx"),
+    (334, "rseq", "xuxx"),
+    (424, "pidfd_send_signal", "ddxx"),
+    (425, "io_uring_setup", "ux"),
+    (426, "io_uring_enter", "duuxxu"),
+    (427, "io_uring_register", "duxu"),
+    (428, "open_tree", "dsx"),
+    (429, "move_mount", "dsdsx"),
+    (430, "fsopen", "sx"),
+    (431, "fsconfig", "dusxd"),
+    (432, "fsmount", "dxx"),
+    (433, "fspick", "dsx"),
+    (434, "pidfd_open", "dx"),
+    (435, "clone3", "xu"),
+    (436, "close_range", "uux"),
+    (437, "openat2", "dsxu"),
+    (438, "pidfd_getfd", "ddx"),
+    (439, "faccessat2", "dsox"),
+    (440, "process_madvise", "dxudx"),
+    (441, "epoll_pwait2", "dxdxxu"),
+    (442, "mount_setattr", "dsxxu"),
+    (443, "quotactl_fd", "duux"),
+    (444, "landlock_create_ruleset", "xux"),
+    (445, "landlock_add_rule", "duxx"),
+    (446, "landlock_restrict_self", "dx"),
+    (447, "memfd_secret", "x"),
+    (448, "process_mrelease", "dx"),
+    (449, "futex_waitv", "xuxxd"),
+    (450, "set_mempolicy_home_node", "xuux"),
+    (451, "cachestat", "duxx"),
+    (452, "fchmodat2", "dsox"),
+    (453, "map_shadow_stack", "xux"),
+];
+
+fn lookup(number: usize) -> Option<&'static (usize, &'static str, ArgFormats)> {
+    SYSCALLS
+        .binary_search_by_key(&number, |(n, _, _)| *n)
+        .ok()
+        .map(|index| &SYSCALLS[index])
+}
+
+/// e.g. "write", or "syscall_999" for numbers not in the table.
+pub fn name(number: usize) -> String {
+    match lookup(number) {
+        Some((_, name, _)) => name.to_string(),
+        None => format!("syscall_{}", number),
+    }
+}
+
+/// The number of a system call given by name or number.
+pub fn number(name_or_number: &str) -> Option<usize> {
+    if let Ok(number) = name_or_number.parse() {
+        return Some(number);
+    }
+    SYSCALLS
+        .iter()
+        .find(|(_, name, _)| *name == name_or_number)
+        .map(|(number, _, _)| *number)
+}
+
+/// Whether the system call never returns to the caller when it succeeds.
+pub fn is_noreturn(number: usize) -> bool {
+    match lookup(number) {
+        Some((_, name, _)) => *name == "exit" || *name == "exit_group",
+        None => false,
+    }
+}
+
+/// Formats the system call the inferior is stopped in, e.g. write(1, "hello\n", 6). returned says
+/// whether it is stopped on return from the call rather than on entry.
+pub fn format_call(infer: &Inferior, regs: &libc::user_regs_struct, returned: bool) -> String {
+    let number = regs.orig_rax as usize;
+    let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+    let formats = match lookup(number) {
+        Some((_, _, formats)) => formats.as_bytes(),
+        None => b"xxxxxx",
+    };
+    let formatted: Vec<String> = formats
+        .iter()
+        .enumerate()
+        .map(|(index, format)| match format {
+            b'd' => (args[index] as i32).to_string(),
+            b'l' => (args[index] as i64).to_string(),
+            b'u' => args[index].to_string(),
+            b'o' => format!("{:#o}", args[index]),
+            b's' => format_string(infer, args[index] as usize, None),
+            b'b' => format_string(infer, args[index] as usize, Some(args[index + 1] as usize)),
+            // Until the call has succeeded, nothing in the buffer is its result
+            b'r' if returned && !is_error(regs) => {
+                format_string(infer, args[index] as usize, Some(regs.rax as usize))
+            }
+            _ => format!("{:#x}", args[index]),
+        })
+        .collect();
+    format!("{}({})", name(number), formatted.join(", "))
+}
+
+/// Formats a system call's return value, e.g. 6, or -1 ENOENT (No such file or directory).
+pub fn format_return(regs: &libc::user_regs_struct) -> String {
+    let value = regs.rax as i64;
+    if is_error(regs) {
+        let errno = Errno::from_i32(-value as i32);
+        return format!("-1 {:?} ({})", errno, errno.desc());
+    }
+    match lookup(regs.orig_rax as usize) {
+        Some((_, "mmap", _)) | Some((_, "brk", _)) | Some((_, "mremap", _)) => {
+            format!("{:#x}", value)
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Whether the system call returned an error, which it does as -errno, from -4095 up.
+fn is_error(regs: &libc::user_regs_struct) -> bool {
+    let value = regs.rax as i64;
+    value < 0 && value >= -4095
+}
+
+/// Quotes a string from the inferior's memory, reading up to its NUL (or len bytes, for a
+/// buffer) and escaping it the way strace does.
+fn format_string(infer: &Inferior, addr: usize, len: Option<usize>) -> String {
+    if addr == 0 {
+        return "NULL".to_string();
+    }
+    let read_len = std::cmp::min(len.unwrap_or(MAX_STRING_LEN + 1), MAX_STRING_LEN + 1);
+    let mut bytes = match infer.read_memory(addr, read_len) {
+        Ok(bytes) => bytes,
+        Err(_) => return format!("{:#x}", addr),
+    };
+    if len.is_none() {
+        if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+            bytes.truncate(nul);
+        }
+    }
+    let truncated = bytes.len() > MAX_STRING_LEN;
+    bytes.truncate(MAX_STRING_LEN);
+    let mut quoted = String::from("\"");
+    for byte in bytes {
+        match byte {
+            b'\n' => quoted.push_str("\\n"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            0x20..=0x7e => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    if truncated {
+        quoted.push_str("...");
+    }
+    quoted
+}
@@ -0,0 +1,216 @@
+//! A gdb-style text UI: source, register and backtrace panes drawn at the top of the terminal,
+//! with the (deet) command line scrolling underneath them.
+//!
+//! Rather than taking over the terminal with an event loop, the panes are redrawn before each
+//! prompt and the bottom of the screen is made a scrolling region (DECSTBM), so rustyline and the
+//! inferior's own output keep working as in the line-based REPL.
+
+use crate::dwarf_data::Line;
+use crossterm::cursor::{MoveTo, RestorePosition, SavePosition};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+
+/// Lines reserved at the bottom of the screen for the command line.
+const MIN_COMMAND_HEIGHT: u16 = 8;
+/// Title row plus 9 rows of two registers each.
+const REGISTER_PANE_HEIGHT: u16 = 10;
+const MIN_SOURCE_HEIGHT: u16 = 5;
+
+/// Everything the panes display, gathered by the debugger before each redraw.
+pub struct View {
+    /// Where the inferior is stopped, or where it will start (main) if it isn't running.
+    pub line: Option<Line>,
+    pub breakpoint_lines: Vec<Line>,
+    pub registers: Option<libc::user_regs_struct>,
+    pub backtrace: Vec<String>,
+}
+
+pub struct Tui {
+    sources: HashMap<String, Option<Vec<String>>>,
+    size: (u16, u16),
+}
+
+impl Tui {
+    /// Returns None if stdout is not a terminal, in which case the caller should stick to the
+    /// line-based REPL.
+    pub fn new() -> Option<Tui> {
+        if !nix::unistd::isatty(libc::STDOUT_FILENO).unwrap_or(false) {
+            return None;
+        }
+        Some(Tui {
+            sources: HashMap::new(),
+            size: (0, 0),
+        })
+    }
+
+    pub fn render(&mut self, view: &View) -> crossterm::Result<()> {
+        let size = terminal::size()?;
+        let (width, height) = size;
+        if height < MIN_COMMAND_HEIGHT + REGISTER_PANE_HEIGHT + MIN_SOURCE_HEIGHT {
+            return Ok(());
+        }
+        let command_height = std::cmp::max(MIN_COMMAND_HEIGHT, height / 4);
+        let panes_height = height - command_height;
+        let source_height = panes_height - REGISTER_PANE_HEIGHT;
+
+        let mut stdout = io::stdout();
+        if size != self.size {
+            // Confine scrolling to the command area. Setting the region homes the cursor, so put
+            // it back at the bottom where the prompt belongs.
+            self.size = size;
+            queue!(
+                stdout,
+                Clear(ClearType::All),
+                Print(format!("\x1b[{};{}r", panes_height + 1, height)),
+                MoveTo(0, height - 1)
+            )?;
+        }
+
+        let mut rows = self.source_rows(view, width as usize, source_height as usize);
+        rows.extend(
+            register_rows(view, width as usize / 2)
+                .into_iter()
+                .zip(backtrace_rows(view, width as usize - width as usize / 2))
+                .map(|(left, right)| format!("{}{}", left, right)),
+        );
+
+        queue!(stdout, SavePosition)?;
+        for (row, text) in rows.iter().enumerate() {
+            let title = row == 0 || row == source_height as usize;
+            queue!(
+                stdout,
+                MoveTo(0, row as u16),
+                Clear(ClearType::CurrentLine),
+                SetAttribute(if title {
+                    Attribute::Reverse
+                } else {
+                    Attribute::Reset
+                }),
+                Print(truncate(text, width as usize)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+        execute!(stdout, RestorePosition)
+    }
+
+    /// A title row with the file name, then the file's lines around view.line with a gutter
+    /// marking breakpoints (B) and the current line (>).
+    fn source_rows(&mut self, view: &View, width: usize, height: usize) -> Vec<String> {
+        let mut rows = Vec::new();
+        let line = match &view.line {
+            Some(line) => line,
+            None => {
+                rows.push(pad("[No source]", width));
+                rows.resize(height, String::new());
+                return rows;
+            }
+        };
+        rows.push(pad(&line.file, width));
+        let source = self
+            .sources
+            .entry(line.file.clone())
+            .or_insert_with(|| {
+                fs::read_to_string(&line.file)
+                    .ok()
+                    .map(|text| text.lines().map(|l| l.replace('\t', "    ")).collect())
+            })
+            .as_ref();
+        match source {
+            Some(source) => {
+                let visible = height - 1;
+                let first = line.number.saturating_sub(visible / 2).max(1);
+                for number in first..first + visible {
+                    let text = match source.get(number - 1) {
+                        Some(text) => text,
+                        None => break,
+                    };
+                    let breakpoint = view
+                        .breakpoint_lines
+                        .iter()
+                        .any(|bp| bp.file == line.file && bp.number == number);
+                    rows.push(format!(
+                        "{}{} {:>4} {}",
+                        if breakpoint { "B" } else { " " },
+                        if number == line.number { ">" } else { " " },
+                        number,
+                        text
+                    ));
+                }
+            }
+            None => rows.push(format!("Cannot read {}", line.file)),
+        }
+        rows.resize(height, String::new());
+        rows
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        // Give the whole screen back to the shell
+        let _ = execute!(
+            io::stdout(),
+            Print("\x1b[r"),
+            MoveTo(0, self.size.1.saturating_sub(1))
+        );
+    }
+}
+
+fn register_rows(view: &View, width: usize) -> Vec<String> {
+    let mut rows = vec![pad("Registers", width)];
+    match view.registers {
+        Some(regs) => {
+            let values = [
+                ("rax", regs.rax),
+                ("rbx", regs.rbx),
+                ("rcx", regs.rcx),
+                ("rdx", regs.rdx),
+                ("rsi", regs.rsi),
+                ("rdi", regs.rdi),
+                ("rbp", regs.rbp),
+                ("rsp", regs.rsp),
+                ("r8", regs.r8),
+                ("r9", regs.r9),
+                ("r10", regs.r10),
+                ("r11", regs.r11),
+                ("r12", regs.r12),
+                ("r13", regs.r13),
+                ("r14", regs.r14),
+                ("r15", regs.r15),
+                ("rip", regs.rip),
+                ("eflags", regs.eflags),
+            ];
+            for pair in values.chunks(2) {
+                let text: Vec<String> = pair
+                    .iter()
+                    .map(|(name, value)| format!("{:<7}{:#018x}", name, value))
+                    .collect();
+                rows.push(pad(&text.join("  "), width));
+            }
+        }
+        None => rows.push(pad("[No process]", width)),
+    }
+    rows.resize(REGISTER_PANE_HEIGHT as usize, pad("", width));
+    rows
+}
+
+fn backtrace_rows(view: &View, width: usize) -> Vec<String> {
+    let mut rows = vec![pad("Backtrace", width)];
+    for (index, frame) in view.backtrace.iter().enumerate() {
+        rows.push(format!("#{} {}", index, frame));
+    }
+    rows.resize(REGISTER_PANE_HEIGHT as usize, String::new());
+    rows
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// Truncates or pads text with spaces to exactly width characters.
+fn pad(text: &str, width: usize) -> String {
+    format!("{:<width$}", truncate(text, width), width = width)
+}
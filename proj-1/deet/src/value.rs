@@ -0,0 +1,450 @@
+//! Formats variable values read from the inferior according to their DWARF type, with
+//! pretty-printers for Rust's String, Vec<T>, &str and enums such as Option<T>.
+
+use crate::dwarf_data::{DwarfData, Member, Type, TypeKind, Variant};
+use crate::inferior::Inferior;
+use std::convert::TryInto;
+
+/// Arrays, vectors and strings are cut off after this many elements, as in gdb.
+const MAX_ELEMENTS: usize = 200;
+/// Values nested deeper than this are elided, in case of a cycle through references.
+const MAX_DEPTH: usize = 8;
+
+/// Formats values the way gdb's print would. Members and elements have their types looked up in
+/// the DwarfData, and the contents of strings and vectors are read from the inferior.
+pub struct Formatter<'a> {
+    dwarf_data: &'a DwarfData,
+    inferior: &'a Inferior,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(dwarf_data: &'a DwarfData, inferior: &'a Inferior) -> Formatter<'a> {
+        Formatter {
+            dwarf_data,
+            inferior,
+        }
+    }
+
+    /// Formats bytes (little-endian, entity_type.size long).
+    pub fn format(&self, entity_type: &Type, bytes: &[u8]) -> String {
+        self.format_nested(entity_type, bytes, 0)
+    }
+
+    fn format_nested(&self, entity_type: &Type, bytes: &[u8], depth: usize) -> String {
+        if bytes.len() < entity_type.size {
+            return "<unreadable>".to_string();
+        }
+        if depth > MAX_DEPTH {
+            return "...".to_string();
+        }
+        match &entity_type.kind {
+            TypeKind::Base => format_base(entity_type, bytes),
+            TypeKind::Alias(target) => match self.dwarf_data.get_type(*target) {
+                Some(target) => self.format_nested(target, bytes, depth),
+                None => format_base(entity_type, bytes),
+            },
+            TypeKind::Pointer(pointee) => self.format_pointer(entity_type, *pointee, bytes, depth),
+            TypeKind::Struct { members, rust, .. } => {
+                if *rust {
+                    if let Some(value) = self.format_rust_builtin(entity_type, bytes, depth) {
+                        return value;
+                    }
+                }
+                self.format_struct(&entity_type.name, members, *rust, bytes, depth)
+            }
+            TypeKind::Enum {
+                discriminant,
+                variants,
+            } => self.format_enum(discriminant.as_ref(), variants, bytes, depth),
+            TypeKind::Array { element, count } => {
+                self.format_array(entity_type, *element, *count, bytes, depth)
+            }
+        }
+    }
+
+    fn format_pointer(
+        &self,
+        pointer_type: &Type,
+        pointee: Option<usize>,
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        let addr = read_uint(&bytes[..pointer_type.size]) as usize;
+        let pointee = match pointee.and_then(|offset| self.resolve(offset)) {
+            Some(pointee) if addr != 0 => pointee,
+            _ => return format!("{:#x}", addr),
+        };
+        if is_char(pointee) {
+            return format!("{:#x} {}", addr, self.read_c_string(addr));
+        }
+        // Rust references show what they refer to
+        if pointer_type.name.starts_with('&') {
+            if let Ok(target) = self.inferior.read_memory(addr, pointee.size) {
+                return format!("&{}", self.format_nested(pointee, &target, depth + 1));
+            }
+        }
+        format!("{:#x}", addr)
+    }
+
+    /// Formats String, Vec<T> and &str by their contents rather than their fields.
+    fn format_rust_builtin(
+        &self,
+        entity_type: &Type,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Option<String> {
+        let name = entity_type.name.as_str();
+        if name == "&str" || name == "&mut str" {
+            let data_ptr = self.member_value(entity_type, bytes, "data_ptr")?;
+            let length = self.member_value(entity_type, bytes, "length")?;
+            return Some(self.format_string(data_ptr as usize, length as usize));
+        }
+        if name == "String" || name == "alloc::string::String" {
+            let (vec_type, vec_bytes) = self.member(entity_type, bytes, "vec")?;
+            let (addr, len, _) = self.vec_contents(vec_type, vec_bytes)?;
+            return Some(self.format_string(addr, len));
+        }
+        if name.starts_with("Vec<") || name.starts_with("alloc::vec::Vec<") {
+            let (addr, len, element) = self.vec_contents(entity_type, bytes)?;
+            let shown = std::cmp::min(len, MAX_ELEMENTS);
+            let data = if element.size == 0 {
+                Vec::new()
+            } else {
+                self.inferior.read_memory(addr, shown * element.size).ok()?
+            };
+            let mut values: Vec<String> = (0..shown)
+                .map(|index| {
+                    let start = index * element.size;
+                    let element_bytes = data.get(start..start + element.size).unwrap_or(&[]);
+                    self.format_nested(element, element_bytes, depth + 1)
+                })
+                .collect();
+            if len > shown {
+                values.push("...".to_string());
+            }
+            return Some(format!("vec![{}]", values.join(", ")));
+        }
+        None
+    }
+
+    /// Returns the address of a Vec's buffer, its length and its element type.
+    fn vec_contents(&self, vec_type: &Type, bytes: &[u8]) -> Option<(usize, usize, &Type)> {
+        let template_params = match &vec_type.kind {
+            TypeKind::Struct {
+                template_params, ..
+            } => template_params,
+            _ => return None,
+        };
+        let element = self.resolve(*template_params.first()?)?;
+        let len = self.member_value(vec_type, bytes, "len")?;
+        // The pointer is nested a few structs deep in the RawVec, differently in different
+        // versions of the standard library
+        let (buf_type, buf_bytes) = self.member(vec_type, bytes, "buf")?;
+        let ptr = self.find_pointer(buf_type, 0)?;
+        let addr = read_uint(buf_bytes.get(ptr..ptr + 8)?);
+        Some((addr as usize, len as usize, element))
+    }
+
+    /// Returns the offset of the first pointer within a type.
+    fn find_pointer(&self, entity_type: &Type, depth: usize) -> Option<usize> {
+        match &entity_type.kind {
+            TypeKind::Pointer(_) => Some(0),
+            TypeKind::Alias(target) => self.find_pointer(self.dwarf_data.get_type(*target)?, depth),
+            TypeKind::Struct { members, .. } if depth < MAX_DEPTH => {
+                members.iter().find_map(|member| {
+                    let member_type = self.dwarf_data.get_type(member.entity_type)?;
+                    Some(member.offset + self.find_pointer(member_type, depth + 1)?)
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn format_struct(
+        &self,
+        name: &str,
+        members: &[Member],
+        rust: bool,
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        let fields: Vec<(&str, String)> = members
+            .iter()
+            .map(|member| {
+                let value = match self.dwarf_data.get_type(member.entity_type) {
+                    Some(member_type) => self.format_nested(
+                        member_type,
+                        member_bytes(bytes, member, member_type).unwrap_or(&[]),
+                        depth + 1,
+                    ),
+                    None => "<unknown type>".to_string(),
+                };
+                (member.name.as_str(), value)
+            })
+            .collect();
+        if !rust {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect();
+            return format!("{{{}}}", fields.join(", "));
+        }
+        if fields.is_empty() {
+            return name.to_string();
+        }
+        // Tuples and tuple structs have fields named __0, __1, ...
+        if fields.iter().all(|(name, _)| name.starts_with("__")) {
+            let values: Vec<String> = fields.into_iter().map(|(_, value)| value).collect();
+            let name = if name.starts_with('(') { "" } else { name };
+            return format!("{}({})", name, values.join(", "));
+        }
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        format!("{} {{ {} }}", name, fields.join(", "))
+    }
+
+    fn format_enum(
+        &self,
+        discriminant: Option<&Member>,
+        variants: &[Variant],
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        // Discriminants are compared at the discriminant's own size, since they may have been
+        // written sign-extended
+        let value = discriminant.and_then(|member| {
+            let member_type = self.dwarf_data.get_type(member.entity_type)?;
+            let mask = match member_type.size {
+                0 => return None,
+                size if size >= 8 => u64::max_value(),
+                size => (1 << (8 * size)) - 1,
+            };
+            let value = read_uint(member_bytes(bytes, member, member_type)?);
+            Some((value, mask))
+        });
+        let variant = variants
+            .iter()
+            .find(|variant| match (variant.discriminant, value) {
+                (Some(expected), Some((value, mask))) => expected & mask == value & mask,
+                _ => false,
+            })
+            .or_else(|| {
+                variants
+                    .iter()
+                    .find(|variant| variant.discriminant.is_none())
+            });
+        let variant = match variant {
+            Some(variant) => variant,
+            None => return "<unknown variant>".to_string(),
+        };
+        match self.dwarf_data.get_type(variant.member.entity_type) {
+            Some(variant_type) => self.format_nested(
+                variant_type,
+                member_bytes(bytes, &variant.member, variant_type).unwrap_or(&[]),
+                depth + 1,
+            ),
+            None => variant.member.name.clone(),
+        }
+    }
+
+    fn format_array(
+        &self,
+        array_type: &Type,
+        element: usize,
+        count: usize,
+        bytes: &[u8],
+        depth: usize,
+    ) -> String {
+        let element = match self.resolve(element) {
+            Some(element) if element.size > 0 => element,
+            _ => return format_base(array_type, bytes),
+        };
+        if is_char(element) {
+            let text = &bytes[..std::cmp::min(count, bytes.len())];
+            let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+            return format!("{:?}", String::from_utf8_lossy(&text[..end]));
+        }
+        let shown = std::cmp::min(count, MAX_ELEMENTS);
+        let mut values: Vec<String> = bytes
+            .chunks(element.size)
+            .take(shown)
+            .map(|element_bytes| self.format_nested(element, element_bytes, depth + 1))
+            .collect();
+        if count > shown {
+            values.push("...".to_string());
+        }
+        // Rust names its arrays [T; N]
+        if array_type.name.starts_with('[') {
+            format!("[{}]", values.join(", "))
+        } else {
+            format!("{{{}}}", values.join(", "))
+        }
+    }
+
+    /// Reads a string of len bytes at addr, quoting and escaping it.
+    fn format_string(&self, addr: usize, len: usize) -> String {
+        let shown = std::cmp::min(len, MAX_ELEMENTS);
+        let text = match self.inferior.read_memory(addr, shown) {
+            Ok(text) if text.len() == shown => text,
+            _ => return format!("<error reading string at {:#x}>", addr),
+        };
+        let quoted = format!("{:?}", String::from_utf8_lossy(&text));
+        if len > shown {
+            format!("{}...", quoted)
+        } else {
+            quoted
+        }
+    }
+
+    /// Reads a NUL-terminated string at addr, quoting and escaping it.
+    fn read_c_string(&self, addr: usize) -> String {
+        let text = match self.inferior.read_memory(addr, MAX_ELEMENTS) {
+            Ok(text) => text,
+            Err(_) => return format!("<error reading string at {:#x}>", addr),
+        };
+        match text.iter().position(|&b| b == 0) {
+            Some(end) => format!("{:?}", String::from_utf8_lossy(&text[..end])),
+            None => format!("{:?}...", String::from_utf8_lossy(&text)),
+        }
+    }
+
+    /// Returns the type and bytes of the named member of a struct.
+    fn member<'b>(
+        &self,
+        struct_type: &Type,
+        bytes: &'b [u8],
+        name: &str,
+    ) -> Option<(&'a Type, &'b [u8])> {
+        let members = match &struct_type.kind {
+            TypeKind::Struct { members, .. } => members,
+            _ => return None,
+        };
+        let member = members.iter().find(|member| member.name == name)?;
+        let member_type = self.dwarf_data.get_type(member.entity_type)?;
+        Some((member_type, member_bytes(bytes, member, member_type)?))
+    }
+
+    /// Returns the value of the named integer or pointer member of a struct.
+    fn member_value(&self, struct_type: &Type, bytes: &[u8], name: &str) -> Option<u64> {
+        let (_, bytes) = self.member(struct_type, bytes, name)?;
+        Some(read_uint(bytes))
+    }
+
+    /// Looks up a type, seeing through typedefs and const.
+    fn resolve(&self, offset: usize) -> Option<&'a Type> {
+        Some(resolve_alias(
+            self.dwarf_data,
+            self.dwarf_data.get_type(offset)?,
+        ))
+    }
+}
+
+/// Follows typedefs and const/volatile qualifiers to the type underneath.
+pub fn resolve_alias<'t>(dwarf_data: &'t DwarfData, entity_type: &'t Type) -> &'t Type {
+    let mut entity_type = entity_type;
+    for _ in 0..MAX_DEPTH {
+        match entity_type.kind {
+            TypeKind::Alias(target) => match dwarf_data.get_type(target) {
+                Some(target) => entity_type = target,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    entity_type
+}
+
+/// Whether a base type is a floating point type, which is passed and returned in xmm registers.
+pub fn is_float(entity_type: &Type) -> bool {
+    ["float", "double", "f32", "f64"].contains(&entity_type.name.as_str())
+}
+
+/// Reads a base type or pointer no bigger than a register as the integer it is passed in one,
+/// sign-extending signed types.
+pub fn register_value(entity_type: &Type, bytes: &[u8]) -> u64 {
+    let value = read_uint(bytes);
+    let bits = 8 * std::cmp::min(bytes.len(), 8) as u32;
+    if bits == 0 || bits == 64 || !is_signed(entity_type) {
+        return value;
+    }
+    let shift = 64 - bits;
+    ((value << shift) as i64 >> shift) as u64
+}
+
+fn is_signed(entity_type: &Type) -> bool {
+    let name = entity_type.name.as_str();
+    match entity_type.kind {
+        TypeKind::Base => !is_unsigned(entity_type) && name != "_Bool" && name != "bool",
+        _ => false,
+    }
+}
+
+fn is_unsigned(entity_type: &Type) -> bool {
+    let name = entity_type.name.as_str();
+    name.contains("unsigned") || name.starts_with('u')
+}
+
+fn member_bytes<'b>(bytes: &'b [u8], member: &Member, member_type: &Type) -> Option<&'b [u8]> {
+    bytes.get(member.offset..member.offset + member_type.size)
+}
+
+/// Reads a little-endian unsigned integer of up to 8 bytes.
+fn read_uint(bytes: &[u8]) -> u64 {
+    let mut padded = [0u8; 8];
+    let len = std::cmp::min(bytes.len(), 8);
+    padded[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(padded)
+}
+
+fn is_char(entity_type: &Type) -> bool {
+    entity_type.size == 1 && entity_type.name.contains("char")
+}
+
+/// Formats a base type by its name and size. Types that aren't recognized are shown as raw bytes.
+fn format_base(entity_type: &Type, bytes: &[u8]) -> String {
+    let name = entity_type.name.as_str();
+    let unsigned = is_unsigned(entity_type);
+    match entity_type.size {
+        1 if name == "_Bool" || name == "bool" => (bytes[0] != 0).to_string(),
+        1 if name.contains("char") => {
+            let byte = bytes[0];
+            let value = if unsigned {
+                byte.to_string()
+            } else {
+                (byte as i8).to_string()
+            };
+            format!("{} {:?}", value, byte as char)
+        }
+        1 if unsigned => bytes[0].to_string(),
+        1 => (bytes[0] as i8).to_string(),
+        2 if unsigned => u16::from_le_bytes(bytes[..2].try_into().unwrap()).to_string(),
+        2 => i16::from_le_bytes(bytes[..2].try_into().unwrap()).to_string(),
+        // Rust's char is a 4-byte Unicode scalar value
+        4 if name == "char" => {
+            let value = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+            match std::char::from_u32(value) {
+                Some(c) => format!("{:?}", c),
+                None => value.to_string(),
+            }
+        }
+        4 if name == "float" || name == "f32" => {
+            f32::from_le_bytes(bytes[..4].try_into().unwrap()).to_string()
+        }
+        4 if unsigned => u32::from_le_bytes(bytes[..4].try_into().unwrap()).to_string(),
+        4 => i32::from_le_bytes(bytes[..4].try_into().unwrap()).to_string(),
+        8 if name == "double" || name == "f64" => {
+            f64::from_le_bytes(bytes[..8].try_into().unwrap()).to_string()
+        }
+        8 if unsigned => u64::from_le_bytes(bytes[..8].try_into().unwrap()).to_string(),
+        8 => i64::from_le_bytes(bytes[..8].try_into().unwrap()).to_string(),
+        _ => {
+            let hex: Vec<String> = bytes[..entity_type.size]
+                .iter()
+                .map(|b| format!("{:#04x}", b))
+                .collect();
+            format!("{{{}}}", hex.join(", "))
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Runs deet on the programs in samples/, built by the Makefile, and checks what it reports:
+//! where the program stops, backtraces, variable values and exit statuses.
+//!
+//! Each test pipes a scripted session into deet. With no terminal, deet prints no prompt, so its
+//! output is just what the commands print (along with whatever the program itself prints).
+
+use std::env;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Once;
+
+static BUILD_SAMPLES: Once = Once::new();
+
+fn sample(name: &str) -> PathBuf {
+    BUILD_SAMPLES.call_once(|| {
+        let status = Command::new("make")
+            .arg("-s")
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .status()
+            .expect("couldn't run make");
+        assert!(status.success(), "building the samples failed");
+    });
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("samples")
+        .join(name)
+}
+
+/// Debugs the named sample, typing commands one per line, and returns everything printed. deet
+/// runs in the temporary directory, with that as its home as well, so that neither a .deetinit
+/// nor the user's history gets involved.
+fn debug(name: &str, commands: &[&str]) -> String {
+    let program = sample(name);
+    let mut deet = Command::new(env!("CARGO_BIN_EXE_deet"))
+        .arg(&program)
+        .current_dir(env::temp_dir())
+        .env("HOME", env::temp_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("couldn't start deet");
+    {
+        let stdin = deet.stdin.as_mut().unwrap();
+        for command in commands {
+            writeln!(stdin, "{}", command).unwrap();
+        }
+    }
+    let output = deet.wait_with_output().unwrap();
+    assert!(output.status.success(), "deet failed on {}", name);
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// The locations the program stopped at, in order, as file name (without its directory) and
+/// line number.
+fn stops(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.starts_with("Stopped at "))
+        .map(|line| line.rsplit('/').next().unwrap().to_string())
+        .collect()
+}
+
+/// The backtraces printed, as the function names of their frames.
+fn backtraces(output: &str) -> Vec<Vec<String>> {
+    let mut backtraces: Vec<Vec<String>> = Vec::new();
+    let mut in_backtrace = false;
+    for line in output.lines() {
+        // #0 func (file:line)
+        if !line.starts_with('#') {
+            in_backtrace = false;
+            continue;
+        }
+        let function = line.split_whitespace().nth(1).unwrap_or("").to_string();
+        if !in_backtrace {
+            backtraces.push(Vec::new());
+            in_backtrace = true;
+        }
+        backtraces.last_mut().unwrap().push(function);
+    }
+    backtraces
+}
+
+fn exit_statuses(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .filter(|line| line.starts_with("Child exited"))
+        .collect()
+}
+
+#[test]
+fn runs_to_completion() {
+    let output = debug("hello", &["run"]);
+    assert!(output.contains("Hello world!"));
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+
+    let output = debug("exit", &["run"]);
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+
+    // Without an argument, sleepy_print prints its usage and fails
+    let output = debug("sleepy_print", &["run"]);
+    assert!(output.contains("Usage: "));
+    assert_eq!(exit_statuses(&output), ["Child exited (status 1)"]);
+}
+
+#[test]
+fn stops_at_a_function_breakpoint() {
+    let output = debug(
+        "function_calls",
+        &["break func3", "run", "bt", "print a", "print global"],
+    );
+    assert_eq!(stops(&output), ["function_calls.c:6"]);
+    assert_eq!(backtraces(&output), [["func3", "func2", "func1", "main"]]);
+    assert!(output.contains("a = 100\n"));
+    assert!(output.contains("global = 5\n"));
+}
+
+#[test]
+fn stops_at_a_line_breakpoint() {
+    let output = debug("count", &["break 5", "run", "continue"]);
+    assert_eq!(stops(&output), ["count.c:5"]);
+    assert!(output.contains("1\n2\n3\n4\n5\n"));
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+}
+
+#[test]
+fn shows_callers_at_the_line_of_the_call() {
+    // The call to func2 is the last instruction of line 18, so it returns to line 19
+    let output = debug("function_calls", &["break func2", "run", "frame 1", "bt"]);
+    assert_eq!(
+        output
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .map(|line| line.rsplit('/').next().unwrap())
+            .collect::<Vec<_>>(),
+        [
+            "function_calls.c:18)",
+            "function_calls.c:10)",
+            "function_calls.c:18)",
+            "function_calls.c:24)"
+        ]
+    );
+}
+
+#[test]
+fn continues_off_a_breakpoint() {
+    // func3 is called twice. Continuing must step over the breakpoint at the first call without
+    // corrupting the instruction underneath it, and still stop at the second.
+    let output = debug(
+        "function_calls",
+        &["break func3", "run", "continue", "bt", "continue"],
+    );
+    assert_eq!(stops(&output), ["function_calls.c:6", "function_calls.c:6"]);
+    assert_eq!(backtraces(&output), [["func3", "func1", "main"]]);
+    assert_eq!(output.matches("Hello from func3! 100\n").count(), 2);
+    assert!(output.contains("end of func1\n"));
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+    assert!(!output.contains("found current location"));
+}
+
+#[test]
+fn backtraces_without_debugging_information() {
+    // A breakpoint on a function without DWARF is at its very first instruction, before it has
+    // pushed rbp, and after one stepi it has pushed it but not yet set it
+    let output = debug(
+        "function_calls_nodebug",
+        &["break func3", "run", "bt", "stepi", "bt", "stepi", "bt"],
+    );
+    let backtraces: Vec<Vec<String>> = backtraces(&output)
+        .into_iter()
+        .map(|frames| {
+            frames
+                .iter()
+                .map(|frame| frame.split('+').next().unwrap().to_string())
+                .collect()
+        })
+        .collect();
+    assert_eq!(backtraces, [["func3", "func2", "func1", "main"]; 3]);
+}
+
+#[test]
+fn backtraces_from_a_shared_library() {
+    // Stopped in libc's write, which has to be looked up in libc rather than the executable
+    let output = debug("hello", &["catch syscall write", "run", "bt"]);
+    let backtraces = backtraces(&output);
+    assert_eq!(backtraces.len(), 1);
+    let function = &backtraces[0][0];
+    assert!(function.contains("write+0x"), "{}", function);
+}
+
+#[test]
+fn runs_until_the_next_line() {
+    // until without a location steps over the calls to func2 and func3
+    let output = debug(
+        "function_calls",
+        &[
+            "break func1",
+            "run",
+            "until",
+            "until",
+            "advance 20",
+            "until",
+        ],
+    );
+    assert_eq!(
+        stops(&output),
+        [
+            "function_calls.c:17",
+            "function_calls.c:18",
+            "function_calls.c:19",
+            "function_calls.c:20",
+            "function_calls.c:21"
+        ]
+    );
+    assert!(!output.contains("addr from line"));
+}
+
+#[test]
+fn stops_at_a_segfault() {
+    let output = debug("segfault", &["run", "bt", "print a"]);
+    assert!(output.contains("Child stopped (signal SIGSEGV)"));
+    assert_eq!(stops(&output), ["segfault.c:5"]);
+    assert_eq!(backtraces(&output), [["func2", "func1", "main"]]);
+    assert!(output.contains("a = 2\n"));
+    assert!(!output.contains("Did segfault!"));
+}
+
+#[test]
+fn restarts_a_running_program() {
+    // run while the program is stopped at a breakpoint kills it and starts over, with the new
+    // arguments and the breakpoint still in place
+    let output = debug(
+        "sleepy_print",
+        &[
+            "break 12",
+            "run 3",
+            "print i",
+            "continue",
+            "print i",
+            "run 2",
+            "print i",
+            "print num_seconds",
+            "continue",
+            "continue",
+        ],
+    );
+    assert_eq!(stops(&output), ["sleepy_print.c:12"; 4]);
+    let values: Vec<&str> = output.lines().filter(|line| line.contains(" = ")).collect();
+    assert_eq!(values, ["i = 0", "i = 1", "i = 0", "num_seconds = 2"]);
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+}
+
+#[test]
+fn disassembles_with_raw_bytes() {
+    let output = debug(
+        "function_calls",
+        &["break func3", "run", "disassemble /r func3"],
+    );
+    // "   0x401136 <func3+0>:\t55\tpush   %rbp"
+    let instructions: Vec<(usize, usize)> = output
+        .lines()
+        .filter(|line| line.contains(" <func3+"))
+        .map(|line| {
+            let address = line[2..].trim_start().split(' ').next().unwrap();
+            let address = usize::from_str_radix(&address[2..], 16).unwrap();
+            let bytes = line.split('\t').nth(1).unwrap();
+            assert!(bytes
+                .split(' ')
+                .all(|byte| byte.len() == 2 && u8::from_str_radix(byte, 16).is_ok()));
+            (address, bytes.split(' ').count())
+        })
+        .collect();
+    assert!(instructions.len() > 1);
+    // Each instruction's bytes run up to the next instruction
+    for pair in instructions.windows(2) {
+        assert_eq!(pair[0].0 + pair[0].1, pair[1].0);
+    }
+}
+
+#[test]
+fn sources_breakpoint_commands_with_blank_lines() {
+    let script = env::temp_dir().join("deet_commands_with_blank_lines");
+    std::fs::write(
+        &script,
+        "break func3\ncommands\n\n# print the argument\nprint a\n\nend\nrun\n",
+    )
+    .unwrap();
+    let output = debug(
+        "function_calls",
+        &[
+            &format!("source {}", script.display()),
+            "continue",
+            "continue",
+        ],
+    );
+    std::fs::remove_file(&script).unwrap();
+    assert_eq!(stops(&output), ["function_calls.c:6", "function_calls.c:6"]);
+    assert_eq!(output.matches("a = 100\n").count(), 2);
+    assert_eq!(exit_statuses(&output), ["Child exited (status 0)"]);
+}